@@ -1,4 +1,10 @@
 rm -f keyboard.desc
 rm -f mouse.desc
+rm -f consumer.desc
+rm -f boot.desc
+rm -f tablet.desc
 hidrd-convert -i xml -o natv keyboard.xml > keyboard.desc
-hidrd-convert -i xml -o natv mouse.xml > mouse.desc
\ No newline at end of file
+hidrd-convert -i xml -o natv mouse.xml > mouse.desc
+hidrd-convert -i xml -o natv consumer.xml > consumer.desc
+hidrd-convert -i xml -o natv boot.xml > boot.desc
+hidrd-convert -i xml -o natv tablet.xml > tablet.desc
\ No newline at end of file
@@ -31,6 +31,9 @@ pub enum Commands {
 
 const KEYBOARD_DESC: &'static [u8] = include_bytes!("../keyboard.desc");
 const MOUSE_DESC: &'static [u8] = include_bytes!("../mouse.desc");
+const CONSUMER_DESC: &'static [u8] = include_bytes!("../consumer.desc");
+const BOOT_DESC: &'static [u8] = include_bytes!("../boot.desc");
+const TABLET_DESC: &'static [u8] = include_bytes!("../tablet.desc");
 const GADGET_SCHEMA: &'static str = include_str!("../gadget-schema.json");
 const SERVICE: &'static str = include_str!("../gadget.service");
 
@@ -39,6 +42,9 @@ const SERVICE_LOC: &'static str = "/etc/systemd/system/gadget.service";
 const DATA_LOC: &'static str = "/usr/gadget/";
 const KEYBOARD_FILE: &'static str = "keyboard.desc";
 const MOUSE_FILE: &'static str = "mouse.desc";
+const CONSUMER_FILE: &'static str = "consumer.desc";
+const BOOT_FILE: &'static str = "boot.desc";
+const TABLET_FILE: &'static str = "tablet.desc";
 
 const GADGET_SERVICE_INSTALL: &'static str = "systemctl daemon-reload && systemctl enable gadget.service";
 const GADGET_SERVICE_UNINSTALL: &'static str = "systemctl stop gadget.service && systemctl disable gadget.service && systemctl daemon-reload";
@@ -81,6 +87,9 @@ fn install() -> io::Result<()> {
     fs::create_dir_all(DATA_LOC)?;
     fs::write(DATA_LOC.to_string() + KEYBOARD_FILE, KEYBOARD_DESC)?;
     fs::write(DATA_LOC.to_string() + MOUSE_FILE, MOUSE_DESC)?;
+    fs::write(DATA_LOC.to_string() + CONSUMER_FILE, CONSUMER_DESC)?;
+    fs::write(DATA_LOC.to_string() + BOOT_FILE, BOOT_DESC)?;
+    fs::write(DATA_LOC.to_string() + TABLET_FILE, TABLET_DESC)?;
     fs::write(SERVICE_LOC, SERVICE)?;
 
     run_command(GADGET_SERVICE_INSTALL)
@@ -101,7 +110,10 @@ fn disable() -> io::Result<()> {
 
 fn configure() {
     if !Path::new(&(DATA_LOC.to_string() + KEYBOARD_FILE)).exists() ||
-        !Path::new(&(DATA_LOC.to_string() + MOUSE_FILE)).exists()
+        !Path::new(&(DATA_LOC.to_string() + MOUSE_FILE)).exists() ||
+        !Path::new(&(DATA_LOC.to_string() + CONSUMER_FILE)).exists() ||
+        !Path::new(&(DATA_LOC.to_string() + BOOT_FILE)).exists() ||
+        !Path::new(&(DATA_LOC.to_string() + TABLET_FILE)).exists()
     {
         println!("The gadget service must be installed first");
         exit(1)
@@ -124,6 +136,9 @@ fn configure() {
 fn clean() -> io::Result<()> {
     ignore_not_found(fs::remove_file(DATA_LOC.to_string() + KEYBOARD_FILE))?;
     ignore_not_found(fs::remove_file(DATA_LOC.to_string() + MOUSE_FILE))?;
+    ignore_not_found(fs::remove_file(DATA_LOC.to_string() + CONSUMER_FILE))?;
+    ignore_not_found(fs::remove_file(DATA_LOC.to_string() + BOOT_FILE))?;
+    ignore_not_found(fs::remove_file(DATA_LOC.to_string() + TABLET_FILE))?;
     ignore_not_found(fs::remove_file(SERVICE_LOC))
 }
 
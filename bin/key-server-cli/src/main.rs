@@ -1,9 +1,11 @@
 #![doc = include_str!("../README.md")]
 
-use std::{fmt::Display, process::exit};
+use std::{fmt::Display, process::{exit, self}, env, fs};
 
 use clap::{Parser, Subcommand};
 use key_rpc::Client;
+use serde::Serialize;
+use serde_json::Value;
 
 pub trait OrExit<T> {
     fn or_exit(self, msg: &str) -> T;
@@ -43,6 +45,13 @@ struct Args {
     #[arg(short, long)]
     ipc: Option<String>,
 
+    #[arg(short, long)]
+    events: Option<String>,
+
+    /// Print output as json
+    #[arg(short, long)]
+    json: bool,
+
     #[command(subcommand)]
     command: Command,
 }
@@ -50,10 +59,19 @@ struct Args {
 #[derive(Subcommand)]
 enum Command {
     Layer,
+    GetLayer { idx: usize },
     LayerIdx,
     NumLayers,
     AddLayer { json: String },
+    InsertLayer { idx: usize, json: String },
+    SetLayer { idx: usize, json: String },
+    MoveLayer { from: usize, to: usize },
     RemoveLayer { idx: usize },
+    SetBinding { layer: usize, x: usize, y: usize, json: String },
+    ClearBinding { layer: usize, x: usize, y: usize },
+    Addresses,
+    AddAddress { json: String },
+    RemoveAddress { x: usize, y: usize },
     SwitchLayer { idx: usize },
     UpLayer,
     DownLayer,
@@ -61,30 +79,177 @@ enum Command {
     Variables,
     SetVariable { name: String, data: String },
     GetVariable{ name:String },
+    SaveVariables,
+    /// Open a layer in $EDITOR and upload it when the editor exits
+    Edit { idx: usize },
+    Watch,
+}
+
+/// Print a value, as json if requested
+fn print<T: Serialize + Display>(value: T, json: bool) {
+    if json {
+        println!("{}", serde_json::to_string(&value).or_exit("Unable to serialize output"));
+    } else {
+        println!("{}", value);
+    }
+}
+
+/// Print a json string, pretty printed
+fn print_json(value: &str) {
+    let value: Value = serde_json::from_str(value).or_exit("Malformed response");
+    println!("{}", serde_json::to_string_pretty(&value).or_exit("Unable to serialize output"));
+}
+
+/// Render a function type as a short table cell
+fn cell(function: &Value) -> String {
+    match function {
+        Value::Null => "".to_string(),
+        Value::String(name) if name == "None" => "".to_string(),
+        Value::String(name) => name.clone(),
+        Value::Object(map) => map.iter()
+            .map(|(name, data)| match data {
+                Value::String(data) => format!("{}({})", name, data),
+                Value::Number(data) => format!("{}({})", name, data),
+                data => format!("{}{}", name, data),
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+        function => function.to_string(),
+    }
+}
+
+/// Print a layer, as a table unless json is requested
+fn print_layer(layer: &str, json: bool) {
+    if json {
+        println!("{}", layer);
+        return;
+    }
+
+    let rows: Vec<Vec<Value>> = serde_json::from_str(layer).or_exit("Malformed layer");
+    let cells: Vec<Vec<String>> = rows.iter()
+        .map(|row| row.iter().map(cell).collect())
+        .collect();
+
+    let columns = cells.iter().map(|row| row.len()).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|x| cells.iter().filter_map(|row| row.get(x)).map(|cell| cell.chars().count()).max().unwrap_or(0))
+        .collect();
+
+    let border = widths.iter()
+        .map(|width| "-".repeat(width + 2))
+        .collect::<Vec<_>>()
+        .join("+");
+    println!("+{}+", border);
+    for row in cells {
+        let row = row.iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!(" {:width$} ", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("|");
+        println!("|{}|", row);
+        println!("+{}+", border);
+    }
+}
+
+/// Open a layer in the users editor and upload it if it changed
+fn edit(client: &mut Client, idx: usize) {
+    let layer: Vec<Vec<Value>> = serde_json::from_str(&client.get_layer(idx).or_exit("Unable to get layer"))
+        .or_exit("Malformed layer");
+    let layer: Vec<Vec<Value>> = layer.into_iter()
+        .map(|row| row.into_iter().map(|function| if function.is_null() { Value::String("None".to_string()) } else { function }).collect())
+        .collect();
+    let layer = serde_json::to_string_pretty(&layer).or_exit("Unable to serialize layer");
+
+    // Unique file so concurrent edits of the same layer don't overwrite each other
+    let file = tempfile::Builder::new()
+        .prefix(&format!("key-server-layer-{}-", idx))
+        .suffix(".json")
+        .tempfile()
+        .or_exit("Unable to create layer file");
+    fs::write(file.path(), &layer).or_exit("Unable to write layer");
+
+    let editor = env::var("EDITOR").unwrap_or("vi".to_string());
+    let status = process::Command::new(&editor).arg(file.path()).status().or_exit("Unable to open editor");
+    if !status.success() {
+        println!("Editor exited with {}, layer not uploaded", status);
+        file.close().ok();
+        exit(1);
+    }
+
+    let edited = fs::read_to_string(file.path()).or_exit("Unable to read layer");
+    file.close().ok();
+
+    if edited == layer {
+        return;
+    }
+
+    let edited: Value = serde_json::from_str(&edited).or_exit("Invalid layer json");
+    client.set_layer(idx, edited.to_string()).or_exit("Unable to set layer");
 }
 
 fn main() {
     let args = Args::parse();
     let ipc = args.ipc.unwrap_or("ipc:///lmk/ksf.ipc".to_string());
-    let mut client = Client::new(&ipc).unwrap();
+    let json = args.json;
+
+    if let Command::Watch = args.command {
+        let events = args.events.unwrap_or("ipc:///lmk/kse.ipc".to_string());
+        for event in Client::subscribe(&events).or_exit("Unable to subscribe to events") {
+            let event = event.or_exit("Unable to receive event");
+            if json {
+                println!("{}", serde_json::to_string(&event).or_exit("Unable to serialize event"));
+            } else {
+                println!("{:?}", event);
+            }
+        }
+        return;
+    }
+
+    let mut client = Client::new(&ipc).or_exit("Unable to connect to key-server");
 
     match args.command {
-        Command::Layer => println!("{}", client.layer().or_exit("Unable to get layer")),
-        Command::LayerIdx => println!("{}", client.layer_idx().or_exit("Unable to get layer")),
-        Command::NumLayers => println!("{}", client.num_layers().or_exit("Unable to get layer")),
+        Command::Layer => print_layer(&client.layer().or_exit("Unable to get layer"), json),
+        Command::GetLayer { idx } => print_layer(&client.get_layer(idx).or_exit("Unable to get layer"), json),
+        Command::LayerIdx => print(client.layer_idx().or_exit("Unable to get layer"), json),
+        Command::NumLayers => print(client.num_layers().or_exit("Unable to get layer"), json),
         Command::AddLayer { json } => client.add_layer(json).or_exit("Unable to add layer"),
-        Command::RemoveLayer { idx } => client.remove_layer(idx).or_exit("Unable to add layer"),
+        Command::InsertLayer { idx, json } => client.insert_layer(idx, json).or_exit("Unable to insert layer"),
+        Command::SetLayer { idx, json } => client.set_layer(idx, json).or_exit("Unable to set layer"),
+        Command::MoveLayer { from, to } => client.move_layer(from, to).or_exit("Unable to move layer"),
+        Command::RemoveLayer { idx } => client.remove_layer(idx).or_exit("Unable to remove layer"),
+        Command::SetBinding { layer, x, y, json } => client.set_binding(layer, x, y, json).or_exit("Unable to set binding"),
+        Command::ClearBinding { layer, x, y } => client.clear_binding(layer, x, y).or_exit("Unable to clear binding"),
+        Command::Addresses => {
+            let addresses = client.addresses().or_exit("Unable to get addresses");
+            if json {
+                println!("{}", addresses);
+            } else {
+                print_json(&addresses);
+            }
+        },
+        Command::AddAddress { json } => client.add_address(json).or_exit("Unable to add address"),
+        Command::RemoveAddress { x, y } => client.remove_address(x, y).or_exit("Unable to remove address"),
         Command::SwitchLayer { idx } => client.switch_layer(idx).or_exit("Unable to switch layer"),
         Command::UpLayer => client.up_layer().or_exit("Unable to switch layer"),
         Command::DownLayer => client.down_layer().or_exit("Unable to switch layer"),
         Command::SaveLayout => client.save_layer().or_exit("Unable to save layout"),
-        Command::Variables => println!("{:?}", client.variables().or_exit("Unable to get variables")),
+        Command::Variables => {
+            let variables = client.variables().or_exit("Unable to get variables");
+            if json {
+                println!("{}", serde_json::to_string(&variables).or_exit("Unable to serialize output"));
+            } else {
+                variables.iter().for_each(|variable| println!("{}", variable));
+            }
+        },
         Command::SetVariable{name, data} => client
             .set_variable(name, data)
             .or_exit("Unable to set variable"),
-        Command::GetVariable{name} => println!(
-            "{}",
-            client.get_variable(name).or_exit("Unable to get variable")
+        Command::GetVariable{name} => print(
+            client.get_variable(name).or_exit("Unable to get variable"),
+            json
         ),
+        Command::SaveVariables => client.save_variables().or_exit("Unable to save variables"),
+        Command::Edit { idx } => edit(&mut client, idx),
+        Command::Watch => (),
     }
 }
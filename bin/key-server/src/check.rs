@@ -0,0 +1,500 @@
+use std::{path::Path, sync::Arc, fs, collections::{HashSet, HashMap, VecDeque}};
+
+use serde::de::DeserializeOwned;
+
+use crate::{
+    layout::{LayoutBuilder, Address},
+    driver::{DriverManager, led::{LEDS, Leds, LED_VARIABLE_PREFIX, led_variable}},
+    function::{FunctionType, log::LogLevel, midi::{Channel, GMSoundSet, note_param}},
+    variables::{Data, VarDef},
+    frontend::{FrontendConfiguration, FrontendConfigData},
+    modules::ModuleManager,
+    events::Events,
+    scheduler::MAX_SCAN_RATE,
+};
+
+/// Config checker, collects every problem found in a config directory
+pub struct Checker {
+    problems: Vec<String>,
+    /// Global variable values, none if variables.json could not be loaded
+    variables: Option<HashMap<String, String>>,
+    defined: HashSet<String>,
+    drivers: Option<DriverManager>,
+    module_manager: Arc<ModuleManager>,
+    num_layers: usize,
+    /// Layers each layer can activate
+    targets: Vec<HashSet<usize>>,
+}
+
+impl Checker {
+    /// Check the config files in a config directory, returning a description of each problem found
+    pub async fn check(
+        layout_path: &Path,
+        variables_path: &Path,
+        frontend_path: &Path,
+        drivers_path: &Path,
+        module_manager: Arc<ModuleManager>,
+    ) -> Vec<String> {
+        let mut checker = Checker {
+            problems: vec![],
+            variables: None,
+            // Led variables are created by the key-server
+            defined: LEDS.iter().map(|led| led_variable(led)).collect(),
+            drivers: None,
+            module_manager: module_manager.clone(),
+            num_layers: 0,
+            targets: vec![],
+        };
+
+        let frontend = fs::read_to_string(frontend_path)
+            .map_err(|e| e.to_string())
+            .and_then(|config| FrontendConfiguration::new(&config, module_manager.clone(), Events::new(), Leds::new()).map_err(|e| e.to_string()));
+        let frontend = match frontend {
+            Ok(frontend) => Some(frontend),
+            Err(e) => {
+                checker.problem("frontend.json", format!("Unable to load frontend config, {}", e));
+                None
+            },
+        };
+
+        let variables = fs::read_to_string(variables_path)
+            .map_err(|e| e.to_string())
+            .and_then(|variables| serde_json::from_str::<Vec<VarDef>>(&variables).map_err(|e| e.to_string()));
+        match variables {
+            Ok(definitions) => {
+                for definition in definitions.iter().filter(|definition| definition.name().starts_with(LED_VARIABLE_PREFIX)) {
+                    checker.problem("variables.json", format!("Variable {} uses the led variable prefix {}", definition.name(), LED_VARIABLE_PREFIX));
+                }
+                checker.variables = Some(definitions.iter()
+                    .map(|definition| (definition.name().to_string(), definition.default().to_string()))
+                    .collect()
+                );
+            },
+            Err(e) => checker.problem("variables.json", format!("Unable to load variables config, {}", e)),
+        }
+
+        match DriverManager::load(drivers_path, module_manager, &Leds::new()).await {
+            Ok(drivers) => checker.drivers = Some(drivers),
+            Err(e) => checker.problem("drivers", format!("Unable to load drivers, {}", e)),
+        }
+
+        if let Some(FrontendConfigData::Scheduler { scan_rate, .. }) = frontend.as_ref()
+            .and_then(|frontend| frontend.get(|config| matches!(config, FrontendConfigData::Scheduler { .. }))) {
+            if *scan_rate > MAX_SCAN_RATE {
+                checker.problem("frontend.json", format!("Scan rate {}Hz is above the maximum of {}Hz", scan_rate, MAX_SCAN_RATE));
+            }
+        }
+
+        let mut unknown_drivers = vec![];
+        if let (Some(frontend), Some(drivers)) = (&frontend, &checker.drivers) {
+            if let Some(FrontendConfigData::Scheduler { tick_intervals, .. }) = frontend.get(|config| matches!(config, FrontendConfigData::Scheduler { .. })) {
+                unknown_drivers.extend(tick_intervals.keys().filter(|name| drivers.get(name).is_none()).cloned());
+            }
+        }
+        for name in unknown_drivers {
+            checker.problem("frontend.json", format!("Tick interval set for unknown driver {}", name));
+        }
+
+        let layout = fs::read_to_string(layout_path)
+            .map_err(|e| e.to_string())
+            .and_then(|layout| serde_json::from_str::<LayoutBuilder>(&layout).map_err(|e| e.to_string()));
+        match layout {
+            Ok(layout) => checker.check_layout(&layout).await,
+            Err(e) => checker.problem("layout.json", format!("Unable to load layout config, {}", e)),
+        }
+
+        checker.problems
+    }
+
+    /// Record a problem
+    fn problem(&mut self, location: &str, msg: String) {
+        self.problems.push(format!("{}: {}", location, msg));
+    }
+
+    /// Check the bound addresses and functions of a layout
+    async fn check_layout(&mut self, layout: &LayoutBuilder) {
+        for address in layout.bound() {
+            let (x, y) = match address {
+                Address::DriverMatrix { root, .. } | Address::DriverAddr { root, .. } => *root,
+                Address::DriverRow { root, .. } => (0, *root),
+                Address::DriverColumn { root, .. } => (*root, 0),
+                Address::None => continue,
+            };
+            self.check_address(&format!("layout.json, address bound at ({}, {})", x, y), address);
+        }
+
+        self.num_layers = layout.layers().len();
+        self.targets = vec![HashSet::new(); self.num_layers];
+
+        // Variables defined inside the layout can be referenced from anywhere in it
+        for layer in layout.layers() {
+            for function in layer.iter().flatten() {
+                self.definitions(function);
+            }
+        }
+        for combo in layout.combos().iter().flatten() {
+            self.definitions(combo.function());
+        }
+
+        for (i, layer) in layout.layers().iter().enumerate() {
+            for (j, function) in layer.iter().enumerate() {
+                if let Some(function) = function {
+                    let location = format!("layout.json, layer {}, ({}, {})", i, j % layout.width(), j / layout.width());
+                    self.check_function(&location, i, function).await;
+                }
+            }
+        }
+        for (i, combos) in layout.combos().iter().enumerate() {
+            for (j, combo) in combos.iter().enumerate() {
+                let location = format!("layout.json, layer {}, combo {}", i, j);
+                self.check_function(&location, i, combo.function()).await;
+            }
+        }
+
+        let mut reachable = vec![false; self.num_layers];
+        let mut queue = VecDeque::from([layout.default_layer()]);
+        while let Some(layer) = queue.pop_front() {
+            if layer >= self.num_layers || reachable[layer] {
+                continue;
+            }
+            reachable[layer] = true;
+            queue.extend(self.targets[layer].iter());
+        }
+        for (i, _) in reachable.iter().enumerate().filter(|(_, reachable)| !**reachable) {
+            self.problem("layout.json", format!("Layer {} can not be reached from the default layer {}", i, layout.default_layer()));
+        }
+    }
+
+    /// Check an address refers to a loaded driver and inputs it provides
+    fn check_address(&mut self, location: &str, address: &Address) {
+        if let Some((name, last)) = address.driver_input() {
+            self.check_driver_input(location, name, last);
+        }
+    }
+
+    /// Check a driver is loaded and has an input
+    fn check_driver_input(&mut self, location: &str, name: &str, input: Option<usize>) {
+        let Some(drivers) = &self.drivers else {
+            return;
+        };
+
+        let problem = match drivers.get(name) {
+            None => Some(format!("Unknown driver {}", name)),
+            Some(driver) => {
+                let len = driver.try_read().map(|driver| driver.iter().len()).unwrap_or_default();
+                input.filter(|input| *input >= len)
+                    .map(|input| format!("Driver {} has {} inputs, input {} is out of range", name, len, input))
+            },
+        };
+        if let Some(problem) = problem {
+            self.problem(location, problem);
+        }
+    }
+
+    /// Collect the names of variables defined by a function
+    fn definitions(&mut self, function: &FunctionType) {
+        let json = serde_json::to_value(function).unwrap_or_default();
+        let mut values = vec![&json];
+        while let Some(value) = values.pop() {
+            match value {
+                serde_json::Value::Object(map) => {
+                    if let Some(name) = map.get("VarDef").and_then(|def| def.get("name")).and_then(|name| name.as_str()) {
+                        self.defined.insert(name.to_string());
+                    }
+                    values.extend(map.values());
+                },
+                serde_json::Value::Array(array) => values.extend(array.iter()),
+                _ => (),
+            }
+        }
+    }
+
+    /// Check a variable reference is defined and its value has the type the function expects
+    fn data<T>(&mut self, location: &str, data: &Data<T>) -> Option<T>
+    where
+        T: DeserializeOwned + Clone + Send + Sync + 'static,
+    {
+        match data {
+            Data::Const(value) => Some(value.clone()),
+            Data::VarDef { default, .. } => Some(default.clone()),
+            Data::Var(name) => {
+                let value = self.variables.as_ref().and_then(|variables| variables.get(name)).cloned();
+                match value {
+                    Some(value) => match serde_json::from_str::<T>(&value) {
+                        Ok(value) => Some(value),
+                        Err(e) => {
+                            self.problem(location, format!("Variable {} has the wrong type, {}", name, e));
+                            None
+                        },
+                    },
+                    None if self.defined.contains(name) => None,
+                    None if self.variables.is_some() => {
+                        self.problem(location, format!("Variable {} is not defined", name));
+                        None
+                    },
+                    None => None,
+                }
+            },
+        }
+    }
+
+    /// Check a layer target exists and record it for the reachability check
+    fn layer(&mut self, location: &str, layer: usize, target: &Data<usize>) {
+        let Some(target) = self.data(location, target) else {
+            // Targets only known at runtime could reach any layer
+            self.targets[layer].extend(0..self.num_layers);
+            return;
+        };
+
+        if target >= self.num_layers {
+            self.problem(location, format!("Layer {} does not exist, there are {} layers", target, self.num_layers));
+        } else {
+            self.targets[layer].insert(target);
+        }
+    }
+
+    /// Check a function, and the functions nested in it
+    async fn check_function(&mut self, location: &str, layer: usize, function: &FunctionType) {
+        match function {
+            FunctionType::Key(_) |
+            FunctionType::None |
+            FunctionType::Block |
+            FunctionType::LeftClick |
+            FunctionType::RightClick |
+            FunctionType::Click(_) |
+            FunctionType::Special(_) |
+            FunctionType::Media(_) |
+            FunctionType::Shortcut { .. } |
+            FunctionType::Modifier(_) |
+            FunctionType::OneShotModifier(_) |
+            FunctionType::NanoMsg { .. } => (),
+            FunctionType::Up => if layer + 1 < self.num_layers {
+                self.targets[layer].insert(layer + 1);
+            },
+            FunctionType::Down => if layer > 0 {
+                self.targets[layer].insert(layer - 1);
+            },
+            FunctionType::Switch(target) |
+            FunctionType::Shift(target) |
+            FunctionType::OneShotLayer(target) |
+            FunctionType::ToggleLayer(target) |
+            FunctionType::DefaultLayer(target) => self.layer(location, layer, target),
+            FunctionType::ConstMove { x, y } |
+            FunctionType::ImmediateMove { x, y } |
+            FunctionType::AbsoluteMove { x, y } => {
+                self.data(location, x);
+                self.data(location, y);
+            },
+            FunctionType::ConstScroll { amount, period } |
+            FunctionType::ConstPan { amount, period } => {
+                self.data(location, amount);
+                self.data(location, period);
+            },
+            FunctionType::ImmediateScroll(amount) |
+            FunctionType::ImmediatePan(amount) => {
+                self.data(location, amount);
+            },
+            FunctionType::String(str) |
+            FunctionType::StringLn(str) |
+            FunctionType::Bash(str) |
+            FunctionType::Pipe(str) |
+            FunctionType::SwitchHid { name: str } |
+            FunctionType::Macro { slot: str } |
+            FunctionType::RecordMacro { slot: str } => {
+                self.data(location, str);
+            },
+            FunctionType::ComplexString { str, layout } |
+            FunctionType::ComplexStringLn { str, layout } |
+            FunctionType::SendHidCommand { name: str, command: layout } => {
+                self.data(location, str);
+                self.data(location, layout);
+            },
+            FunctionType::RegionMove { dir: _, min, max, invert } => {
+                self.data(location, min);
+                self.data(location, max);
+                self.data(location, invert);
+            },
+            FunctionType::Move { dir: _, invert, slope_y, slope_x, maximum, threshold } => {
+                self.data(location, invert);
+                self.data(location, slope_y);
+                self.data(location, slope_x);
+                self.data(location, maximum);
+                self.data(location, threshold);
+            },
+            FunctionType::Scroll { period, invert, slope_y, slope_x, maximum, threshold } |
+            FunctionType::Pan { period, invert, slope_y, slope_x, maximum, threshold } => {
+                self.data(location, period);
+                self.data(location, invert);
+                self.data(location, slope_y);
+                self.data(location, slope_x);
+                self.data(location, maximum);
+                self.data(location, threshold);
+            },
+            FunctionType::Note { channel, note, velocity } => {
+                self.data::<Channel>(location, channel);
+                self.data::<note_param::Note>(location, note);
+                self.data(location, velocity);
+            },
+            FunctionType::ConstPitchBend { channel, bend } => {
+                self.data::<Channel>(location, channel);
+                self.data(location, bend);
+            },
+            FunctionType::PitchBend { channel, invert, threshold, scale } => {
+                self.data::<Channel>(location, channel);
+                self.data(location, invert);
+                self.data(location, threshold);
+                self.data(location, scale);
+            },
+            FunctionType::Instrument { channel, instrument } => {
+                self.data::<Channel>(location, channel);
+                self.data::<GMSoundSet>(location, instrument);
+            },
+            FunctionType::Log(level, msg) => {
+                self.data::<LogLevel>(location, level);
+                self.data(location, msg);
+            },
+            FunctionType::External { module, func } => {
+                if let Err(e) = self.module_manager.load_function(module, func.clone()).await {
+                    self.problem(location, format!("Unable to load function from module {}, {}", module, e));
+                }
+            },
+            FunctionType::Output { driver_name, idx, state } => {
+                let idx = self.data(location, idx);
+                self.data(location, state);
+                self.check_driver_input(location, driver_name, idx);
+            },
+            FunctionType::Flip { driver_name, idx } => {
+                let idx = self.data(location, idx);
+                self.check_driver_input(location, driver_name, idx);
+            },
+            FunctionType::ToggleHid { modes } => {
+                self.data(location, modes);
+            },
+            FunctionType::TapHold { tap, hold, timeout_ms, .. } => {
+                self.data(location, timeout_ms);
+                Box::pin(self.check_function(location, layer, tap)).await;
+                Box::pin(self.check_function(location, layer, hold)).await;
+            },
+            FunctionType::Leader { sequences, timeout_ms, .. } => {
+                self.data(location, timeout_ms);
+                for sequence in sequences {
+                    Box::pin(self.check_function(location, layer, &sequence.function)).await;
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Layout two keys wide, bound to the two inputs of the "keys" driver
+    fn layout(layers: Value) -> Value {
+        json!({
+            "width": 2,
+            "height": 1,
+            "bound": [{"DriverRow": {"name": "keys", "input": [0, 1], "root": 0}}],
+            "layers": layers,
+        })
+    }
+
+    /// Config directory with the layout and variables, an empty frontend config and a two input simulation driver "keys"
+    fn config(layout: Value, variables: Value) -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("layout.json"), layout.to_string()).unwrap();
+        fs::write(dir.path().join("variables.json"), variables.to_string()).unwrap();
+        fs::write(dir.path().join("frontend.json"), "[]").unwrap();
+        fs::create_dir(dir.path().join("drivers")).unwrap();
+        fs::write(dir.path().join("drivers").join("keys-sim"), json!({"inputs": 2}).to_string()).unwrap();
+        dir
+    }
+
+    async fn check(dir: &TempDir) -> Vec<String> {
+        Checker::check(
+            &dir.path().join("layout.json"),
+            &dir.path().join("variables.json"),
+            &dir.path().join("frontend.json"),
+            &dir.path().join("drivers"),
+            ModuleManager::empty(Events::new()),
+        ).await
+    }
+
+    #[tokio::test]
+    async fn valid_config_has_no_problems() {
+        let dir = config(layout(json!([
+            [[{"Shift": {"Const": 1}}, null]],
+            [[{"Key": "a"}, {"Switch": {"Var": "home"}}]],
+        ])), json!([{"name": "home", "default": "0"}]));
+
+        assert_eq!(check(&dir).await, Vec::<String>::new());
+    }
+
+    #[tokio::test]
+    async fn reports_unknown_drivers_and_inputs() {
+        let mut layout = layout(json!([[[
+            {"Output": {"driver_name": "missing", "idx": {"Const": 0}, "state": {"Const": 1}}},
+            null,
+        ]]]));
+        layout["bound"] = json!([
+            {"DriverAddr": {"name": "missing", "input": 0, "root": [0, 0]}},
+            {"DriverAddr": {"name": "keys", "input": 2, "root": [1, 0]}},
+        ]);
+        let dir = config(layout, json!([]));
+
+        assert_eq!(check(&dir).await, vec![
+            "layout.json, address bound at (0, 0): Unknown driver missing",
+            "layout.json, address bound at (1, 0): Driver keys has 2 inputs, input 2 is out of range",
+            "layout.json, layer 0, (0, 0): Unknown driver missing",
+        ]);
+    }
+
+    #[tokio::test]
+    async fn reports_undefined_and_mistyped_variables() {
+        let dir = config(layout(json!([
+            [[{"Shift": {"Var": "missing"}}, {"Shift": {"Var": "speed"}}]],
+            [[null, null]],
+        ])), json!([
+            {"name": "speed", "default": "\"fast\""},
+            {"name": "led.caps_lock", "default": "false"},
+        ]));
+
+        let problems = check(&dir).await;
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert_eq!(problems[0], "variables.json: Variable led.caps_lock uses the led variable prefix led.");
+        assert_eq!(problems[1], "layout.json, layer 0, (0, 0): Variable missing is not defined");
+        assert!(problems[2].starts_with("layout.json, layer 0, (1, 0): Variable speed has the wrong type, "), "{}", problems[2]);
+    }
+
+    #[tokio::test]
+    async fn reports_missing_and_unreachable_layers() {
+        let dir = config(layout(json!([
+            [[{"Switch": {"Const": 5}}, {"Shift": {"Const": 1}}]],
+            [[null, null]],
+            [[null, null]],
+        ])), json!([]));
+
+        assert_eq!(check(&dir).await, vec![
+            "layout.json, layer 0, (0, 0): Layer 5 does not exist, there are 3 layers",
+            "layout.json: Layer 2 can not be reached from the default layer 0",
+        ]);
+    }
+
+    #[tokio::test]
+    async fn reachability_starts_from_default_layer() {
+        let mut layout = layout(json!([
+            [[null, null]],
+            [[{"Shift": {"Const": 2}}, null]],
+            [[null, null]],
+        ]));
+        layout["default_layer"] = json!(1);
+        let dir = config(layout, json!([]));
+
+        assert_eq!(check(&dir).await, vec![
+            "layout.json: Layer 0 can not be reached from the default layer 1",
+        ]);
+    }
+}
@@ -1,18 +1,20 @@
-use std::{sync::Arc, thread, time::Duration, io::{Read, Write}, path::PathBuf, fs};
+use std::{sync::Arc, thread, time::Duration, io::{Read, Write}, path::PathBuf, fs, fmt::Display};
 
 use itertools::Itertools;
+use log::error;
 use nanomsg::{Socket, Protocol};
+use serde::de::DeserializeOwned;
 use tokio::{sync::{RwLock, oneshot}, task::JoinHandle};
-use key_rpc::{Command, FallibleRet};
+use key_rpc::{Command, Request, Response, RpcError, ErrorCode, PROTOCOL_VERSION};
 
-use crate::{layout::{Layout}, OrLogIgnore, function::FunctionType, OrLog, variables::Variables};
+use crate::{layout::{Layout, LayoutError, Address}, OrLogIgnore, function::FunctionType, OrLog, variables::Variables, reload::SavedConfigs};
 
 
 pub struct ConfigRPC {
 }
 
 impl ConfigRPC {
-    pub async fn start(front: String, back: String, layout: Arc<RwLock<Layout>>, layout_path: PathBuf, variables: Arc<RwLock<Variables>>, variables_path: PathBuf) -> Result<JoinHandle<()>, nanomsg::Error> {
+    pub async fn start(front: String, back: String, layout: Arc<RwLock<Layout>>, layout_path: PathBuf, variables: Arc<RwLock<Variables>>, variables_path: PathBuf, saved: SavedConfigs) -> Result<JoinHandle<()>, nanomsg::Error> {
         let (device_tx, mut device_rx) = oneshot::channel();
         {
             let back = back.clone();
@@ -55,16 +57,34 @@ impl ConfigRPC {
         socket.connect(&back)?;
         socket.set_send_timeout(10)?;
 
-        fn bool_to_str(bool: bool) -> &'static str{
-            if bool {
-                "true"
-            } else {
-                "false"
+        /// Response to a command that either succeeds or fails with an error code
+        fn ok_or<E: Display>(res: Result<(), E>, code: ErrorCode) -> Response {
+            match res {
+                Ok(()) => Response::Ok,
+                Err(e) => RpcError::new(code, e).into(),
             }
         }
 
+        /// Parse a json command argument
+        fn parse<T: DeserializeOwned>(json: &str, name: &str) -> Result<T, Response> {
+            serde_json::from_str(json)
+                .map_err(|e| RpcError::new(ErrorCode::InvalidArgument, format!("Unable to parse {}, {}", name, e)).into())
+        }
+
+        /// Write json to a config file, recorded so the config watcher does not reload it
+        fn save(json: Result<String, serde_json::Error>, path: &PathBuf, saved: &SavedConfigs) -> Response {
+            let res = json
+                .map_err(|e| e.to_string())
+                .and_then(|json| {
+                    saved.record(path, &json);
+                    fs::write(path, json).map_err(|e| e.to_string())
+                });
+            ok_or(res, ErrorCode::IO)
+        }
+
         Ok(tokio::task::spawn_blocking(move || {
             let mut buffer = String::new();
+            let handle = tokio::runtime::Handle::current();
             loop {
                 buffer.clear();
 
@@ -72,125 +92,119 @@ impl ConfigRPC {
                     continue;
                 };
 
-                let Some(command) = serde_json::from_str::<Command>(&buffer).or_log("Invalid RPC command (Config RPC)") else {
-                    continue;
+                // Every request carries the client's protocol version, requests from other versions are refused
+                let command = match serde_json::from_str::<Request>(&buffer) {
+                    Err(e) => Err(RpcError::new(ErrorCode::InvalidCommand, e)),
+                    Ok(Request { version, command }) if version == PROTOCOL_VERSION => Ok(command),
+                    Ok(Request { version, .. }) => Err(RpcError::new(
+                        ErrorCode::VersionMismatch,
+                        format!("Server uses protocol version {}, client uses {}", PROTOCOL_VERSION, version)
+                    )),
                 };
 
-                socket.write_all(&match command {
-                    Command::Layer => layout.blocking_read()
-                        .layout_string()
-                        .unwrap_or("".to_string())
-                        .as_bytes()
-                        .to_owned(),
-                    Command::GetLayer(index) => serde_json::to_string(
-                            &FallibleRet{
-                                ret: layout.blocking_read()
-                                    .layout_string_at(index)
-                            }
-                        ).unwrap_or_else(|_| "".to_string())
-                        .as_bytes()
-                        .to_owned(),
-                    Command::LayerIdx => layout.blocking_read()
-                        .curr_layer()
-                        .to_string()
-                        .as_bytes()
-                        .to_owned(),
-                    Command::NumLayers => layout.blocking_read()
-                        .num_layers()
-                        .to_string()
-                        .as_bytes()
-                        .to_owned(),
-                    Command::AddLayer(layer) => {
-                        let Some(layer) = serde_json::from_str::<Vec<Vec<FunctionType>>>(&layer).or_log("Unable to parse layer (ConfigRPC)") else {
-                            continue;
-                        };
-
-                        let mut layout_write = layout.blocking_write();
-                        let index = layout_write.layer_len();
-                        bool_to_str(
-                            tokio::runtime::Handle::current()
-                            .block_on(layout_write.add_layer(layer, index))
-                            .is_ok()
-                        )
-                        .as_bytes()
-                        .to_owned()
-                    }
-                    Command::RemoveLayer(idx) => {
-                        bool_to_str(
-                            layout.blocking_write()
-                            .remove_layer(idx)
-                            .is_some()
-                        )
-                        .as_bytes()
-                        .to_owned()
-                    }
-                    Command::SwitchLayer(index) => bool_to_str(
-                        layout.blocking_write()
-                            .switch_layer(index).is_some()
-                        )
-                        .as_bytes()
-                        .to_owned(),
-                    Command::UpLayer => bool_to_str(
-                            layout.blocking_write().
-                            up_layer().
-                            is_some()
-                        )
-                        .as_bytes()
-                        .to_owned(),
-                    Command::DownLayer => bool_to_str(
-                            layout.blocking_write().
-                            down_layer()
-                            .is_some()
-                        )
-                        .as_bytes()
-                        .to_owned(),
-                    Command::SaveLayout => layout.blocking_read().to_json().or_log("Unable to serialize layout")
-                        .and_then(|json| 
-                            fs::File::create(&layout_path)
-                            .or_log("Unable to open layout config")
-                            .map(|file| (json, file))
-                        )
-                        .and_then(|(json, mut file)| 
-                            file.write_all(&json.as_bytes())
-                            .or_log("Unable to write to layout config")
-                        )
-                        .map(|_| "true".as_bytes().to_owned())
-                        .unwrap_or_else(|| "false".as_bytes().to_owned()),
-                    Command::Variables => serde_json::to_string(
-                            &variables.blocking_read()
+                let response: Response = match command {
+                    Err(e) => e.into(),
+                    Ok(Command::Version) => Response::Version(PROTOCOL_VERSION),
+                    Ok(Command::Layer) => match layout.blocking_read().layout_string() {
+                        Some(layer) => Response::String(layer),
+                        None => RpcError::new(ErrorCode::NotFound, "No active layer").into(),
+                    },
+                    Ok(Command::GetLayer(index)) => match layout.blocking_read().layout_string_at(index) {
+                        Some(layer) => Response::String(layer),
+                        None => RpcError::new(ErrorCode::NotFound, LayoutError::InvalidLayer).into(),
+                    },
+                    Ok(Command::LayerIdx) => Response::Usize(layout.blocking_read().curr_layer()),
+                    Ok(Command::NumLayers) => Response::Usize(layout.blocking_read().num_layers()),
+                    Ok(Command::AddLayer(layer)) => match parse::<Vec<Vec<FunctionType>>>(&layer, "layer") {
+                        Ok(layer) => {
+                            let mut layout = layout.blocking_write();
+                            let index = layout.layer_len();
+                            ok_or(handle.block_on(layout.add_layer(layer, index)).map(|_| ()), ErrorCode::InvalidLayout)
+                        },
+                        Err(response) => response,
+                    },
+                    Ok(Command::InsertLayer(index, layer)) => match parse::<Vec<Vec<FunctionType>>>(&layer, "layer") {
+                        Ok(layer) => ok_or(
+                            handle.block_on(layout.blocking_write().add_layer(layer, index)).map(|_| ()),
+                            ErrorCode::InvalidLayout
+                        ),
+                        Err(response) => response,
+                    },
+                    Ok(Command::SetLayer(index, layer)) => match parse::<Vec<Vec<FunctionType>>>(&layer, "layer") {
+                        Ok(layer) => ok_or(handle.block_on(layout.blocking_write().set_layer(layer, index)), ErrorCode::InvalidLayout),
+                        Err(response) => response,
+                    },
+                    Ok(Command::MoveLayer(from, to)) => ok_or(
+                        layout.blocking_write().move_layer(from, to).ok_or(LayoutError::InvalidLayer),
+                        ErrorCode::NotFound
+                    ),
+                    Ok(Command::RemoveLayer(index)) => ok_or(
+                        layout.blocking_write().remove_layer(index).map(|_| ()).ok_or(LayoutError::InvalidLayer),
+                        ErrorCode::NotFound
+                    ),
+                    Ok(Command::SetBinding(index, x, y, function)) => match parse::<FunctionType>(&function, "function") {
+                        Ok(function) => ok_or(
+                            handle.block_on(layout.blocking_write().set_binding(index, (x, y), function)),
+                            ErrorCode::InvalidLayout
+                        ),
+                        Err(response) => response,
+                    },
+                    Ok(Command::ClearBinding(index, x, y)) => ok_or(
+                        handle.block_on(layout.blocking_write().set_binding(index, (x, y), FunctionType::None)),
+                        ErrorCode::InvalidLayout
+                    ),
+                    Ok(Command::Addresses) => match serde_json::to_string(&layout.blocking_read().addresses()) {
+                        Ok(addresses) => Response::String(addresses),
+                        Err(e) => RpcError::new(ErrorCode::InvalidLayout, e).into(),
+                    },
+                    Ok(Command::AddAddress(address)) => match parse::<Address>(&address, "address") {
+                        Ok(address) => ok_or(handle.block_on(layout.blocking_write().add_address(address)), ErrorCode::InvalidLayout),
+                        Err(response) => response,
+                    },
+                    Ok(Command::RemoveAddress(x, y)) => ok_or(
+                        handle.block_on(layout.blocking_write().remove_address((x, y))).map(|_| ()).ok_or("No address bound"),
+                        ErrorCode::NotFound
+                    ),
+                    Ok(Command::SwitchLayer(index)) => ok_or(
+                        layout.blocking_write().switch_layer(index).ok_or(LayoutError::InvalidLayer),
+                        ErrorCode::NotFound
+                    ),
+                    Ok(Command::UpLayer) => ok_or(
+                        layout.blocking_write().up_layer().ok_or("Already on the top layer"),
+                        ErrorCode::NotFound
+                    ),
+                    Ok(Command::DownLayer) => ok_or(
+                        layout.blocking_write().down_layer().ok_or("Already on the bottom layer"),
+                        ErrorCode::NotFound
+                    ),
+                    Ok(Command::SaveLayout) => save(layout.blocking_read().to_json(), &layout_path, &saved),
+                    Ok(Command::Variables) => Response::Strings(
+                        variables.blocking_read()
                             .variables()
+                            .cloned()
                             .collect_vec()
-                        ).unwrap_or_else(|_| "".to_string())
-                        .as_bytes()
-                        .to_owned(),
-                    Command::SetVariable(name, value) => bool_to_str(
-                            variables.blocking_read()
-                            .update(&name, value)
-                            .is_some()
-                        )
-                        .as_bytes()
-                        .to_owned(),
-                    Command::GetVariable(name) => serde_json::to_string(
-                        &FallibleRet{
-                            ret: variables.blocking_read().get(&name)
-                        }
-                    ).unwrap_or_else(|_| "".to_string())
-                    .as_bytes()
-                    .to_owned(),
-                    Command::SaveVariables => variables.blocking_read().to_json().or_log("Unable to serialize layout")
-                    .and_then(|json| 
-                        fs::File::create(&variables_path)
-                        .or_log("Unable to open variables")
-                        .map(|file| (json, file))
-                    )
-                    .and_then(|(json, mut file)| 
-                        file.write_all(&json.as_bytes())
-                        .or_log("Unable to write to variables")
-                    )
-                    .map(|_| "true".as_bytes().to_owned())
-                    .unwrap_or_else(|| "false".as_bytes().to_owned()),
-                }).or_log_ignore("Socket error (Config RPC)");    
+                    ),
+                    Ok(Command::SetVariable(name, value)) => ok_or(
+                        variables.blocking_read().update(&name, value).ok_or("No such variable"),
+                        ErrorCode::NotFound
+                    ),
+                    Ok(Command::GetVariable(name)) => match variables.blocking_read().get(&name) {
+                        Some(value) => Response::String(value),
+                        None => RpcError::new(ErrorCode::NotFound, "No such variable").into(),
+                    },
+                    Ok(Command::SaveVariables) => save(variables.blocking_read().to_json(), &variables_path, &saved),
+                };
+
+                if let Response::Error(e) = &response {
+                    error!("RPC command failed (Config RPC), {}", e);
+                }
+
+                let Some(response) = serde_json::to_string(&response).or_log("Unable to serialize response (Config RPC)") else {
+                    continue;
+                };
+
+                socket.write_all(response.as_bytes()).or_log_ignore("Socket error (Config RPC)");
             }
         }))
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,70 @@
+use std::time::{Instant, Duration};
+
+use serde::{Serialize, Deserialize};
+
+use crate::function::{State, StateHelpers};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// Debounce algorithm
+pub enum DebounceAlgorithm {
+    /// Report a change straight away then ignore changes until the window has passed
+    Eager,
+    /// Report a change once it has held for the window
+    Deferred,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// Debounce configuration
+pub struct Debounce {
+    pub algorithm: DebounceAlgorithm,
+    pub window_ms: u64,
+}
+
+/// Debouncer, filters chatter from the states of a single input
+pub struct Debouncer {
+    algorithm: DebounceAlgorithm,
+    window: Duration,
+    state: State,
+    changed: Option<Instant>,
+}
+
+impl Debouncer {
+    /// New
+    pub fn new(debounce: Debounce) -> Debouncer {
+        Debouncer {
+            algorithm: debounce.algorithm,
+            window: Duration::from_millis(debounce.window_ms),
+            state: 0,
+            changed: None,
+        }
+    }
+
+    /// Filter a raw state, returning the debounced state
+    pub fn debounce(&mut self, state: State) -> State {
+        if state.high() == self.state.high() {
+            if self.algorithm == DebounceAlgorithm::Deferred {
+                self.changed = None;
+            }
+            self.state = state;
+            return self.state;
+        }
+
+        match self.algorithm {
+            DebounceAlgorithm::Eager => {
+                if !matches!(self.changed, Some(changed) if changed.elapsed() < self.window) {
+                    self.state = state;
+                    self.changed = Some(Instant::now());
+                }
+            },
+            DebounceAlgorithm::Deferred => {
+                let changed = *self.changed.get_or_insert_with(Instant::now);
+                if changed.elapsed() >= self.window {
+                    self.state = state;
+                    self.changed = None;
+                }
+            },
+        }
+
+        self.state
+    }
+}
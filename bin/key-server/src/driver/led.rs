@@ -0,0 +1,145 @@
+use std::{ops::Range, sync::Arc};
+
+use async_trait::async_trait;
+use tokio::sync::{RwLock, watch};
+
+use crate::{function::{PRESSED, RELEASED}, variables::Variables};
+
+use super::{Driver, DriverData, DriverError, DriverInterface};
+
+/// Module name of the built in led driver
+pub const LED_MODULE: &str = "led";
+
+/// Keyboard leds in the order of their bits in a led report, also the names of the led variables
+pub const LEDS: [&str; 5] = ["num_lock", "caps_lock", "scroll_lock", "compose", "kana"];
+
+/// Prefix of the led variable names, keeping them apart from variables defined in variables.json
+pub const LED_VARIABLE_PREFIX: &str = "led.";
+
+/// Name of the variable of a led
+pub fn led_variable(led: &str) -> String {
+    format!("{}{}", LED_VARIABLE_PREFIX, led)
+}
+
+#[derive(Clone)]
+/// Host led state, the last led report received by the hid controller
+pub struct Leds {
+    tx: watch::Sender<u8>,
+}
+
+impl Leds {
+    /// New, every led is off until a led report is received
+    pub fn new() -> Leds {
+        let (tx, _) = watch::channel(0);
+        Leds { tx }
+    }
+
+    /// Set the host led state from a led report
+    pub fn set(&self, report: u8) {
+        self.tx.send_if_modified(|state| {
+            let modified = *state != report;
+            *state = report;
+            modified
+        });
+    }
+
+    /// Receive the host led state as it changes
+    pub fn subscribe(&self) -> watch::Receiver<u8> {
+        self.tx.subscribe()
+    }
+}
+
+/// Whether a led is on in a led report
+fn is_on(report: u8, led: usize) -> bool {
+    report & (1 << led) != 0
+}
+
+/// Led driver, an input per keyboard led that is pressed whilst the host has the led turned on
+pub struct LedDriver {
+    state: Vec<u16>,
+    leds: watch::Receiver<u8>,
+}
+
+impl LedDriver {
+    /// New
+    pub fn new(leds: &Leds) -> LedDriver {
+        LedDriver { state: vec![RELEASED; LEDS.len()], leds: leds.subscribe() }
+    }
+
+    /// New, the led driver has no configuration
+    pub fn from_config(_data: &str, leds: &Leds) -> Result<Driver, DriverError> {
+        Ok(Box::new(LedDriver::new(leds)))
+    }
+}
+
+#[async_trait]
+impl DriverInterface for LedDriver {
+    fn iter(&self) -> std::slice::Iter<u16> {
+        self.state.iter()
+    }
+
+    fn poll(&self, idx: usize) -> u16 {
+        self.state.get(idx).copied().unwrap_or(RELEASED)
+    }
+
+    fn poll_range(&self, range: &Range<usize>) -> Option<&[u16]> {
+        self.state.get(range.clone())
+    }
+
+    fn poll_list(&self, idx: &Vec<usize>) -> Option<Vec<u16>> {
+        Some(idx.iter().map(|idx| self.poll(*idx)).collect())
+    }
+
+    async fn set(&mut self, _idx: usize, _state: u16) {
+    }
+
+    async fn tick(&mut self) {
+        let report = *self.leds.borrow_and_update();
+        for (led, state) in self.state.iter_mut().enumerate() {
+            *state = if is_on(report, led) { PRESSED } else { RELEASED };
+        }
+    }
+
+    fn to_driver_data(&self) -> DriverData {
+        DriverData { module: LED_MODULE.to_string(), data: String::new() }
+    }
+}
+
+/// Keep a variable per keyboard led, named with the led variable prefix, "true" whilst the host has the led turned on
+pub async fn update_variables(variables: Arc<RwLock<Variables>>, leds: Leds) {
+    let mut leds = leds.subscribe();
+    loop {
+        let report = *leds.borrow_and_update();
+        {
+            let mut variables = variables.write().await;
+            for (led, name) in LEDS.iter().enumerate() {
+                let name = led_variable(name);
+                let value = is_on(report, led).to_string();
+                if variables.update(&name, value.clone()).is_none() {
+                    variables.set(&name, watch::channel(value));
+                }
+            }
+        }
+
+        if leds.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn polls_led_report() {
+        let leds = Leds::new();
+        let mut driver = LedDriver::new(&leds);
+        leds.set(0b010);
+        driver.tick().await;
+        assert_eq!(driver.iter().copied().collect::<Vec<u16>>(), vec![RELEASED, PRESSED, RELEASED, RELEASED, RELEASED]);
+        leds.set(0b101);
+        driver.tick().await;
+        assert_eq!(driver.iter().copied().collect::<Vec<u16>>(), vec![PRESSED, RELEASED, PRESSED, RELEASED, RELEASED]);
+    }
+}
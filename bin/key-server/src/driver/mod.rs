@@ -1,11 +1,19 @@
-use std::{ops::Range, collections::HashMap, fmt::Display, sync::Arc, path::Path, fs, io::Write};
+use std::{ops::Range, collections::HashMap, fmt::Display, sync::Arc, path::Path, fs, io::Write, time::Duration};
 
 use async_trait::async_trait;
 use itertools::Itertools;
 use serde::{Serialize, Deserialize};
+use tokio::{sync::RwLock, task::JoinHandle, time::{self, MissedTickBehavior}};
 
 use crate::modules::{ExternalDriver, ModuleManager};
 
+use self::{sim::{SimDriver, SIM_MODULE}, led::{LedDriver, Leds, LED_MODULE}};
+
+/// Keyboard led driver
+pub mod led;
+/// Simulation driver
+pub mod sim;
+
 #[derive(Debug)]
 /// Driver error
 pub struct DriverError {
@@ -55,20 +63,36 @@ pub trait DriverInterface {
 /// Driver Object
 pub type Driver = Box<dyn DriverInterface+ Send + Sync>;
 
+/// Driver shared between the scan loop and its tick task
+pub type SharedDriver = Arc<RwLock<Driver>>;
+
 /// Driver Manager
 pub struct DriverManager {
-    drivers: HashMap<String, Driver>,
+    drivers: HashMap<String, SharedDriver>,
+    tick_intervals: HashMap<String, Duration>,
+    tick_tasks: HashMap<String, JoinHandle<()>>,
+}
+
+impl Drop for DriverManager {
+    fn drop(&mut self) {
+        for (_, task) in self.tick_tasks.drain() {
+            task.abort();
+        }
+    }
 }
 
 impl DriverManager {
-    #[allow(dead_code)]
     /// New
     pub fn new(drivers: HashMap<String, Driver>) -> DriverManager {
-        DriverManager { drivers }
+        DriverManager {
+            drivers: drivers.into_iter().map(|(name, driver)| (name, Arc::new(RwLock::new(driver)))).collect(),
+            tick_intervals: HashMap::new(),
+            tick_tasks: HashMap::new(),
+        }
     }
 
     /// Load driver configurations from folder
-    pub async fn load(drivers: &Path, module_manager: Arc<ModuleManager>) -> Result<DriverManager, DriverError> {
+    pub async fn load(drivers: &Path, module_manager: Arc<ModuleManager>, leds: &Leds) -> Result<DriverManager, DriverError> {
         let contents = fs::read_dir(drivers).map_err(|e| DriverError::new(format!("{}", e)))?;
 
         let mut drivers = HashMap::new();
@@ -95,19 +119,26 @@ impl DriverManager {
                 return Err(DriverError::new("Driver name already taken".to_string()))
             }
 
-            let driver: Driver = ExternalDriver::new(module.to_string(), data, module_manager.clone()).await
-                    .map_err(|e| DriverError::new(format!("{}", e)))?;
+            let driver: Driver = match module.as_str() {
+                SIM_MODULE => SimDriver::from_script(&data)?,
+                LED_MODULE => LedDriver::from_config(&data, leds)?,
+                _ => ExternalDriver::new(module.to_string(), data, module_manager.clone()).await
+                    .map_err(|e| DriverError::new(format!("{}", e)))?,
+            };
 
             drivers.insert(name, driver);
         }
 
-        Ok(DriverManager { drivers })
+        Ok(DriverManager::new(drivers))
     }
 
     #[allow(dead_code)]
     /// Serialize driver configuration to driver folder
     pub fn serialize(&self, drivers: &Path) -> Result<(), DriverError> {
         for (name, driver) in &self.drivers {
+            let Ok(driver) = driver.try_read() else {
+                return Err(DriverError::new(format!("Driver {} is busy", name)));
+            };
             match driver.to_driver_data() {
                 DriverData{module, data} => {
                     fs::File::create(drivers.join(format!("{}-{}", name, module)))
@@ -122,20 +153,57 @@ impl DriverManager {
     }
 
     /// Get a driver by name
-    pub fn get(&self, name: &str) -> Option<&Driver> {
+    pub fn get(&self, name: &str) -> Option<&SharedDriver> {
         self.drivers.get(name)
     }
 
-    #[allow(dead_code)]
-    /// Get a driver by name
-    pub fn get_mut(&mut self, name: &str) -> Option<&mut Driver> {
-        self.drivers.get_mut(name)
+    /// Replace the loaded drivers, keeping tick intervals
+    pub fn replace_drivers(&mut self, mut driver_manager: DriverManager) {
+        self.drivers = std::mem::take(&mut driver_manager.drivers);
+        let names = self.tick_intervals.keys().cloned().collect_vec();
+        for name in names {
+            self.spawn_tick_task(&name);
+        }
+    }
+
+    /// Set the time between ticks of a driver, a driver with an interval is ticked on its own task
+    /// instead of on every scan, so a slow tick does not hold up scanning. An interval of 0 ticks on every scan.
+    pub fn set_tick_interval(&mut self, name: &str, interval: Duration) {
+        if interval.is_zero() {
+            self.tick_intervals.remove(name);
+        } else {
+            self.tick_intervals.insert(name.to_string(), interval);
+        }
+        self.spawn_tick_task(name);
+    }
+
+    /// Restart the tick task of a driver, stopping it if the driver has no tick interval
+    fn spawn_tick_task(&mut self, name: &str) {
+        if let Some(task) = self.tick_tasks.remove(name) {
+            task.abort();
+        }
+
+        let (Some(interval), Some(driver)) = (self.tick_intervals.get(name), self.drivers.get(name)) else {
+            return;
+        };
+
+        let mut interval = time::interval(*interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let driver = driver.clone();
+        self.tick_tasks.insert(name.to_string(), tokio::spawn(async move {
+            loop {
+                interval.tick().await;
+                driver.write().await.tick().await;
+            }
+        }));
     }
 
-    /// Tick drivers
-    pub async fn tick(&mut self) {
-        for driver in self.drivers.values_mut() {
-            driver.tick().await;
+    /// Tick drivers without a tick interval, drivers with one are ticked by their tick task
+    pub async fn tick(&self) {
+        for (name, driver) in self.drivers.iter() {
+            if !self.tick_intervals.contains_key(name) {
+                driver.write().await.tick().await;
+            }
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,155 @@
+use std::{collections::VecDeque, ops::Range, sync::{Arc, Mutex}};
+
+use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
+
+use super::{Driver, DriverData, DriverError, DriverInterface};
+
+/// Module name of the built in simulation driver
+pub const SIM_MODULE: &str = "sim";
+
+/// State changes applied in a single tick, a list of (input, state) pairs
+pub type Frame = Vec<(usize, u16)>;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+/// Simulation script, used to configure a simulation driver
+pub struct SimScript {
+    /// Number of inputs
+    pub inputs: usize,
+    /// Frames applied one per tick
+    #[serde(default)]
+    pub frames: Vec<Frame>,
+    /// Play the frames again once they have all been applied
+    #[serde(default)]
+    pub repeat: bool,
+}
+
+#[derive(Clone)]
+/// Simulation input, queues frames on a simulation driver
+pub struct SimInput {
+    frames: Arc<Mutex<VecDeque<Frame>>>,
+}
+
+impl SimInput {
+    /// Queue a frame, applied on the tick after every frame queued before it
+    pub fn queue(&self, frame: Frame) {
+        if let Ok(mut frames) = self.frames.lock() {
+            frames.push_back(frame);
+        }
+    }
+
+    /// Queue a frame that sets a single input
+    pub fn set(&self, idx: usize, state: u16) {
+        self.queue(vec![(idx, state)]);
+    }
+
+    /// Number of frames waiting to be applied
+    pub fn pending(&self) -> usize {
+        self.frames.lock().map(|frames| frames.len()).unwrap_or(0)
+    }
+}
+
+/// Simulation driver, a driver whose states are scripted instead of read from hardware
+pub struct SimDriver {
+    state: Vec<u16>,
+    script: SimScript,
+    input: SimInput,
+}
+
+impl SimDriver {
+    /// New, returns the driver and an input used to queue frames on it
+    pub fn new(script: SimScript) -> (SimDriver, SimInput) {
+        let input = SimInput { frames: Arc::new(Mutex::new(script.frames.iter().cloned().collect())) };
+        (SimDriver { state: vec![0; script.inputs], script, input: input.clone() }, input)
+    }
+
+    /// New, from a json simulation script
+    pub fn from_script(data: &str) -> Result<Driver, DriverError> {
+        let script = serde_json::from_str(data)
+            .map_err(|e| DriverError::new(format!("Unable to parse simulation script, {}", e)))?;
+        Ok(Box::new(SimDriver::new(script).0))
+    }
+}
+
+#[async_trait]
+impl DriverInterface for SimDriver {
+    fn iter(&self) -> std::slice::Iter<u16> {
+        self.state.iter()
+    }
+
+    fn poll(&self, idx: usize) -> u16 {
+        self.state.get(idx).copied().unwrap_or(0)
+    }
+
+    fn poll_range(&self, range: &Range<usize>) -> Option<&[u16]> {
+        self.state.get(range.clone())
+    }
+
+    fn poll_list(&self, idx: &Vec<usize>) -> Option<Vec<u16>> {
+        Some(idx.iter().map(|idx| self.poll(*idx)).collect())
+    }
+
+    async fn set(&mut self, idx: usize, state: u16) {
+        if let Some(input) = self.state.get_mut(idx) {
+            *input = state;
+        }
+    }
+
+    async fn tick(&mut self) {
+        let Ok(mut frames) = self.input.frames.lock() else {
+            return;
+        };
+
+        if frames.is_empty() && self.script.repeat {
+            frames.extend(self.script.frames.iter().cloned());
+        }
+
+        for (idx, state) in frames.pop_front().unwrap_or_default() {
+            if let Some(input) = self.state.get_mut(idx) {
+                *input = state;
+            }
+        }
+    }
+
+    fn to_driver_data(&self) -> DriverData {
+        DriverData { module: SIM_MODULE.to_string(), data: serde_json::to_string(&self.script).unwrap_or_default() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn applies_one_frame_per_tick() {
+        let (mut driver, input) = SimDriver::new(SimScript { inputs: 2, frames: vec![vec![(0, 1)], vec![(1, 2)]], repeat: false });
+        input.set(0, 3);
+
+        driver.tick().await;
+        assert_eq!(driver.iter().copied().collect::<Vec<u16>>(), vec![1, 0]);
+        driver.tick().await;
+        assert_eq!(driver.iter().copied().collect::<Vec<u16>>(), vec![1, 2]);
+        driver.tick().await;
+        assert_eq!(driver.iter().copied().collect::<Vec<u16>>(), vec![3, 2]);
+        assert_eq!(input.pending(), 0);
+    }
+
+    #[tokio::test]
+    async fn repeats_script() {
+        let (mut driver, _) = SimDriver::new(SimScript { inputs: 1, frames: vec![vec![(0, 1)], vec![(0, 0)]], repeat: true });
+
+        let mut states = vec![];
+        for _ in 0..4 {
+            driver.tick().await;
+            states.push(driver.poll(0));
+        }
+        assert_eq!(states, vec![1, 0, 1, 0]);
+    }
+
+    #[test]
+    fn parses_script() {
+        let driver = SimDriver::from_script(r#"{"inputs": 3, "frames": [[[2, 65535]]]}"#).unwrap();
+        assert_eq!(driver.iter().len(), 3);
+        assert!(SimDriver::from_script("[]").is_err());
+    }
+}
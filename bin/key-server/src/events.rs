@@ -0,0 +1,53 @@
+use std::io::Write;
+
+use key_rpc::Event;
+use nanomsg::{Socket, Protocol};
+use tokio::{sync::broadcast::{self, error::RecvError}, task::JoinHandle};
+
+use crate::{OrLog, OrLogIgnore};
+
+/// Number of events buffered for slow subscribers
+const EVENT_CAPACITY: usize = 256;
+
+#[derive(Clone)]
+/// Event bus, publishes server events to rpc subscribers
+pub struct Events {
+    tx: broadcast::Sender<Event>,
+}
+
+impl Events {
+    /// New
+    pub fn new() -> Events {
+        let (tx, _) = broadcast::channel(EVENT_CAPACITY);
+        Events { tx }
+    }
+
+    /// Send an event, dropped if nothing is publishing
+    pub fn send(&self, event: Event) {
+        self.tx.send(event).ok();
+    }
+
+    /// Start publishing events on a nanomsg pub socket
+    pub fn start(&self, addr: String) -> Result<JoinHandle<()>, nanomsg::Error> {
+        let mut socket = Socket::new(Protocol::Pub)?;
+        let endpoint = socket.bind(&addr)?;
+        let mut rx = self.tx.subscribe();
+
+        Ok(tokio::task::spawn_blocking(move || {
+            let _endpoint = endpoint;
+            loop {
+                let event = match rx.blocking_recv() {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                };
+
+                let Some(event) = serde_json::to_string(&event).or_log("Unable to serialize event (Events)") else {
+                    continue;
+                };
+
+                socket.write_all(event.as_bytes()).or_log_ignore("Socket error (Events)");
+            }
+        }))
+    }
+}
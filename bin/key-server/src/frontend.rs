@@ -1,9 +1,9 @@
-use std::{collections::HashSet, hash::Hash, sync::Arc};
+use std::{collections::{HashSet, HashMap}, hash::Hash, sync::Arc};
 
 use async_trait::async_trait;
 use serde::{Serialize, Deserialize};
 
-use crate::modules::ModuleManager;
+use crate::{function::hid::default_wheel_multiplier, modules::ModuleManager, events::Events, driver::led::Leds, scheduler::{default_scan_rate, default_metrics_period}};
 
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq)]
@@ -14,6 +14,18 @@ pub enum FrontendConfigData {
         mouse: String,
         keyboard: String,
         led: String,
+        #[serde(default)]
+        consumer: String,
+        #[serde(default)]
+        boot: String,
+        #[serde(default)]
+        tablet: String,
+        #[serde(default = "default_wheel_multiplier")]
+        wheel_multiplier: u8,
+        #[serde(default)]
+        hi_res_scroll: bool,
+        #[serde(default)]
+        mirror: Vec<String>,
     },
     MidiController,
     NanoMsg {
@@ -24,7 +36,15 @@ pub enum FrontendConfigData {
     RPC {
         front: String,
         back: String,
-    }
+    },
+    Scheduler {
+        #[serde(default = "default_scan_rate")]
+        scan_rate: u32,
+        #[serde(default)]
+        tick_intervals: HashMap<String, u64>,
+        #[serde(default = "default_metrics_period")]
+        metrics_period: u64,
+    },
 }
 
 impl Hash for FrontendConfigData {
@@ -53,6 +73,8 @@ pub trait FrontendConfig {
 /// Function configuration, managers function controller configs
 pub struct FrontendConfiguration {
     pub module_manager: Arc<ModuleManager>,
+    pub events: Events,
+    pub leds: Leds,
     configs: HashSet<FrontendConfigData>,
 }
 
@@ -61,11 +83,15 @@ impl FrontendConfiguration {
     pub fn new(
         config: &str,
         module_manager: Arc<ModuleManager>,
+        events: Events,
+        leds: Leds,
     ) -> Result<FrontendConfiguration, serde_json::Error> {
         let configs = serde_json::from_str(config)?;
         Ok(FrontendConfiguration {
             configs,
             module_manager,
+            events,
+            leds,
         })
     }
 
@@ -0,0 +1,842 @@
+use std::{sync::Arc, collections::HashMap, fs::{File, OpenOptions}, io::{self, Read, Write}, os::unix::fs::OpenOptionsExt};
+
+use log::{info, error};
+use tokio::runtime::Handle;
+use uinput::{event::{self, controller::Mouse, relative::{Position, Wheel}, absolute, keyboard::{Key, Misc, KeyPad, InputAssist}}, Device};
+use virt_hid::{key::{self, BasicKey, KeyOrigin, SpecialKey, Modifier}, mouse::MouseDir};
+
+use crate::{OrLog, modules::ModuleManager};
+
+use super::{hid::{HIDError, HidConfig}, keyboard::MediaKey, mouse::{Button, ABSOLUTE_MAX}, nkro::NkroKeyboard, pointer::UsbMouse};
+
+/// Hid backend, a device hid actions are sent to. Backends are registered with the hid controller by name.
+pub trait HidBackend: Send {
+    /// Hold key
+    fn hold_key(&mut self, key: char);
+    /// Hold special key
+    fn hold_special(&mut self, special: SpecialKey);
+    /// Hold modifier
+    fn hold_modifier(&mut self, modifier: Modifier);
+    /// Release key
+    fn release_key(&mut self, key: char);
+    /// Release special key
+    fn release_special(&mut self, special: SpecialKey);
+    /// Release modifier
+    fn release_modifier(&mut self, modifier: Modifier);
+    /// Type string (with default keyboard layout)
+    fn press_basic_str(&mut self, str: &str);
+    /// Type string resolving keys with a keyboard layout
+    fn press_str(&mut self, layout: &str, str: &str);
+    /// Scroll wheel
+    fn scroll_wheel(&mut self, amount: i8);
+    /// Scroll horizontally
+    fn pan(&mut self, _amount: i8) {}
+    /// Move mouse
+    fn move_mouse(&mut self, amount: i8, dir: MouseDir);
+    /// Hold mouse button
+    fn hold_button(&mut self, button: Button);
+    /// Release mouse button
+    fn release_button(&mut self, button: Button);
+    /// Hold media key, sent immediately
+    fn hold_media(&mut self, _media: MediaKey) {}
+    /// Release media key, sent immediately
+    fn release_media(&mut self, _media: MediaKey) {}
+    /// Move the pointer to an absolute position, an axis is left unchanged if it is none, sent immediately
+    fn move_absolute(&mut self, _x: Option<u16>, _y: Option<u16>) {}
+    /// Send keyboard report
+    fn send_keyboard(&mut self) {}
+    /// Send mouse report
+    fn send_mouse(&mut self) {}
+    /// Backend specific command
+    fn send_command(&mut self, _data: String) {}
+    /// Poll the backend whilst the hid controller waits for commands, returns the last led report the host sent
+    fn poll(&mut self) -> Option<u8> {
+        None
+    }
+}
+
+/// Hid backends by name
+pub type Backends = HashMap<String, Box<dyn HidBackend>>;
+
+/// Usb gadget backend, writes reports to the usb hid interfaces
+pub struct UsbBackend {
+    hid: virt_hid::HID,
+    led: Option<File>,
+    /// Whether reading the last led report failed, errors are logged once until a report is read
+    led_error: bool,
+    keyboard: key::Keyboard,
+    mouse: UsbMouse,
+    consumer: Option<File>,
+    /// Held media keys in the order they were pressed
+    media: Vec<MediaKey>,
+    nkro: Option<NkroKeyboard>,
+    tablet: Option<File>,
+    position: (u16, u16),
+}
+
+impl UsbBackend {
+    /// New, requires the mouse and keyboard interfaces. Media keys are ignored without a consumer interface,
+    /// keys are sent as nkro reports, with a fallback to the boot keyboard, if there is a boot interface and
+    /// absolute pointer positions are ignored without a tablet interface. An optional interface that fails to
+    /// open is logged and left out. Scrolling is scaled so `wheel_multiplier` steps scroll one notch, in high-resolution
+    /// units if `hi_res_scroll` is set
+    pub fn new(config: &HidConfig) -> Result<UsbBackend, HIDError> {
+        let hid = virt_hid::HID::new(&config.mouse, &config.keyboard, &config.led).map_err(|e| HIDError::IO(e))?;
+        let mouse = UsbMouse::new(&config.mouse, config.wheel_multiplier, config.hi_res_scroll)?;
+        let led = open_optional(&config.led, "led", OpenOptions::new().read(true).custom_flags(libc::O_NONBLOCK));
+        let consumer = open_optional(&config.consumer, "consumer", OpenOptions::new().write(true));
+        let tablet = open_optional(&config.tablet, "tablet", OpenOptions::new().write(true));
+        let nkro = match config.boot.as_str() {
+            "" => None,
+            boot => NkroKeyboard::new(&config.keyboard, boot)
+                .or_log("Unable to open boot keyboard interface (HID Driver), sending boot keyboard reports"),
+        };
+        Ok(UsbBackend { hid, led, led_error: false, keyboard: key::Keyboard::new(), mouse, consumer, media: vec![], nkro, tablet, position: (0, 0) })
+    }
+
+    /// Send a consumer control report, a report holds one media key so the last one pressed is sent
+    fn send_consumer(&mut self) {
+        let Some(consumer) = &mut self.consumer else {
+            return;
+        };
+        let usage = self.media.last().map(|media| media.usage()).unwrap_or(0);
+        consumer.write_all(&usage.to_le_bytes()).or_log("USB HID error (HID Driver)");
+    }
+
+    /// Send an absolute pointer report, buttons are sent with the relative mouse
+    fn send_tablet(&mut self) {
+        let Some(tablet) = &mut self.tablet else {
+            return;
+        };
+        let (x, y) = (self.position.0.to_le_bytes(), self.position.1.to_le_bytes());
+        tablet.write_all(&[0, x[0], x[1], y[0], y[1]]).or_log("USB HID error (HID Driver)");
+    }
+}
+
+impl HidBackend for UsbBackend {
+    fn hold_key(&mut self, key: char) {
+        match &mut self.nkro {
+            Some(nkro) => nkro.state().hold_key(key),
+            None => self.keyboard.hold_key(&BasicKey::Char(key, KeyOrigin::Keyboard)),
+        }
+    }
+
+    fn hold_special(&mut self, special: SpecialKey) {
+        match &mut self.nkro {
+            Some(nkro) => nkro.state().hold_special(special),
+            None => self.keyboard.hold_key(&BasicKey::Special(special)),
+        }
+    }
+
+    fn hold_modifier(&mut self, modifier: Modifier) {
+        match &mut self.nkro {
+            Some(nkro) => nkro.state().hold_modifier(modifier),
+            None => self.keyboard.hold_mod(&modifier),
+        }
+    }
+
+    fn release_key(&mut self, key: char) {
+        match &mut self.nkro {
+            Some(nkro) => nkro.state().release_key(key),
+            None => self.keyboard.release_key(&BasicKey::Char(key, KeyOrigin::Keyboard)),
+        }
+    }
+
+    fn release_special(&mut self, special: SpecialKey) {
+        match &mut self.nkro {
+            Some(nkro) => nkro.state().release_special(special),
+            None => self.keyboard.release_key(&BasicKey::Special(special)),
+        }
+    }
+
+    fn release_modifier(&mut self, modifier: Modifier) {
+        match &mut self.nkro {
+            Some(nkro) => nkro.state().release_modifier(modifier),
+            None => self.keyboard.release_mod(&modifier),
+        }
+    }
+
+    fn press_basic_str(&mut self, str: &str) {
+        match &mut self.nkro {
+            Some(nkro) => nkro.type_str(str),
+            None => self.keyboard.press_basic_string(str),
+        }
+    }
+
+    fn press_str(&mut self, layout: &str, str: &str) {
+        match &mut self.nkro {
+            Some(_) => error!("Unable to type string (HID Driver), keyboard layouts are not supported with nkro reports, {}", layout),
+            None => self.keyboard.press_string(layout, str),
+        }
+    }
+
+    fn scroll_wheel(&mut self, amount: i8) {
+        self.mouse.scroll_wheel(amount);
+    }
+
+    fn pan(&mut self, amount: i8) {
+        self.mouse.pan(amount);
+    }
+
+    fn move_mouse(&mut self, amount: i8, dir: MouseDir) {
+        self.mouse.state().move_mouse(amount, dir);
+    }
+
+    fn hold_button(&mut self, button: Button) {
+        self.mouse.state().hold_button(button);
+    }
+
+    fn release_button(&mut self, button: Button) {
+        self.mouse.state().release_button(button);
+    }
+
+    fn hold_media(&mut self, media: MediaKey) {
+        self.media.retain(|held| *held != media);
+        self.media.push(media);
+        self.send_consumer();
+    }
+
+    fn release_media(&mut self, media: MediaKey) {
+        let last = self.media.last().copied();
+        self.media.retain(|held| *held != media);
+        if self.media.last().copied() != last {
+            self.send_consumer();
+        }
+    }
+
+    fn move_absolute(&mut self, x: Option<u16>, y: Option<u16>) {
+        self.position = (x.unwrap_or(self.position.0), y.unwrap_or(self.position.1));
+        self.send_tablet();
+    }
+
+    fn send_keyboard(&mut self) {
+        match &mut self.nkro {
+            Some(nkro) => nkro.send(),
+            None => {self.keyboard.send(&mut self.hid).or_log("USB HID error (HID Driver)");},
+        }
+    }
+
+    fn send_mouse(&mut self) {
+        self.mouse.send();
+    }
+
+    fn poll(&mut self) -> Option<u8> {
+        let boot = self.nkro.as_mut().map(|nkro| nkro.poll()).unwrap_or(Ok(None));
+        let led = self.led.as_mut().map(read_leds).unwrap_or(Ok(None));
+        match led.and_then(|report| boot.map(|boot| boot.or(report))) {
+            Ok(report) => {
+                self.led_error = false;
+                report
+            },
+            Err(e) => {
+                if !self.led_error {
+                    error!("Unable to read led reports (HID Driver), {}", e);
+                }
+                self.led_error = true;
+                None
+            },
+        }
+    }
+}
+
+/// Open an optional usb hid interface, an empty path or an interface that fails to open is left out
+fn open_optional(path: &str, name: &str, options: &OpenOptions) -> Option<File> {
+    match path {
+        "" => None,
+        path => options.open(path).or_log(&format!("Unable to open {} interface (HID Driver), continuing without it", name)),
+    }
+}
+
+/// Read the led reports waiting on a non-blocking hid interface, returns the last report
+pub(crate) fn read_leds(interface: &mut File) -> io::Result<Option<u8>> {
+    let mut report = None;
+    let mut buffer = [0; 8];
+    loop {
+        match interface.read(&mut buffer) {
+            Ok(0) => return Ok(report),
+            Ok(_) => report = Some(buffer[0]),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(report),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Uinput backend, sends input events to the local machine
+pub struct UinputBackend {
+    device: Device,
+    /// Scroll steps per wheel notch
+    wheel_multiplier: i32,
+    /// Scroll steps not yet sent as a whole notch, (wheel, pan)
+    scroll: (i32, i32),
+    /// Absolute pointer, a separate device so the relative mouse is not mistaken for a tablet
+    tablet: Device,
+}
+
+impl UinputBackend {
+    /// New, scroll steps are sent as whole notches of wheel multiplier steps
+    pub fn new(wheel_multiplier: u8) -> Result<UinputBackend, HIDError> {
+        let device = uinput::default().map_err(|e| HIDError::UInput(e))?
+            .name("lmk").map_err(|e| HIDError::UInput(e))?
+            .event(event::Keyboard::All).map_err(|e| HIDError::UInput(e))?
+            .event(event::Controller::Mouse(Mouse::Left)).map_err(|e| HIDError::UInput(e))?
+            .event(event::Controller::Mouse(Mouse::Right)).map_err(|e| HIDError::UInput(e))?
+            .event(event::Controller::Mouse(Mouse::Middle)).map_err(|e| HIDError::UInput(e))?
+            .event(event::Controller::Mouse(Mouse::Side)).map_err(|e| HIDError::UInput(e))?
+            .event(event::Controller::Mouse(Mouse::Extra)).map_err(|e| HIDError::UInput(e))?
+            .event(event::Relative::Position(Position::X)).map_err(|e| HIDError::UInput(e))?
+            .event(event::Relative::Position(Position::Y)).map_err(|e| HIDError::UInput(e))?
+            .event(event::Relative::Wheel(Wheel::Vertical)).map_err(|e| HIDError::UInput(e))?
+            .event(event::Relative::Wheel(Wheel::Horizontal)).map_err(|e| HIDError::UInput(e))?
+            .create()
+            .map_err(|e| HIDError::UInput(e))?;
+        let tablet = uinput::default().map_err(|e| HIDError::UInput(e))?
+            .name("lmk-tablet").map_err(|e| HIDError::UInput(e))?
+            .event(event::Controller::Mouse(Mouse::Left)).map_err(|e| HIDError::UInput(e))?
+            .event(event::Absolute::Position(absolute::Position::X)).map_err(|e| HIDError::UInput(e))?
+            .min(0).max(ABSOLUTE_MAX as i32)
+            .event(event::Absolute::Position(absolute::Position::Y)).map_err(|e| HIDError::UInput(e))?
+            .min(0).max(ABSOLUTE_MAX as i32)
+            .create()
+            .map_err(|e| HIDError::UInput(e))?;
+        Ok(UinputBackend { device, wheel_multiplier: wheel_multiplier.max(1) as i32, scroll: (0, 0), tablet })
+    }
+
+    /// Scroll a wheel once enough steps have been scrolled for a notch
+    fn scroll(&mut self, wheel: Wheel, amount: i8) {
+        let steps = match wheel {
+            Wheel::Horizontal => &mut self.scroll.1,
+            _ => &mut self.scroll.0,
+        };
+        *steps += amount as i32;
+        let notches = *steps / self.wheel_multiplier;
+        *steps -= notches * self.wheel_multiplier;
+
+        if notches != 0 {
+            self.device.position(&event::Relative::Wheel(wheel), notches).or_log("Uinput error (HID Driver)");
+        }
+    }
+
+    /// Press a uinput key
+    fn press(&mut self, key: Option<event::Keyboard>) {
+        if let Some(key) = key {
+            self.device.press(&key).or_log("Uinput error (HID Driver)");
+        }
+    }
+
+    /// Release a uinput key
+    fn release(&mut self, key: Option<event::Keyboard>) {
+        if let Some(key) = key {
+            self.device.release(&key).or_log("Uinput error (HID Driver)");
+        }
+    }
+
+    /// Click each character of a string, uinput has no keyboard layouts
+    fn click_str(&mut self, str: &str) {
+        for key in str.chars() {
+            if requires_shift(key) {
+                self.device.press(&event::Keyboard::Key(Key::LeftShift)).or_log("Uinput error (HID Driver)");
+            }
+
+            if let Some(ukey) = char_to_uinput(key) {
+                self.device.click(&ukey).or_log("Uinput error (HID Driver)");
+            }
+
+            if requires_shift(key) {
+                self.device.release(&event::Keyboard::Key(Key::LeftShift)).or_log("Uinput error (HID Driver)");
+            }
+        }
+    }
+}
+
+impl HidBackend for UinputBackend {
+    fn hold_key(&mut self, key: char) {
+        self.press(char_to_uinput(key));
+    }
+
+    fn hold_special(&mut self, special: SpecialKey) {
+        self.press(special_to_uinput(special));
+    }
+
+    fn hold_modifier(&mut self, modifier: Modifier) {
+        self.press(mod_to_uinput(modifier));
+    }
+
+    fn release_key(&mut self, key: char) {
+        self.release(char_to_uinput(key));
+    }
+
+    fn release_special(&mut self, special: SpecialKey) {
+        self.release(special_to_uinput(special));
+    }
+
+    fn release_modifier(&mut self, modifier: Modifier) {
+        self.release(mod_to_uinput(modifier));
+    }
+
+    fn press_basic_str(&mut self, str: &str) {
+        self.click_str(str);
+    }
+
+    fn press_str(&mut self, _layout: &str, str: &str) {
+        self.click_str(str);
+    }
+
+    fn scroll_wheel(&mut self, amount: i8) {
+        self.scroll(Wheel::Vertical, amount);
+    }
+
+    fn pan(&mut self, amount: i8) {
+        self.scroll(Wheel::Horizontal, amount);
+    }
+
+    fn move_mouse(&mut self, amount: i8, dir: MouseDir) {
+        self.device.position(&event::Relative::Position(mouse_dir_to_position(dir)), amount as i32).or_log("Uinput error (HID Driver)");
+    }
+
+    fn hold_button(&mut self, button: Button) {
+        self.device.press(&event::Controller::Mouse(button_to_mouse(button))).or_log("Uinput error (HID Driver)");
+    }
+
+    fn release_button(&mut self, button: Button) {
+        self.device.release(&event::Controller::Mouse(button_to_mouse(button))).or_log("Uinput error (HID Driver)");
+    }
+
+    fn hold_media(&mut self, media: MediaKey) {
+        self.press(Some(media_to_uinput(media)));
+    }
+
+    fn release_media(&mut self, media: MediaKey) {
+        self.release(Some(media_to_uinput(media)));
+    }
+
+    fn move_absolute(&mut self, x: Option<u16>, y: Option<u16>) {
+        if let Some(x) = x {
+            self.tablet.position(&event::Absolute::Position(absolute::Position::X), x as i32).or_log("Uinput error (HID Driver)");
+        }
+        if let Some(y) = y {
+            self.tablet.position(&event::Absolute::Position(absolute::Position::Y), y as i32).or_log("Uinput error (HID Driver)");
+        }
+        self.tablet.synchronize().or_log("Uinput error (HID Driver)");
+    }
+}
+
+/// Module backend, forwards hid actions to a hid module. Hid modules have no media keys, absolute pointer,
+/// horizontal scrolling or side and extra buttons
+pub struct ModuleBackend {
+    name: String,
+    module_manager: Arc<ModuleManager>,
+    handle: Handle,
+}
+
+impl ModuleBackend {
+    /// New, must be created inside the tokio runtime
+    pub fn new(name: String, module_manager: Arc<ModuleManager>) -> ModuleBackend {
+        ModuleBackend { name, module_manager, handle: Handle::current() }
+    }
+}
+
+impl HidBackend for ModuleBackend {
+    fn hold_key(&mut self, key: char) {
+        self.handle.block_on(self.module_manager.hold_key(&self.name, key)).or_log("Unable to process hid input (HID Driver)");
+    }
+
+    fn hold_special(&mut self, special: SpecialKey) {
+        self.handle.block_on(self.module_manager.hold_special(&self.name, special)).or_log("Unable to process hid input (HID Driver)");
+    }
+
+    fn hold_modifier(&mut self, modifier: Modifier) {
+        self.handle.block_on(self.module_manager.hold_modifier(&self.name, modifier)).or_log("Unable to process hid input (HID Driver)");
+    }
+
+    fn release_key(&mut self, key: char) {
+        self.handle.block_on(self.module_manager.release_key(&self.name, key)).or_log("Unable to process hid input (HID Driver)");
+    }
+
+    fn release_special(&mut self, special: SpecialKey) {
+        self.handle.block_on(self.module_manager.release_special(&self.name, special)).or_log("Unable to process hid input (HID Driver)");
+    }
+
+    fn release_modifier(&mut self, modifier: Modifier) {
+        self.handle.block_on(self.module_manager.release_modifier(&self.name, modifier)).or_log("Unable to process hid input (HID Driver)");
+    }
+
+    fn press_basic_str(&mut self, str: &str) {
+        self.handle.block_on(self.module_manager.press_basic_str(&self.name, str.to_string())).or_log("Unable to process hid input (HID Driver)");
+    }
+
+    fn press_str(&mut self, layout: &str, str: &str) {
+        self.handle.block_on(self.module_manager.press_str(&self.name, layout.to_string(), str.to_string())).or_log("Unable to process hid input (HID Driver)");
+    }
+
+    fn scroll_wheel(&mut self, amount: i8) {
+        self.handle.block_on(self.module_manager.scroll_wheel(&self.name, amount)).or_log("Unable to process hid input (HID Driver)");
+    }
+
+    fn move_mouse(&mut self, amount: i8, dir: MouseDir) {
+        self.handle.block_on(self.module_manager.move_mouse(&self.name, amount, dir)).or_log("Unable to process hid input (HID Driver)");
+    }
+
+    fn hold_button(&mut self, button: Button) {
+        if let Some(button) = button.mouse_button() {
+            self.handle.block_on(self.module_manager.hold_button(&self.name, button)).or_log("Unable to process hid input (HID Driver)");
+        }
+    }
+
+    fn release_button(&mut self, button: Button) {
+        if let Some(button) = button.mouse_button() {
+            self.handle.block_on(self.module_manager.release_button(&self.name, button)).or_log("Unable to process hid input (HID Driver)");
+        }
+    }
+
+    fn send_keyboard(&mut self) {
+        self.handle.block_on(self.module_manager.send_keyboard(&self.name)).or_log("Unable to process hid input (HID Driver)");
+    }
+
+    fn send_mouse(&mut self) {
+        self.handle.block_on(self.module_manager.send_mouse(&self.name)).or_log("Unable to process hid input (HID Driver)");
+    }
+
+    fn send_command(&mut self, data: String) {
+        self.handle.block_on(self.module_manager.send_command(&self.name, data)).or_log("Unable to process hid input (HID Driver)");
+    }
+}
+
+/// Log backend, logs hid actions instead of sending them
+pub struct LogBackend;
+
+impl HidBackend for LogBackend {
+    fn hold_key(&mut self, key: char) {
+        info!("Hold key {:?} (HID Log)", key);
+    }
+
+    fn hold_special(&mut self, special: SpecialKey) {
+        info!("Hold special {:?} (HID Log)", special);
+    }
+
+    fn hold_modifier(&mut self, modifier: Modifier) {
+        info!("Hold modifier {:?} (HID Log)", modifier);
+    }
+
+    fn release_key(&mut self, key: char) {
+        info!("Release key {:?} (HID Log)", key);
+    }
+
+    fn release_special(&mut self, special: SpecialKey) {
+        info!("Release special {:?} (HID Log)", special);
+    }
+
+    fn release_modifier(&mut self, modifier: Modifier) {
+        info!("Release modifier {:?} (HID Log)", modifier);
+    }
+
+    fn press_basic_str(&mut self, str: &str) {
+        info!("Press string {:?} (HID Log)", str);
+    }
+
+    fn press_str(&mut self, layout: &str, str: &str) {
+        info!("Press string {:?} with layout {} (HID Log)", str, layout);
+    }
+
+    fn scroll_wheel(&mut self, amount: i8) {
+        info!("Scroll wheel {} (HID Log)", amount);
+    }
+
+    fn pan(&mut self, amount: i8) {
+        info!("Pan {} (HID Log)", amount);
+    }
+
+    fn move_mouse(&mut self, amount: i8, dir: MouseDir) {
+        info!("Move mouse {} {:?} (HID Log)", amount, dir);
+    }
+
+    fn hold_button(&mut self, button: Button) {
+        info!("Hold button {:?} (HID Log)", button);
+    }
+
+    fn release_button(&mut self, button: Button) {
+        info!("Release button {:?} (HID Log)", button);
+    }
+
+    fn hold_media(&mut self, media: MediaKey) {
+        info!("Hold media {:?} (HID Log)", media);
+    }
+
+    fn release_media(&mut self, media: MediaKey) {
+        info!("Release media {:?} (HID Log)", media);
+    }
+
+    fn move_absolute(&mut self, x: Option<u16>, y: Option<u16>) {
+        info!("Move absolute {:?} {:?} (HID Log)", x, y);
+    }
+
+    fn send_command(&mut self, data: String) {
+        info!("Command {:?} (HID Log)", data);
+    }
+}
+
+/// Media key to uinput
+fn media_to_uinput(media: MediaKey) -> event::Keyboard {
+    event::Keyboard::Misc(match media {
+        MediaKey::PlayPause => Misc::PlayPause,
+        MediaKey::NextTrack => Misc::NextSong,
+        MediaKey::PreviousTrack => Misc::PreviousSong,
+        MediaKey::Stop => Misc::StopCD,
+        MediaKey::FastForward => Misc::FastForward,
+        MediaKey::Rewind => Misc::Rewind,
+        MediaKey::Eject => Misc::EjectCD,
+        MediaKey::VolumeUp => Misc::VolumeUp,
+        MediaKey::VolumeDown => Misc::VolumeDown,
+        MediaKey::Mute => Misc::Mute,
+        MediaKey::BrightnessUp => Misc::BrightnessUp,
+        MediaKey::BrightnessDown => Misc::BrightnessDown,
+        MediaKey::Calculator => Misc::Calc,
+        MediaKey::Mail => Misc::Mail,
+        MediaKey::Browser => Misc::HomePage,
+        MediaKey::Search => Misc::Search,
+    })
+}
+
+/// Usb mouse to uinput
+fn button_to_mouse(button: Button) -> Mouse {
+    match button {
+        Button::Left => Mouse::Left,
+        Button::Right => Mouse::Right,
+        Button::Middle => Mouse::Middle,
+        Button::Side => Mouse::Side,
+        Button::Extra => Mouse::Extra,
+    }
+}
+
+/// Usb mouse to uinput
+fn mouse_dir_to_position(dir: MouseDir) -> Position {
+    match dir {
+        MouseDir::X => Position::X,
+        MouseDir::Y => Position::Y,
+    }
+}
+
+/// True if character requires shift to press
+pub(crate) fn requires_shift(key: char) -> bool {
+    match key {
+        '!' | '@' | '#' | '$' | '%' | '^' | '&' | '*' | '(' | ')' | '_' | '+' | '{' | '}' | '|' | ':' | '"' | '<' | '>' | '?' | '~' => true, 
+        _ => key.is_ascii_alphabetic() && key.is_ascii_uppercase(),
+    }
+}
+
+/// Usb keyboard to uinput
+fn mod_to_uinput(modifier: Modifier) -> Option<event::Keyboard> {
+    Some(match modifier {
+        Modifier::LeftControl => event::Keyboard::Key(Key::LeftControl),
+        Modifier::LeftShift => event::Keyboard::Key(Key::LeftShift),
+        Modifier::LeftAlt => event::Keyboard::Key(Key::LeftAlt),
+        Modifier::LeftMeta => event::Keyboard::Key(Key::LeftMeta),
+        Modifier::RightControl => event::Keyboard::Key(Key::RightControl),
+        Modifier::RightShift => event::Keyboard::Key(Key::RightShift),
+        Modifier::RightAlt => event::Keyboard::Key(Key::RightAlt),
+        Modifier::RightMeta => event::Keyboard::Key(Key::RightMeta),
+    })
+}
+
+/// Usb keyboard to uinput
+fn special_to_uinput(special: SpecialKey) -> Option<event::Keyboard> {
+    Some(match special {
+        SpecialKey::ReturnEnter => event::Keyboard::Key(Key::Enter),
+        SpecialKey::Return => event::Keyboard::Key(Key::LineFeed),
+        SpecialKey::Escape => event::Keyboard::Key(Key::Esc),
+        SpecialKey::Backspace => event::Keyboard::Key(Key::BackSpace),
+        SpecialKey::Tab => event::Keyboard::Key(Key::Tab),
+        SpecialKey::Spacebar => event::Keyboard::Key(Key::Space),
+        SpecialKey::NONUSHashAndTilda => return None,
+        SpecialKey::CapsLock => event::Keyboard::Key(Key::CapsLock),
+        SpecialKey::F1 => event::Keyboard::Key(Key::F1),
+        SpecialKey::F2 => event::Keyboard::Key(Key::F2),
+        SpecialKey::F3 => event::Keyboard::Key(Key::F3),
+        SpecialKey::UpArrow => event::Keyboard::Key(Key::Up),
+        SpecialKey::DownArrow => event::Keyboard::Key(Key::Down),
+        SpecialKey::LeftArrow => event::Keyboard::Key(Key::Left),
+        SpecialKey::RightArrow => event::Keyboard::Key(Key::Right),
+        SpecialKey::PageDown => event::Keyboard::Key(Key::PageDown),
+        SpecialKey::End => event::Keyboard::Key(Key::End),
+        SpecialKey::DeleteForward => event::Keyboard::Key(Key::Delete),
+        SpecialKey::PageUp => event::Keyboard::Key(Key::PageUp),
+        SpecialKey::Home => event::Keyboard::Key(Key::Home),
+        SpecialKey::Insert => event::Keyboard::Key(Key::Insert),
+        SpecialKey::Pause => event::Keyboard::Misc(Misc::Pause),
+        SpecialKey::ScrollLock => event::Keyboard::Key(Key::ScrollLock),
+        SpecialKey::PrintScreen => return None,
+        SpecialKey::F12 => event::Keyboard::Key(Key::F12),
+        SpecialKey::F11 => event::Keyboard::Key(Key::F11),
+        SpecialKey::F10 => event::Keyboard::Key(Key::F10),
+        SpecialKey::F9 => event::Keyboard::Key(Key::F9),
+        SpecialKey::F8 => event::Keyboard::Key(Key::F8),
+        SpecialKey::F7 => event::Keyboard::Key(Key::F7),
+        SpecialKey::F6 => event::Keyboard::Key(Key::F6),
+        SpecialKey::F5 => event::Keyboard::Key(Key::F5),
+        SpecialKey::F4 => event::Keyboard::Key(Key::F4),
+        SpecialKey::NumLockAndClear => event::Keyboard::Key(Key::NumLock),
+        SpecialKey::Enter => event::Keyboard::Key(Key::Enter),
+        SpecialKey::Application => event::Keyboard::Misc(Misc::AppSelect),
+        SpecialKey::Power => event::Keyboard::Misc(Misc::Power),
+        SpecialKey::RightGUI => event::Keyboard::Key(Key::RightMeta),
+        SpecialKey::RightAlt => event::Keyboard::Key(Key::RightAlt),
+        SpecialKey::RightShift => event::Keyboard::Key(Key::RightShift),
+        SpecialKey::RightControl => event::Keyboard::Key(Key::RightControl),
+        SpecialKey::LeftGUI => event::Keyboard::Key(Key::LeftMeta),
+        SpecialKey::LeftAlt => event::Keyboard::Key(Key::LeftAlt),
+        SpecialKey::LeftShift => event::Keyboard::Key(Key::LeftShift),
+        SpecialKey::LeftControl => event::Keyboard::Key(Key::LeftControl),
+        SpecialKey::Hexadecimal => return None,
+        SpecialKey::Decimal => return None,
+        SpecialKey::Octal => return None,
+        SpecialKey::Binary => return None,
+        SpecialKey::ClearEntry => return None,
+        SpecialKey::Clear => event::Keyboard::Misc(Misc::Clear),
+        SpecialKey::PlusMinux => event::Keyboard::KeyPad(KeyPad::PlusMinus),
+        SpecialKey::MemoryDivide => return None,
+        SpecialKey::MemoryMultiply => return None,
+        SpecialKey::MemorySubtract => return None,
+        SpecialKey::MemoryAdd => return None,
+        SpecialKey::MemoryClear => return None,
+        SpecialKey::MemoryRecall => return None,
+        SpecialKey::MemoryStore => return None,
+        SpecialKey::Space => event::Keyboard::Key(Key::Space),
+        SpecialKey::Or => return None,
+        SpecialKey::And => return None,
+        SpecialKey::XOR => return None,
+        SpecialKey::CurrencySubunit => return None,
+        SpecialKey::CurrencyUnit => return None,
+        SpecialKey::DecimalSeparator => return None,
+        SpecialKey::ThousandsSeparator => return None,
+        SpecialKey::_000 => return None,
+        SpecialKey::_00 => return None,
+        SpecialKey::ExSel => return None,
+        SpecialKey::CrSelProps => return None,
+        SpecialKey::ClearAgain => return None,
+        SpecialKey::Oper => return None,
+        SpecialKey::Out => return None,
+        SpecialKey::Separator => return None,
+        SpecialKey::Prior => return None,
+        SpecialKey::Cancel => event::Keyboard::InputAssist(InputAssist::Cancel),
+        SpecialKey::SysReqAttention1 => return None,
+        SpecialKey::AlternateErase => return None,
+        SpecialKey::LANG9 => return None,
+        SpecialKey::LANG8 => return None,
+        SpecialKey::LANG7 => return None,
+        SpecialKey::LANG6 => return None,
+        SpecialKey::LANG5 => return None,
+        SpecialKey::LANG4 => return None,
+        SpecialKey::LANG3 => return None,
+        SpecialKey::LANG2 => return None,
+        SpecialKey::LANG1 => return None,
+        SpecialKey::International9 => return None,
+        SpecialKey::International8 => return None,
+        SpecialKey::International7 => return None,
+        SpecialKey::International6 => return None,
+        SpecialKey::International5 => return None,
+        SpecialKey::International4 => return None,
+        SpecialKey::International3 => return None,
+        SpecialKey::International2 => return None,
+        SpecialKey::International1 => return None,
+        SpecialKey::LockingScrollLock => return None,
+        SpecialKey::LockingNumLock => return None,
+        SpecialKey::LockingCapsLock => return None,
+        SpecialKey::VolumeDown => event::Keyboard::Misc(Misc::VolumeDown),
+        SpecialKey::VolumeUp => event::Keyboard::Misc(Misc::VolumeUp),
+        SpecialKey::Mute => event::Keyboard::Misc(Misc::Mute),
+        SpecialKey::Find => event::Keyboard::Misc(Misc::Find),
+        SpecialKey::Paste => event::Keyboard::Misc(Misc::Paste),
+        SpecialKey::Copy => event::Keyboard::Misc(Misc::Copy),
+        SpecialKey::Cut => event::Keyboard::Misc(Misc::Cut),
+        SpecialKey::Undo => event::Keyboard::Misc(Misc::Undo),
+        SpecialKey::Again => event::Keyboard::Misc(Misc::Again),
+        SpecialKey::Stop => event::Keyboard::Misc(Misc::Stop),
+        SpecialKey::Select => event::Keyboard::Misc(Misc::Select),
+        SpecialKey::Menu => event::Keyboard::Misc(Misc::Menu),
+        SpecialKey::Help => event::Keyboard::Misc(Misc::Help),
+        SpecialKey::Execute => return None,
+        SpecialKey::F24 => event::Keyboard::Key(Key::F24),
+        SpecialKey::F23 => event::Keyboard::Key(Key::F23),
+        SpecialKey::F22 => event::Keyboard::Key(Key::F22),
+        SpecialKey::F21 => event::Keyboard::Key(Key::F21),
+        SpecialKey::F20 => event::Keyboard::Key(Key::F20),
+        SpecialKey::F19 => event::Keyboard::Key(Key::F19),
+        SpecialKey::F18 => event::Keyboard::Key(Key::F18),
+        SpecialKey::F17 => event::Keyboard::Key(Key::F17),
+        SpecialKey::F16 => event::Keyboard::Key(Key::F16),
+        SpecialKey::F15 => event::Keyboard::Key(Key::F15),
+        SpecialKey::F14 => event::Keyboard::Key(Key::F14),
+        SpecialKey::F13 => event::Keyboard::Key(Key::F13),
+        SpecialKey::NonUSSlashAndPipe => return  None,
+        SpecialKey::_DotAndDelete => event::Keyboard::KeyPad(KeyPad::Dot),
+        SpecialKey::_0AndInsert => event::Keyboard::KeyPad(KeyPad::_0),
+        SpecialKey::_9AndPageUp => event::Keyboard::KeyPad(KeyPad::_9),
+        SpecialKey::_8AndUpArrow => event::Keyboard::KeyPad(KeyPad::_8),
+        SpecialKey::_7AndHome => event::Keyboard::KeyPad(KeyPad::_7),
+        SpecialKey::_6AndRightArrow => event::Keyboard::KeyPad(KeyPad::_6),
+        SpecialKey::_5 => event::Keyboard::KeyPad(KeyPad::_5),
+        SpecialKey::_4AndLeftArrow => event::Keyboard::KeyPad(KeyPad::_4),
+        SpecialKey::_3AndPageDn => event::Keyboard::KeyPad(KeyPad::_3),
+        SpecialKey::_2AndDownArrow => event::Keyboard::KeyPad(KeyPad::_2),
+        SpecialKey::_1AndEnd => event::Keyboard::KeyPad(KeyPad::_1),
+        SpecialKey::PadClear => return None,
+        SpecialKey::PadBackspace => return None,
+        SpecialKey::PadTab => return None,
+        SpecialKey::EqualsSign => return None,
+        SpecialKey::Comma => event::Keyboard::KeyPad(KeyPad::Comma),
+    })
+} 
+
+/// Usb keyboard to uinput
+fn char_to_uinput(key: char) -> Option<event::Keyboard> {
+    Some(match key.to_ascii_lowercase() {
+		'1' | '!' => event::Keyboard::Key(Key::_1),
+		'2' | '@' => event::Keyboard::Key(Key::_2),
+		'3' | '#' => event::Keyboard::Key(Key::_3),
+		'4' | '$' => event::Keyboard::Key(Key::_4),
+		'5' | '%' => event::Keyboard::Key(Key::_5),
+		'6' | '^' => event::Keyboard::Key(Key::_6),
+		'7' | '&' => event::Keyboard::Key(Key::_7),
+		'8' | '*' => event::Keyboard::Key(Key::_8),
+		'9' | '(' => event::Keyboard::Key(Key::_9),
+		'0' | ')' => event::Keyboard::Key(Key::_0),
+		'-' | '_' => event::Keyboard::Key(Key::Minus),
+		'=' | '+' => event::Keyboard::Key(Key::Equal),
+		'q' => event::Keyboard::Key(Key::Q),
+		'w' => event::Keyboard::Key(Key::W),
+		'e' => event::Keyboard::Key(Key::E),
+		'r' => event::Keyboard::Key(Key::R),
+		't' => event::Keyboard::Key(Key::T),
+		'y' => event::Keyboard::Key(Key::Y),
+		'u' => event::Keyboard::Key(Key::U),
+		'i' => event::Keyboard::Key(Key::I),
+		'o' => event::Keyboard::Key(Key::O),
+		'p' => event::Keyboard::Key(Key::P),
+		'{' | '[' => event::Keyboard::Key(Key::LeftBrace),
+		'}' | ']' => event::Keyboard::Key(Key::RightBrace),
+		'a' => event::Keyboard::Key(Key::A),
+		's' => event::Keyboard::Key(Key::S),
+		'd' => event::Keyboard::Key(Key::D),
+		'f' => event::Keyboard::Key(Key::F),
+		'g' => event::Keyboard::Key(Key::G),
+		'h' => event::Keyboard::Key(Key::H),
+		'j' => event::Keyboard::Key(Key::J),
+		'k' => event::Keyboard::Key(Key::K),
+		'l' => event::Keyboard::Key(Key::L),
+		';' | ':' => event::Keyboard::Key(Key::SemiColon),
+		'\'' | '"' => event::Keyboard::Key(Key::Apostrophe),
+		'~' | '`'  => event::Keyboard::Key(Key::Grave),
+		'\\' | '|' => event::Keyboard::Key(Key::BackSlash),
+		'z' => event::Keyboard::Key(Key::Z),
+		'x' => event::Keyboard::Key(Key::X),
+		'c' => event::Keyboard::Key(Key::C),
+		'v' => event::Keyboard::Key(Key::V),
+		'b' => event::Keyboard::Key(Key::B),
+		'n' => event::Keyboard::Key(Key::N),
+		'm' => event::Keyboard::Key(Key::M),
+		',' | '<' => event::Keyboard::Key(Key::Comma),
+		'.' | '>' => event::Keyboard::Key(Key::Dot),
+		'/' | '?' => event::Keyboard::Key(Key::Slash),
+		' ' => event::Keyboard::Key(Key::Space),
+        _ => return None,
+    })
+}
\ No newline at end of file
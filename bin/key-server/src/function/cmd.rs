@@ -7,6 +7,7 @@ use crate::{OrLog, variables::Variable, frontend::{FrontendConfiguration, Fronte
 
 use super::{Function, FunctionInterface, ReturnCommand, FunctionType, State, StateHelpers};
 
+#[derive(Default)]
 /// Command Pool, reaps spawn children
 pub struct CommandPool {
     commands: Arc<RwLock<Vec<Child>>>,
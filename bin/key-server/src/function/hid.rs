@@ -1,14 +1,14 @@
-use std::{sync::Arc, io, fmt::Display};
+use std::{sync::Arc, io, fmt::Display, time::{Duration, Instant}};
 
 use async_trait::async_trait;
+use key_rpc::Event;
 use log::error;
 use tokio::{sync::{RwLock, mpsc::{UnboundedSender, self}, oneshot}, runtime::Handle};
-use uinput::{event::{self, controller::Mouse, relative::{Position, Wheel}, keyboard::{Key, Misc, KeyPad, InputAssist}}, Device};
-use virt_hid::{key::{self, BasicKey, KeyOrigin, SpecialKey, Modifier}, mouse::{self, MouseDir, MouseButton}};
+use virt_hid::{key::{SpecialKey, Modifier}, mouse::MouseDir};
 
-use crate::{OrLogIgnore, OrLog, modules::ModuleManager, variables::Variable, frontend::{FrontendConfig, FrontendConfigData, FrontendConfiguration}};
+use crate::{OrLogIgnore, OrLog, modules::ModuleManager, driver::led::Leds, variables::Variable, frontend::{FrontendConfig, FrontendConfigData, FrontendConfiguration}, events::Events};
 
-use super::{Function, FunctionInterface, ReturnCommand, FunctionType, State, StateHelpers};
+use super::{Function, FunctionInterface, ReturnCommand, FunctionType, State, StateHelpers, macros::MacroAction, mouse::Button, keyboard::MediaKey, backend::{HidBackend, Backends, UsbBackend, UinputBackend, LogBackend, ModuleBackend}};
 
 #[derive(Debug)]
 /// HID Error
@@ -19,8 +19,6 @@ pub enum HIDError {
     IO(io::Error),
     /// No configuration for HID
     NoConfig,
-    /// Message passing error
-    ChannelError,
 }
 
 impl Display for HIDError {
@@ -29,7 +27,6 @@ impl Display for HIDError {
             HIDError::UInput(e) => f.write_fmt(format_args!("UInput error, {}", e)),
             HIDError::IO(e) => f.write_fmt(format_args!("IO error, {}", e)),
             HIDError::NoConfig => f.write_str("No configuration was supplied"),
-            HIDError::ChannelError => f.write_str("Channel Error"),
         }
     }
 }
@@ -139,8 +136,10 @@ impl FunctionInterface for ToggleHid {
     }
 }
 
-#[derive(Debug)]
-/// Hid command for wrapped usb hid and uinput controller
+/// Time between polls of the hid backends whilst no commands arrive
+const POLL_PERIOD: Duration = Duration::from_millis(2);
+
+/// Hid command for the hid backends
 enum Command {
     HoldKey(char),
     HoldSpecial(SpecialKey),
@@ -151,21 +150,218 @@ enum Command {
     PressBasicStr(String),
     PressStr(String, String),
     ScrollWheel(i8),
+    Pan(i8),
     MoveMouse(i8, MouseDir),
-    HoldButton(MouseButton),
-    ReleaseButton(MouseButton),
+    HoldButton(Button),
+    ReleaseButton(Button),
+    HoldMedia(MediaKey),
+    ReleaseMedia(MediaKey),
+    MoveAbsolute(Option<u16>, Option<u16>),
     SendCommand(String,String),
     SendKeyboard,
     SendMouse,
     Switch(String),
+    /// Register a backend by name
+    Register(String, Box<dyn HidBackend>),
+    StartRecording,
+    StopRecording(oneshot::Sender<Vec<MacroAction>>),
+}
+
+impl Command {
+    /// Macro action that records the command, if it can be recorded
+    fn to_macro_action(&self) -> Option<MacroAction> {
+        Some(match self {
+            Command::HoldKey(key) => MacroAction::HoldKey(*key),
+            Command::HoldSpecial(special) => MacroAction::HoldSpecial(*special),
+            Command::HoldModifier(modifier) => MacroAction::HoldModifier(*modifier),
+            Command::ReleaseKey(key) => MacroAction::ReleaseKey(*key),
+            Command::ReleaseSpecial(special) => MacroAction::ReleaseSpecial(*special),
+            Command::ReleaseModifier(modifier) => MacroAction::ReleaseModifier(*modifier),
+            Command::PressBasicStr(str) => MacroAction::PressBasicStr(str.clone()),
+            Command::PressStr(layout, str) => MacroAction::PressStr(layout.clone(), str.clone()),
+            Command::ScrollWheel(amount) => MacroAction::ScrollWheel(*amount),
+            Command::Pan(amount) => MacroAction::Pan(*amount),
+            Command::MoveMouse(amount, dir) => MacroAction::MoveMouse(*amount, dir.clone()),
+            Command::HoldButton(button) => MacroAction::HoldButton(*button),
+            Command::ReleaseButton(button) => MacroAction::ReleaseButton(*button),
+            Command::HoldMedia(media) => MacroAction::HoldMedia(*media),
+            Command::ReleaseMedia(media) => MacroAction::ReleaseMedia(*media),
+            Command::MoveAbsolute(x, y) => MacroAction::MoveAbsolute(*x, *y),
+            Command::SendKeyboard => MacroAction::SendKeyboard,
+            Command::SendMouse => MacroAction::SendMouse,
+            Command::SendCommand(_, _) 
+            | Command::Switch(_) 
+            | Command::Register(_, _)
+            | Command::StartRecording 
+            | Command::StopRecording(_) => return None,
+        })
+    }
+}
+
+impl Command {
+    /// Command that plays back a macro action, delays are not commands
+    fn from_macro_action(action: MacroAction) -> Option<Command> {
+        Some(match action {
+            MacroAction::HoldKey(key) => Command::HoldKey(key),
+            MacroAction::HoldSpecial(special) => Command::HoldSpecial(special),
+            MacroAction::HoldModifier(modifier) => Command::HoldModifier(modifier),
+            MacroAction::ReleaseKey(key) => Command::ReleaseKey(key),
+            MacroAction::ReleaseSpecial(special) => Command::ReleaseSpecial(special),
+            MacroAction::ReleaseModifier(modifier) => Command::ReleaseModifier(modifier),
+            MacroAction::PressBasicStr(str) => Command::PressBasicStr(str),
+            MacroAction::PressStr(layout, str) => Command::PressStr(layout, str),
+            MacroAction::ScrollWheel(amount) => Command::ScrollWheel(amount),
+            MacroAction::Pan(amount) => Command::Pan(amount),
+            MacroAction::MoveMouse(amount, dir) => Command::MoveMouse(amount, dir),
+            MacroAction::HoldButton(button) => Command::HoldButton(button),
+            MacroAction::ReleaseButton(button) => Command::ReleaseButton(button),
+            MacroAction::HoldMedia(media) => Command::HoldMedia(media),
+            MacroAction::ReleaseMedia(media) => Command::ReleaseMedia(media),
+            MacroAction::MoveAbsolute(x, y) => Command::MoveAbsolute(x, y),
+            MacroAction::SendKeyboard => Command::SendKeyboard,
+            MacroAction::SendMouse => Command::SendMouse,
+            MacroAction::Delay(_) => return None,
+        })
+    }
+}
+
+/// Apply a hid action to a backend, commands that are not hid actions are ignored
+fn apply(backend: &mut dyn HidBackend, command: &Command) {
+    match command {
+        Command::HoldKey(key) => backend.hold_key(*key),
+        Command::HoldSpecial(special) => backend.hold_special(*special),
+        Command::HoldModifier(modifier) => backend.hold_modifier(*modifier),
+        Command::ReleaseKey(key) => backend.release_key(*key),
+        Command::ReleaseSpecial(special) => backend.release_special(*special),
+        Command::ReleaseModifier(modifier) => backend.release_modifier(*modifier),
+        Command::PressBasicStr(str) => backend.press_basic_str(str),
+        Command::PressStr(layout, str) => backend.press_str(layout, str),
+        Command::ScrollWheel(amount) => backend.scroll_wheel(*amount),
+        Command::Pan(amount) => backend.pan(*amount),
+        Command::MoveMouse(amount, dir) => backend.move_mouse(*amount, dir.clone()),
+        Command::HoldButton(button) => backend.hold_button(*button),
+        Command::ReleaseButton(button) => backend.release_button(*button),
+        Command::HoldMedia(media) => backend.hold_media(*media),
+        Command::ReleaseMedia(media) => backend.release_media(*media),
+        Command::MoveAbsolute(x, y) => backend.move_absolute(*x, *y),
+        Command::SendKeyboard => backend.send_keyboard(),
+        Command::SendMouse => backend.send_mouse(),
+        _ => (),
+    }
+}
+
+/// Default scroll steps per wheel notch, used when the hid config does not set it
+pub fn default_wheel_multiplier() -> u8 {
+    1
+}
+
+/// Find a backend by name, hid modules are registered the first time they are used
+fn find_backend<'a>(backends: &'a mut Backends, name: &str, module_manager: &Arc<ModuleManager>) -> Option<&'a mut Box<dyn HidBackend>> {
+    if !backends.contains_key(name) && module_manager.is_hid(name) {
+        backends.insert(name.to_string(), Box::new(ModuleBackend::new(name.to_string(), module_manager.clone())));
+    }
+    backends.get_mut(name)
+}
+
+/// Apply hid commands to the backends until every handle to the hid controller is dropped
+fn run(mut rx: mpsc::UnboundedReceiver<Command>, mut cur_hid: String, mirror: Vec<String>, module_manager: Arc<ModuleManager>, events: Events, leds: Leds) {
+    let mut backends = Backends::new();
+    let mut recording: Option<(Vec<MacroAction>, Instant)> = None;
+    let handle = Handle::current();
+
+    loop {
+        for backend in backends.values_mut() {
+            if let Some(report) = backend.poll() {
+                leds.set(report);
+            }
+        }
+
+        // Backends are polled whilst waiting for commands, the loop ends once the controller is dropped
+        let command = match handle.block_on(tokio::time::timeout(POLL_PERIOD, rx.recv())) {
+            Ok(Some(command)) => command,
+            Ok(None) => break,
+            Err(_) => continue,
+        };
+
+        if let Some((actions, last)) = &mut recording {
+            if let Some(action) = command.to_macro_action() {
+                let delay = last.elapsed().as_millis() as u64;
+                if delay > 0 && !actions.is_empty() {
+                    actions.push(MacroAction::Delay(delay));
+                }
+                actions.push(action);
+                *last = Instant::now();
+            }
+        }
+
+        match command {
+            Command::Switch(name) => if find_backend(&mut backends, &name, &module_manager).is_some() {
+                cur_hid = name;
+                events.send(Event::HidMode(cur_hid.clone()));
+            } else {
+                error!("Could not switch to hid (HID Driver), Unable to find hid backend, {}", name)
+            },
+            Command::SendCommand(name, data) => match find_backend(&mut backends, &name, &module_manager) {
+                Some(backend) => backend.send_command(data),
+                None => error!("Could not send hid command (HID Driver), Unable to find hid backend, {}", name),
+            },
+            Command::Register(name, backend) => {
+                backends.insert(name, backend);
+            },
+            Command::StartRecording => recording = Some((vec![], Instant::now())),
+            Command::StopRecording(tx) => {
+                let actions = recording.take().map(|(actions, _)| actions).unwrap_or_default();
+                tx.send(actions).or_log_ignore("Broken Channel (HID Driver)");
+            },
+            command => for name in std::iter::once(&cur_hid).chain(mirror.iter().filter(|name| **name != cur_hid)) {
+                match find_backend(&mut backends, name, &module_manager) {
+                    Some(backend) => apply(backend.as_mut(), &command),
+                    None => error!("Unable to process hid input (HID Driver), Unable to find hid backend, {}", name),
+                }
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// HID configuration, the usb hid interfaces, scrolling and mirrored hids
+pub struct HidConfig {
+    pub mouse: String,
+    pub keyboard: String,
+    pub led: String,
+    /// Media keys are not sent over usb if empty
+    pub consumer: String,
+    /// Keys are sent as nkro reports if not empty
+    pub boot: String,
+    /// Absolute pointer positions are not sent over usb if empty
+    pub tablet: String,
+    /// Scroll steps per wheel notch
+    pub wheel_multiplier: u8,
+    /// Scroll in high-resolution units over usb, for hosts that enable the wheel resolution multiplier
+    pub hi_res_scroll: bool,
+    /// Hids that also receive every hid action
+    pub mirror: Vec<String>,
+}
+
+/// Start the built in backends, a backend that fails to start is logged and left out
+async fn builtin_backends(config: HidConfig) -> Vec<(String, Box<dyn HidBackend>)> {
+    tokio::task::spawn_blocking(move || {
+        let mut backends: Vec<(String, Box<dyn HidBackend>)> = vec![];
+        if let Some(usb) = UsbBackend::new(&config).or_log("Unable to start usb hid (HID Driver)") {
+            backends.push(("usb".to_string(), Box::new(usb)));
+        }
+        if let Some(uinput) = UinputBackend::new(config.wheel_multiplier).or_log("Unable to start uinput hid (HID Driver)") {
+            backends.push(("uinput".to_string(), Box::new(uinput)));
+        }
+        backends.push(("log".to_string(), Box::new(LogBackend)));
+        backends
+    }).await.or_log("Unable to start hid backends (HID Driver)").unwrap_or_default()
 }
 
 /// HID controller
 pub struct HID {
     tx: UnboundedSender<Command>,
-    led: String, 
-    mouse: String, 
-    keyboard: String,
+    config: HidConfig,
 }
 
 #[async_trait]
@@ -175,249 +371,39 @@ impl FrontendConfig for HID {
     type Error = HIDError;
 
     fn to_config_data(&self) -> FrontendConfigData {
-        FrontendConfigData::HID{mouse: self.mouse.clone(), keyboard: self.keyboard.clone(), led: self.led.clone()}
+        let config = self.config.clone();
+        FrontendConfigData::HID{mouse: config.mouse, keyboard: config.keyboard, led: config.led, consumer: config.consumer, boot: config.boot, tablet: config.tablet, wheel_multiplier: config.wheel_multiplier, hi_res_scroll: config.hi_res_scroll, mirror: config.mirror}
     }
 
     async fn from_config(function_config: &FrontendConfiguration) -> Result<Self::Output, Self::Error> {
-        let Some(FrontendConfigData::HID { mouse, keyboard, led }) = function_config
-            .get(|config| matches!(config, FrontendConfigData::HID { mouse: _, keyboard: _, led: _})) else {
+        let Some(FrontendConfigData::HID { mouse, keyboard, led, consumer, boot, tablet, wheel_multiplier, hi_res_scroll, mirror }) = function_config
+            .get(|config| matches!(config, FrontendConfigData::HID { .. })) else {
                 return Err(HIDError::NoConfig)
         };
-        HID::new(mouse.clone(), keyboard.clone(), led.clone(), function_config.module_manager.clone()).await
+        let config = HidConfig { mouse: mouse.clone(), keyboard: keyboard.clone(), led: led.clone(), consumer: consumer.clone(), boot: boot.clone(), tablet: tablet.clone(), wheel_multiplier: *wheel_multiplier, hi_res_scroll: *hi_res_scroll, mirror: mirror.clone() };
+
+        let hid = HID::new(config.clone(), function_config.module_manager.clone(), function_config.events.clone(), function_config.leds.clone());
+        for (name, backend) in builtin_backends(config).await {
+            hid.read().await.register(&name, backend);
+        }
+        Ok(hid)
     }
 }
 
 impl HID {
-    /// New, requires path to usb hid interfaces
-    pub async fn new(mouse: String, keyboard: String, led: String, module_manager: Arc<ModuleManager>) -> Result<Arc<RwLock<HID>>, HIDError> {
-        let (tx, mut rx) = mpsc::unbounded_channel();        
-        let (new_tx, new_rx) = oneshot::channel();    
-
-        let mse = mouse.clone();
-        let kbd = keyboard.clone();
-        let ld = led.clone();
-        tokio::task::spawn_blocking(move || {
-            let mut hid = match virt_hid::HID::new(&mse, &kbd, &ld){
-                Ok(hid) => hid,
-                Err(e) => {new_tx.send(Err(HIDError::IO(e))).or_log_ignore("Broken Channel (HID Driver)"); return;}
-            };
-
-            let mut uinput = match (|| -> Result<Device, HIDError>{
-                uinput::default().map_err(|e| HIDError::UInput(e))?
-                    .name("lmk").map_err(|e| HIDError::UInput(e))?
-                    .event(event::Keyboard::All).map_err(|e| HIDError::UInput(e))?
-                    .event(event::Controller::Mouse(Mouse::Left)).map_err(|e| HIDError::UInput(e))?
-                    .event(event::Controller::Mouse(Mouse::Right)).map_err(|e| HIDError::UInput(e))?
-                    .event(event::Controller::Mouse(Mouse::Middle)).map_err(|e| HIDError::UInput(e))?
-                    .event(event::Relative::Position(Position::X)).map_err(|e| HIDError::UInput(e))?
-                    .event(event::Relative::Position(Position::Y)).map_err(|e| HIDError::UInput(e))?
-                    .event(event::Relative::Wheel(Wheel::Vertical)).map_err(|e| HIDError::UInput(e))?
-                    .create()
-                    .map_err(|e| HIDError::UInput(e))
-            })() {
-                Ok(uinput) => uinput,
-                Err(e) => {new_tx.send(Err(e)).or_log_ignore("Broken Channel (HID Driver)"); return;}
-            };
-            new_tx.send(Ok(())).or_log_ignore("Broken Channel (HID Driver)");
-
-            let mut keyboard = key::Keyboard::new(); 
-            let mut mouse = mouse::Mouse::new();
-            let mut cur_hid = "usb".to_owned();
-
-            while let Some(command) = rx.blocking_recv() {
-                match command {
-                    Command::HoldKey(key) => match cur_hid.as_ref() { 
-                        "usb" => {
-                            keyboard.hold_key(&BasicKey::Char(key, KeyOrigin::Keyboard));
-                        } 
-                        "uinput" => {
-                            let Some(key) = char_to_uinput(key) else {
-                                continue;
-                            };
-                            uinput.press(&key).or_log("Uinput error (HID Driver)");
-                        }
-                        _ => {Handle::current().block_on(module_manager.hold_key(&cur_hid, key)).or_log("Unable to process hid input (HID Driver)");}
-                    },
-                    Command::HoldSpecial(special) => match cur_hid.as_ref() { 
-                        "usb" => {
-                            keyboard.hold_key(&BasicKey::Special(special));
-                        } 
-                        "uinput" => {
-                            let Some(key) = special_to_uinput(special) else {
-                                continue;
-                            };
-                            uinput.press(&key).or_log("Uinput error (HID Driver)");
-                        }
-                        _ => {Handle::current().block_on(module_manager.hold_special(&cur_hid, special)).or_log("Unable to process hid input (HID Driver)");}
-                    },
-                    Command::HoldModifier(modifier) => match cur_hid.as_ref() { 
-                        "usb" => {
-                            keyboard.hold_mod(&modifier);
-                        } 
-                        "uinput" => {
-                            let Some(key) = mod_to_uinput(modifier) else {
-                                continue;
-                            };
-                            uinput.press(&key).or_log("Uinput error (HID Driver)");
-                        }
-                        _ => {Handle::current().block_on(module_manager.hold_modifier(&cur_hid, modifier)).or_log("Unable to process hid input (HID Driver)");}
-                    },
-                    Command::ReleaseKey(key) => match cur_hid.as_ref() { 
-                        "usb" => {
-                            keyboard.release_key(&BasicKey::Char(key, KeyOrigin::Keyboard));
-                        } 
-                        "uinput" => {
-                            let Some(key) = char_to_uinput(key) else {
-                                continue;
-                            };
-                            uinput.release(&key).or_log("Uinput error (HID Driver)");
-                        }
-                        _ => {Handle::current().block_on(module_manager.release_key(&cur_hid, key)).or_log("Unable to process hid input (HID Driver)");}
-                    },
-                    Command::ReleaseSpecial(special) => match cur_hid.as_ref() { 
-                        "usb" => {
-                            keyboard.release_key(&BasicKey::Special(special));
-                        } 
-                        "uinput" => {
-                            let Some(key) = special_to_uinput(special) else {
-                                continue;
-                            };
-                            uinput.release(&key).or_log("Uinput error (HID Driver)");
-                        }
-                        _ => {Handle::current().block_on(module_manager.release_special(&cur_hid, special)).or_log("Unable to process hid input (HID Driver)");}
-                    },
-                    Command::ReleaseModifier(modifier) => match cur_hid.as_ref() { 
-                        "usb" => {
-                            keyboard.release_mod(&modifier);
-                        } 
-                        "uinput" => {
-                            let Some(key) = mod_to_uinput(modifier) else {
-                                continue;
-                            };
-                            uinput.release(&key).or_log("Uinput error (HID Driver)");
-                        }
-                        _ => {Handle::current().block_on(module_manager.release_modifier(&cur_hid, modifier)).or_log("Unable to process hid input (HID Driver)");}
-                    },
-                    Command::PressBasicStr(str) => match cur_hid.as_ref() { 
-                        "usb" => {
-                            keyboard.press_basic_string(&str);
-                        } 
-                        "uinput" => {
-                            for key in str.chars() {
-                                if requires_shift(key) {
-                                    uinput.press(&event::Keyboard::Key(Key::LeftShift)).or_log("Uinput error (HID Driver)");
-                                }
-                
-                                let Some(ukey) = char_to_uinput(key) else {
-                                    continue;
-                                };
-                                uinput.click(&ukey).or_log("Uinput error (HID Driver)");
-                
-                                if requires_shift(key) {
-                                    uinput.release(&event::Keyboard::Key(Key::LeftShift)).or_log("Uinput error (HID Driver)");
-                                }
-                            }
-                        }
-                        _ => {Handle::current().block_on(module_manager.press_basic_str(&cur_hid, str)).or_log("Unable to process hid input (HID Driver)");}
-                    },
-                    Command::PressStr(layout, str) => match cur_hid.as_ref() { 
-                        "usb" => {
-                            keyboard.press_string(&layout, &str);
-                        } 
-                        "uinput" => {
-                            for key in str.chars() {
-                                if requires_shift(key) {
-                                    uinput.press(&event::Keyboard::Key(Key::LeftShift)).or_log("Uinput error (HID Driver)");
-                                }
-                
-                                let Some(ukey) = char_to_uinput(key) else {
-                                    continue;
-                                };
-                                uinput.click(&ukey).or_log("Uinput error (HID Driver)");
-                
-                                if requires_shift(key) {
-                                    uinput.release(&event::Keyboard::Key(Key::LeftShift)).or_log("Uinput error (HID Driver)");
-                                }
-                            }
-                        }
-                        _ => {Handle::current().block_on(module_manager.press_str(&cur_hid, layout, str)).or_log("Unable to process hid input (HID Driver)");}
-                    }
-                    Command::ScrollWheel(amount) => match cur_hid.as_ref() { 
-                        "usb" => {
-                            mouse.scroll_wheel(&amount);
-                        } 
-                        "uinput" => {
-                            uinput.position(&event::Relative::Wheel(Wheel::Vertical), amount as i32).or_log("Uinput error (HID Driver)");
-                        }
-                        _ => {Handle::current().block_on(module_manager.scroll_wheel(&cur_hid, amount)).or_log("Unable to process hid input (HID Driver)");}
-                    },
-                    Command::MoveMouse(amount, dir) => match cur_hid.as_ref() { 
-                        "usb" => {
-                            mouse.move_mouse(&amount, &dir);
-                        } 
-                        "uinput" => {
-                            let dir = mouse_dir_to_position(dir);
-                            uinput.position(&event::Relative::Position(dir), amount as i32).or_log("Uinput error (HID Driver)");
-                        }
-                        _ => {Handle::current().block_on(module_manager.move_mouse(&cur_hid, amount, dir)).or_log("Unable to process hid input (HID Driver)");}
-                    },
-                    Command::HoldButton(button) => match cur_hid.as_ref() { 
-                        "usb" => {
-                            mouse.hold_button(&button);
-                        } 
-                        "uinput" => {
-                            let button = mouse_button_to_mouse(button);
-                            uinput.press(&event::Controller::Mouse(button)).or_log("Uinput error (HID Driver)");
-                        }
-                        _ => {Handle::current().block_on(module_manager.hold_button(&cur_hid, button)).or_log("Unable to process hid input (HID Driver)");}
-                    },
-                    Command::ReleaseButton(button) => match cur_hid.as_ref() { 
-                        "usb" => {
-                            mouse.release_button(&button);
-                        } 
-                        "uinput" => {
-                            let button = mouse_button_to_mouse(button);
-                            uinput.release(&event::Controller::Mouse(button)).or_log("Uinput error (HID Driver)");
-                        }
-                        _ => {Handle::current().block_on(module_manager.release_button(&cur_hid, button)).or_log("Unable to process hid input (HID Driver)");}
-                    },
-                    Command::SendKeyboard => match cur_hid.as_ref() { 
-                        "usb" => {
-                            keyboard.send(&mut hid).or_log("USB HID error (HID Driver)");
-                        }
-                        "uinput" => (),
-                        _ => {Handle::current().block_on(module_manager.send_keyboard(&cur_hid)).or_log("Unable to process hid input (HID Driver)");}
-                    },
-                    Command::SendMouse => match cur_hid.as_ref() { 
-                        "usb" => {
-                            mouse.send(&mut hid).or_log("USB HID error (HID Driver)");
-                        }
-                        "uinput" => (),
-                        _ => {Handle::current().block_on(module_manager.send_mouse(&cur_hid)).or_log("Unable to process hid input (HID Driver)");}
-                    },
-                    Command::Switch(name) => match name.as_ref() {
-                        "usb" => cur_hid = name,
-                        "uinput" => cur_hid = name,
-                        _ => if module_manager.is_hid(&name) {
-                            cur_hid = name;
-                        } else {
-                            error!("Could not switch to hid (HID Driver), Unable to find hid module, {}", name)
-                        },
-                    },
-                    Command::SendCommand(name, data) => match name.as_ref() {
-                        "usb" => (),
-                        "uinput" => (),
-                        _ => {Handle::current().block_on(module_manager.send_command(&name, data)).or_log("Unable to process hid input (HID Driver)");},
-                    },
-                }
-            }
-        });
+    /// New, hid actions are sent to the "usb" backend until switched and to the mirrored hids.
+    /// Backends are registered by name, hid modules are registered the first time they are used.
+    /// Led reports read from the backends update the host led state
+    pub fn new(config: HidConfig, module_manager: Arc<ModuleManager>, events: Events, leds: Leds) -> Arc<RwLock<HID>> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mirror = config.mirror.clone();
+        tokio::task::spawn_blocking(move || run(rx, "usb".to_owned(), mirror, module_manager, events, leds));
+        Arc::new(RwLock::new(HID { tx, config }))
+    }
 
-        
-        match new_rx.await {
-            Ok(res) => res.map(|_| Arc::new(RwLock::new(HID { tx, mouse, keyboard, led }))),
-            Err(_) => Err(HIDError::ChannelError)
-        }
+    /// Register a backend by name, replacing the backend registered with the same name
+    pub fn register(&self, name: &str, backend: Box<dyn HidBackend>) {
+        self.tx.send(Command::Register(name.to_string(), backend)).or_log_ignore("Broken Channel (HID Driver)");
     }
 
     /// Hold key
@@ -465,21 +451,41 @@ impl HID {
         self.tx.send(Command::ScrollWheel(amount)).or_log_ignore("Broken Channel (HID Driver)");
     }
 
+    /// Scroll horizontally
+    pub async fn pan(&self, amount: i8) {
+        self.tx.send(Command::Pan(amount)).or_log_ignore("Broken Channel (HID Driver)");
+    }
+
     /// Move mouse
     pub async fn move_mouse(&self, amount: i8, dir: MouseDir) {
         self.tx.send(Command::MoveMouse(amount, dir)).or_log_ignore("Broken Channel (HID Driver)");
     }
 
     /// Hold button
-    pub async fn hold_button(&self, button: MouseButton) {
+    pub async fn hold_button(&self, button: Button) {
         self.tx.send(Command::HoldButton(button)).or_log_ignore("Broken Channel (HID Driver)");
     }
     
     /// Release button
-    pub async fn release_button(&self, button: MouseButton) {
+    pub async fn release_button(&self, button: Button) {
         self.tx.send(Command::ReleaseButton(button)).or_log_ignore("Broken Channel (HID Driver)");
     }
     
+    /// Hold media key, sent immediately
+    pub async fn hold_media(&self, media: MediaKey) {
+        self.tx.send(Command::HoldMedia(media)).or_log_ignore("Broken Channel (HID Driver)");
+    }
+
+    /// Release media key, sent immediately
+    pub async fn release_media(&self, media: MediaKey) {
+        self.tx.send(Command::ReleaseMedia(media)).or_log_ignore("Broken Channel (HID Driver)");
+    }
+
+    /// Move the pointer to an absolute position, an axis is left unchanged if it is none, sent immediately
+    pub async fn move_absolute(&self, x: Option<u16>, y: Option<u16>) {
+        self.tx.send(Command::MoveAbsolute(x, y)).or_log_ignore("Broken Channel (HID Driver)");
+    }
+
     /// Send key strokes to interface (does nothing for uinput)
     pub fn send_keyboard(&self) {
         self.tx.send(Command::SendKeyboard).or_log_ignore("Broken Channel (HID Driver)");
@@ -495,251 +501,67 @@ impl HID {
         self.tx.send(Command::SendMouse).or_log_ignore("Broken Channel (HID Driver)");
     }
 
-    /// Switch hid controllers (uinput or usb hid)
+    /// Switch hid backend (usb, uinput, log or a hid module)
     pub fn switch(&self, name: String) {
         self.tx.send(Command::Switch(name)).or_log_ignore("Broken Channel (HID Driver)");
     }
-}
 
-/// Usb mouse to uinput
-fn mouse_button_to_mouse(button: MouseButton) -> Mouse {
-    match button {
-        MouseButton::Left => Mouse::Left,
-        MouseButton::Right => Mouse::Right,
-        MouseButton::Middle => Mouse::Middle,
+    /// Play back macro actions in a task, delays hold back the later actions of the macro
+    pub fn play_macro(&self, actions: Vec<MacroAction>) {
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            for action in actions {
+                if let MacroAction::Delay(delay) = action {
+                    tokio::time::sleep(Duration::from_millis(delay)).await;
+                } else if let Some(command) = Command::from_macro_action(action) {
+                    if tx.send(command).or_log_ignore("Broken Channel (HID Driver)").is_none() {
+                        return;
+                    }
+                }
+            }
+        });
     }
-}
 
-/// Usb mouse to uinput
-fn mouse_dir_to_position(dir: MouseDir) -> Position {
-    match dir {
-        MouseDir::X => Position::X,
-        MouseDir::Y => Position::Y,
+    /// Start recording hid actions
+    pub fn start_recording(&self) {
+        self.tx.send(Command::StartRecording).or_log_ignore("Broken Channel (HID Driver)");
     }
-}
 
-/// True if character requires shift to press
-fn requires_shift(key: char) -> bool {
-    match key {
-        '!' | '@' | '#' | '$' | '%' | '^' | '&' | '*' | '(' | ')' | '_' | '+' | '{' | '}' | '|' | ':' | '"' | '<' | '>' | '?' | '~' => true, 
-        _ => key.is_ascii_alphabetic() && key.is_ascii_uppercase(),
+    /// Stop recording hid actions, returning the recorded actions
+    pub async fn stop_recording(&self) -> Option<Vec<MacroAction>> {
+        let (tx, rx) = oneshot::channel();
+        self.tx.send(Command::StopRecording(tx)).or_log_ignore("Broken Channel (HID Driver)")?;
+        rx.await.or_log_ignore("Broken Channel (HID Driver)")
     }
 }
 
-/// Usb keyboard to uinput
-fn mod_to_uinput(modifier: Modifier) -> Option<event::Keyboard> {
-    Some(match modifier {
-        Modifier::LeftControl => event::Keyboard::Key(Key::LeftControl),
-        Modifier::LeftShift => event::Keyboard::Key(Key::LeftShift),
-        Modifier::LeftAlt => event::Keyboard::Key(Key::LeftAlt),
-        Modifier::LeftMeta => event::Keyboard::Key(Key::LeftMeta),
-        Modifier::RightControl => event::Keyboard::Key(Key::RightControl),
-        Modifier::RightShift => event::Keyboard::Key(Key::RightShift),
-        Modifier::RightAlt => event::Keyboard::Key(Key::RightAlt),
-        Modifier::RightMeta => event::Keyboard::Key(Key::RightMeta),
-    })
+#[cfg(test)]
+mod tests {
+    use crate::harness::{keys, recorders};
+
+    #[tokio::test]
+    async fn switch_and_mirror_backends() {
+        let (hid, recorders) = recorders(&["a", "b", "c"], &["c"]).await;
+        let hid = hid.read().await;
+
+        hid.hold_key('x').await;
+        hid.send_keyboard();
+        assert_eq!(recorders[0].reports().await, [keys(&['x'])]);
+        assert_eq!(recorders[1].reports().await, []);
+        assert_eq!(recorders[2].reports().await, [keys(&['x'])]);
+
+        hid.switch("b".to_string());
+        hid.release_key('x').await;
+        hid.send_keyboard();
+        assert_eq!(recorders[0].reports().await, []);
+        assert_eq!(recorders[1].reports().await, [keys(&[])]);
+        assert_eq!(recorders[2].reports().await, [keys(&[])]);
+
+        hid.switch("c".to_string());
+        hid.send_keyboard();
+        assert_eq!(recorders[2].reports().await, [keys(&[])]);
+        hid.switch("missing".to_string());
+        hid.send_keyboard();
+        assert_eq!(recorders[2].reports().await, [keys(&[])]);
+    }
 }
-
-/// Usb keyboard to uinput
-fn special_to_uinput(special: SpecialKey) -> Option<event::Keyboard> {
-    Some(match special {
-        SpecialKey::ReturnEnter => event::Keyboard::Key(Key::Enter),
-        SpecialKey::Return => event::Keyboard::Key(Key::LineFeed),
-        SpecialKey::Escape => event::Keyboard::Key(Key::Esc),
-        SpecialKey::Backspace => event::Keyboard::Key(Key::BackSpace),
-        SpecialKey::Tab => event::Keyboard::Key(Key::Tab),
-        SpecialKey::Spacebar => event::Keyboard::Key(Key::Space),
-        SpecialKey::NONUSHashAndTilda => return None,
-        SpecialKey::CapsLock => event::Keyboard::Key(Key::CapsLock),
-        SpecialKey::F1 => event::Keyboard::Key(Key::F1),
-        SpecialKey::F2 => event::Keyboard::Key(Key::F2),
-        SpecialKey::F3 => event::Keyboard::Key(Key::F3),
-        SpecialKey::UpArrow => event::Keyboard::Key(Key::Up),
-        SpecialKey::DownArrow => event::Keyboard::Key(Key::Down),
-        SpecialKey::LeftArrow => event::Keyboard::Key(Key::Left),
-        SpecialKey::RightArrow => event::Keyboard::Key(Key::Right),
-        SpecialKey::PageDown => event::Keyboard::Key(Key::PageDown),
-        SpecialKey::End => event::Keyboard::Key(Key::End),
-        SpecialKey::DeleteForward => event::Keyboard::Key(Key::Delete),
-        SpecialKey::PageUp => event::Keyboard::Key(Key::PageUp),
-        SpecialKey::Home => event::Keyboard::Key(Key::Home),
-        SpecialKey::Insert => event::Keyboard::Key(Key::Insert),
-        SpecialKey::Pause => event::Keyboard::Misc(Misc::Pause),
-        SpecialKey::ScrollLock => event::Keyboard::Key(Key::ScrollLock),
-        SpecialKey::PrintScreen => return None,
-        SpecialKey::F12 => event::Keyboard::Key(Key::F12),
-        SpecialKey::F11 => event::Keyboard::Key(Key::F11),
-        SpecialKey::F10 => event::Keyboard::Key(Key::F10),
-        SpecialKey::F9 => event::Keyboard::Key(Key::F9),
-        SpecialKey::F8 => event::Keyboard::Key(Key::F8),
-        SpecialKey::F7 => event::Keyboard::Key(Key::F7),
-        SpecialKey::F6 => event::Keyboard::Key(Key::F6),
-        SpecialKey::F5 => event::Keyboard::Key(Key::F5),
-        SpecialKey::F4 => event::Keyboard::Key(Key::F4),
-        SpecialKey::NumLockAndClear => event::Keyboard::Key(Key::NumLock),
-        SpecialKey::Enter => event::Keyboard::Key(Key::Enter),
-        SpecialKey::Application => event::Keyboard::Misc(Misc::AppSelect),
-        SpecialKey::Power => event::Keyboard::Misc(Misc::Power),
-        SpecialKey::RightGUI => event::Keyboard::Key(Key::RightMeta),
-        SpecialKey::RightAlt => event::Keyboard::Key(Key::RightAlt),
-        SpecialKey::RightShift => event::Keyboard::Key(Key::RightShift),
-        SpecialKey::RightControl => event::Keyboard::Key(Key::RightControl),
-        SpecialKey::LeftGUI => event::Keyboard::Key(Key::LeftMeta),
-        SpecialKey::LeftAlt => event::Keyboard::Key(Key::LeftAlt),
-        SpecialKey::LeftShift => event::Keyboard::Key(Key::LeftShift),
-        SpecialKey::LeftControl => event::Keyboard::Key(Key::LeftControl),
-        SpecialKey::Hexadecimal => return None,
-        SpecialKey::Decimal => return None,
-        SpecialKey::Octal => return None,
-        SpecialKey::Binary => return None,
-        SpecialKey::ClearEntry => return None,
-        SpecialKey::Clear => event::Keyboard::Misc(Misc::Clear),
-        SpecialKey::PlusMinux => event::Keyboard::KeyPad(KeyPad::PlusMinus),
-        SpecialKey::MemoryDivide => return None,
-        SpecialKey::MemoryMultiply => return None,
-        SpecialKey::MemorySubtract => return None,
-        SpecialKey::MemoryAdd => return None,
-        SpecialKey::MemoryClear => return None,
-        SpecialKey::MemoryRecall => return None,
-        SpecialKey::MemoryStore => return None,
-        SpecialKey::Space => event::Keyboard::Key(Key::Space),
-        SpecialKey::Or => return None,
-        SpecialKey::And => return None,
-        SpecialKey::XOR => return None,
-        SpecialKey::CurrencySubunit => return None,
-        SpecialKey::CurrencyUnit => return None,
-        SpecialKey::DecimalSeparator => return None,
-        SpecialKey::ThousandsSeparator => return None,
-        SpecialKey::_000 => return None,
-        SpecialKey::_00 => return None,
-        SpecialKey::ExSel => return None,
-        SpecialKey::CrSelProps => return None,
-        SpecialKey::ClearAgain => return None,
-        SpecialKey::Oper => return None,
-        SpecialKey::Out => return None,
-        SpecialKey::Separator => return None,
-        SpecialKey::Prior => return None,
-        SpecialKey::Cancel => event::Keyboard::InputAssist(InputAssist::Cancel),
-        SpecialKey::SysReqAttention1 => return None,
-        SpecialKey::AlternateErase => return None,
-        SpecialKey::LANG9 => return None,
-        SpecialKey::LANG8 => return None,
-        SpecialKey::LANG7 => return None,
-        SpecialKey::LANG6 => return None,
-        SpecialKey::LANG5 => return None,
-        SpecialKey::LANG4 => return None,
-        SpecialKey::LANG3 => return None,
-        SpecialKey::LANG2 => return None,
-        SpecialKey::LANG1 => return None,
-        SpecialKey::International9 => return None,
-        SpecialKey::International8 => return None,
-        SpecialKey::International7 => return None,
-        SpecialKey::International6 => return None,
-        SpecialKey::International5 => return None,
-        SpecialKey::International4 => return None,
-        SpecialKey::International3 => return None,
-        SpecialKey::International2 => return None,
-        SpecialKey::International1 => return None,
-        SpecialKey::LockingScrollLock => return None,
-        SpecialKey::LockingNumLock => return None,
-        SpecialKey::LockingCapsLock => return None,
-        SpecialKey::VolumeDown => event::Keyboard::Misc(Misc::VolumeDown),
-        SpecialKey::VolumeUp => event::Keyboard::Misc(Misc::VolumeUp),
-        SpecialKey::Mute => event::Keyboard::Misc(Misc::Mute),
-        SpecialKey::Find => event::Keyboard::Misc(Misc::Find),
-        SpecialKey::Paste => event::Keyboard::Misc(Misc::Paste),
-        SpecialKey::Copy => event::Keyboard::Misc(Misc::Copy),
-        SpecialKey::Cut => event::Keyboard::Misc(Misc::Cut),
-        SpecialKey::Undo => event::Keyboard::Misc(Misc::Undo),
-        SpecialKey::Again => event::Keyboard::Misc(Misc::Again),
-        SpecialKey::Stop => event::Keyboard::Misc(Misc::Stop),
-        SpecialKey::Select => event::Keyboard::Misc(Misc::Select),
-        SpecialKey::Menu => event::Keyboard::Misc(Misc::Menu),
-        SpecialKey::Help => event::Keyboard::Misc(Misc::Help),
-        SpecialKey::Execute => return None,
-        SpecialKey::F24 => event::Keyboard::Key(Key::F24),
-        SpecialKey::F23 => event::Keyboard::Key(Key::F23),
-        SpecialKey::F22 => event::Keyboard::Key(Key::F22),
-        SpecialKey::F21 => event::Keyboard::Key(Key::F21),
-        SpecialKey::F20 => event::Keyboard::Key(Key::F20),
-        SpecialKey::F19 => event::Keyboard::Key(Key::F19),
-        SpecialKey::F18 => event::Keyboard::Key(Key::F18),
-        SpecialKey::F17 => event::Keyboard::Key(Key::F17),
-        SpecialKey::F16 => event::Keyboard::Key(Key::F16),
-        SpecialKey::F15 => event::Keyboard::Key(Key::F15),
-        SpecialKey::F14 => event::Keyboard::Key(Key::F14),
-        SpecialKey::F13 => event::Keyboard::Key(Key::F13),
-        SpecialKey::NonUSSlashAndPipe => return  None,
-        SpecialKey::_DotAndDelete => event::Keyboard::KeyPad(KeyPad::Dot),
-        SpecialKey::_0AndInsert => event::Keyboard::KeyPad(KeyPad::_0),
-        SpecialKey::_9AndPageUp => event::Keyboard::KeyPad(KeyPad::_9),
-        SpecialKey::_8AndUpArrow => event::Keyboard::KeyPad(KeyPad::_8),
-        SpecialKey::_7AndHome => event::Keyboard::KeyPad(KeyPad::_7),
-        SpecialKey::_6AndRightArrow => event::Keyboard::KeyPad(KeyPad::_6),
-        SpecialKey::_5 => event::Keyboard::KeyPad(KeyPad::_5),
-        SpecialKey::_4AndLeftArrow => event::Keyboard::KeyPad(KeyPad::_4),
-        SpecialKey::_3AndPageDn => event::Keyboard::KeyPad(KeyPad::_3),
-        SpecialKey::_2AndDownArrow => event::Keyboard::KeyPad(KeyPad::_2),
-        SpecialKey::_1AndEnd => event::Keyboard::KeyPad(KeyPad::_1),
-        SpecialKey::PadClear => return None,
-        SpecialKey::PadBackspace => return None,
-        SpecialKey::PadTab => return None,
-        SpecialKey::EqualsSign => return None,
-        SpecialKey::Comma => event::Keyboard::KeyPad(KeyPad::Comma),
-    })
-} 
-
-/// Usb keyboard to uinput
-fn char_to_uinput(key: char) -> Option<event::Keyboard> {
-    Some(match key.to_ascii_lowercase() {
-		'1' | '!' => event::Keyboard::Key(Key::_1),
-		'2' | '@' => event::Keyboard::Key(Key::_2),
-		'3' | '#' => event::Keyboard::Key(Key::_3),
-		'4' | '$' => event::Keyboard::Key(Key::_4),
-		'5' | '%' => event::Keyboard::Key(Key::_5),
-		'6' | '^' => event::Keyboard::Key(Key::_6),
-		'7' | '&' => event::Keyboard::Key(Key::_7),
-		'8' | '*' => event::Keyboard::Key(Key::_8),
-		'9' | '(' => event::Keyboard::Key(Key::_9),
-		'0' | ')' => event::Keyboard::Key(Key::_0),
-		'-' | '_' => event::Keyboard::Key(Key::Minus),
-		'=' | '+' => event::Keyboard::Key(Key::Equal),
-		'q' => event::Keyboard::Key(Key::Q),
-		'w' => event::Keyboard::Key(Key::W),
-		'e' => event::Keyboard::Key(Key::E),
-		'r' => event::Keyboard::Key(Key::R),
-		't' => event::Keyboard::Key(Key::T),
-		'y' => event::Keyboard::Key(Key::Y),
-		'u' => event::Keyboard::Key(Key::U),
-		'i' => event::Keyboard::Key(Key::I),
-		'o' => event::Keyboard::Key(Key::O),
-		'p' => event::Keyboard::Key(Key::P),
-		'{' | '[' => event::Keyboard::Key(Key::LeftBrace),
-		'}' | ']' => event::Keyboard::Key(Key::RightBrace),
-		'a' => event::Keyboard::Key(Key::A),
-		's' => event::Keyboard::Key(Key::S),
-		'd' => event::Keyboard::Key(Key::D),
-		'f' => event::Keyboard::Key(Key::F),
-		'g' => event::Keyboard::Key(Key::G),
-		'h' => event::Keyboard::Key(Key::H),
-		'j' => event::Keyboard::Key(Key::J),
-		'k' => event::Keyboard::Key(Key::K),
-		'l' => event::Keyboard::Key(Key::L),
-		';' | ':' => event::Keyboard::Key(Key::SemiColon),
-		'\'' | '"' => event::Keyboard::Key(Key::Apostrophe),
-		'~' | '`'  => event::Keyboard::Key(Key::Grave),
-		'\\' | '|' => event::Keyboard::Key(Key::BackSlash),
-		'z' => event::Keyboard::Key(Key::Z),
-		'x' => event::Keyboard::Key(Key::X),
-		'c' => event::Keyboard::Key(Key::C),
-		'v' => event::Keyboard::Key(Key::V),
-		'b' => event::Keyboard::Key(Key::B),
-		'n' => event::Keyboard::Key(Key::N),
-		'm' => event::Keyboard::Key(Key::M),
-		',' | '<' => event::Keyboard::Key(Key::Comma),
-		'.' | '>' => event::Keyboard::Key(Key::Dot),
-		'/' | '?' => event::Keyboard::Key(Key::Slash),
-		' ' => event::Keyboard::Key(Key::Space),
-        _ => return None,
-    })
-}
\ No newline at end of file
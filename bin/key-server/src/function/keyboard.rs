@@ -1,12 +1,13 @@
 use std::{sync::{Arc}};
 
 use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
 use tokio::{sync::RwLock};
 use virt_hid::key::{BasicKey, SpecialKey, Modifier};
 
 use crate::variables::{Variable};
 
-use super::{FunctionInterface, ReturnCommand, FunctionType, hid::HID, Function, State, StateHelpers};
+use super::{FunctionInterface, ReturnCommand, FunctionType, hid::HID, Function, State, StateHelpers, OneShot};
 
 /// Key function (acts as normal key)
 pub struct Key{
@@ -122,6 +123,149 @@ impl FunctionInterface for ModifierKey {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// Media key, sent as a consumer control usage
+pub enum MediaKey {
+    PlayPause,
+    NextTrack,
+    PreviousTrack,
+    Stop,
+    FastForward,
+    Rewind,
+    Eject,
+    VolumeUp,
+    VolumeDown,
+    Mute,
+    BrightnessUp,
+    BrightnessDown,
+    Calculator,
+    Mail,
+    Browser,
+    Search,
+}
+
+impl MediaKey {
+    /// Consumer page usage id
+    pub fn usage(&self) -> u16 {
+        match self {
+            MediaKey::PlayPause => 0xCD,
+            MediaKey::NextTrack => 0xB5,
+            MediaKey::PreviousTrack => 0xB6,
+            MediaKey::Stop => 0xB7,
+            MediaKey::FastForward => 0xB3,
+            MediaKey::Rewind => 0xB4,
+            MediaKey::Eject => 0xB8,
+            MediaKey::VolumeUp => 0xE9,
+            MediaKey::VolumeDown => 0xEA,
+            MediaKey::Mute => 0xE2,
+            MediaKey::BrightnessUp => 0x6F,
+            MediaKey::BrightnessDown => 0x70,
+            MediaKey::Calculator => 0x192,
+            MediaKey::Mail => 0x18A,
+            MediaKey::Browser => 0x223,
+            MediaKey::Search => 0x221,
+        }
+    }
+}
+
+/// Media key function (acts as normal key)
+pub struct Media {
+    media: MediaKey,
+    hid: Arc<RwLock<HID>>,
+    prev_state: u16,
+}
+
+impl Media {
+    /// New
+    pub fn new(media: MediaKey, hid: Arc<RwLock<HID>>) -> Function {
+        Some(Box::new(Media { media, prev_state: 0, hid }))
+    }
+}
+
+#[async_trait]
+impl FunctionInterface for Media {
+    async fn event(&mut self, state: State) -> ReturnCommand {
+        if state.rising(self.prev_state) {
+            self.hid.read().await.hold_media(self.media).await;
+        } else if state.falling(self.prev_state) {
+            self.hid.read().await.release_media(self.media).await;
+        }
+
+        self.prev_state = state;
+        ReturnCommand::None
+    }
+
+    fn ftype(&self) -> FunctionType {
+        FunctionType::Media(self.media)
+    }
+}
+
+/// One shot modifier function, holds a modifier for the next key press when tapped or whilst held
+pub struct OneShotModifier {
+    modifier: Modifier,
+    hid: Arc<RwLock<HID>>,
+    one_shot: OneShot,
+    prev_state: u16,
+}
+
+impl OneShotModifier {
+    /// New
+    pub fn new(modifier: Modifier, hid: Arc<RwLock<HID>>) -> Function {
+        Some(Box::new(OneShotModifier { modifier, one_shot: OneShot::Released, prev_state: 0, hid }))
+    }
+
+    /// Release the modifier
+    async fn release(&mut self) {
+        self.one_shot = OneShot::Released;
+
+        let hid = self.hid.read().await;
+
+        hid.release_mod(self.modifier).await;
+        hid.send_keyboard();
+    }
+}
+
+#[async_trait]
+impl FunctionInterface for OneShotModifier {
+    async fn event(&mut self, state: State) -> ReturnCommand {
+        let ret = if state.rising(self.prev_state) {
+            self.one_shot = OneShot::Held;
+
+            let hid = self.hid.read().await;
+
+            hid.hold_mod(self.modifier).await;
+            hid.send_keyboard();
+            ReturnCommand::WatchNextKey
+        } else if state.falling(self.prev_state) {
+            if self.one_shot == OneShot::Held {
+                self.one_shot = OneShot::Pending;
+                ReturnCommand::WatchNextKey
+            } else {
+                self.release().await;
+                ReturnCommand::None
+            }
+        } else {
+            ReturnCommand::None
+        };
+
+        self.prev_state = state;
+        ret
+    }
+
+    async fn next_key(&mut self) -> ReturnCommand {
+        match self.one_shot {
+            OneShot::Held => self.one_shot = OneShot::Used,
+            OneShot::Pending => self.release().await,
+            OneShot::Released | OneShot::Used => (),
+        }
+        ReturnCommand::None
+    }
+
+    fn ftype(&self) -> FunctionType {
+        FunctionType::OneShotModifier(self.modifier)
+    }
+}
+
 /// Basic String function, types a string
 pub struct BasicString {
     string: Variable<String>,
@@ -244,4 +388,38 @@ impl FunctionInterface for Shortcut {
     fn ftype(&self) -> FunctionType {
         FunctionType::Shortcut{modifiers: self.modifiers.clone(), keys: self.keys.clone()}
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use virt_hid::key::Modifier;
+
+    use crate::harness::{Harness, row_layout, keys, keyboard};
+
+    #[tokio::test]
+    async fn one_shot_modifier_applies_to_next_key() {
+        let mut harness = Harness::new(&row_layout(json!([[[{"OneShotModifier": "LeftShift"}, {"Key": "a"}]]])), 2).await;
+
+        harness.tap(0).await;
+        harness.tap(1).await;
+        harness.tap(1).await;
+        harness.assert_reports(&[
+            keyboard(&[Modifier::LeftShift], &[]),
+            keyboard(&[Modifier::LeftShift], &['a']),
+            keys(&['a']),
+            keys(&[]),
+            keys(&['a']),
+            keys(&[]),
+        ]).await;
+
+        harness.press(0).await;
+        harness.tap(1).await;
+        harness.release(0).await;
+        harness.assert_reports(&[
+            keyboard(&[Modifier::LeftShift], &[]),
+            keyboard(&[Modifier::LeftShift], &['a']),
+            keyboard(&[Modifier::LeftShift], &[]),
+            keys(&[]),
+        ]).await;
+    }
+}
@@ -0,0 +1,200 @@
+use std::time::{Instant, Duration};
+
+use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
+
+use crate::variables::{Variable, Data};
+
+use super::{Function, FunctionInterface, ReturnCommand, FunctionType, State, StateHelpers, forward_event, PRESSED, RELEASED};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// What a leader does with the captured keys when no sequence matches
+pub enum LeaderFallback {
+    /// Drop the captured keys
+    #[default]
+    Discard,
+    /// Send the captured keys as if the leader was never pressed
+    Replay,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Leader sequence, a function run when its keys are typed after the leader
+pub struct LeaderSequence<F> {
+    pub keys: String,
+    pub function: F,
+}
+
+/// Keys captured since the leader was pressed
+struct Capture {
+    keys: String,
+    /// Grid indices of the captured key presses
+    indices: Vec<usize>,
+    start: Instant,
+}
+
+/// Leader function, captures the keys typed after it and runs the function of the sequence they match
+pub struct Leader {
+    sequences: Vec<LeaderSequence<Function>>,
+    timeout: Variable<Duration>,
+    fallback: LeaderFallback,
+    captured: Option<Capture>,
+    prev_state: u16,
+}
+
+impl Leader {
+    /// New
+    pub fn new(sequences: Vec<LeaderSequence<Function>>, timeout: Variable<u64>, fallback: LeaderFallback) -> Function {
+        let timeout: Variable<Duration> = timeout.map(|timeout| Duration::from_millis(timeout));
+        Some(Box::new(Leader{sequences, timeout, fallback, captured: None, prev_state: 0}))
+    }
+
+    /// Stop capturing without a match
+    fn fail(&mut self) -> ReturnCommand {
+        let indices = self.captured.take().map(|captured| captured.indices).unwrap_or_default();
+        match self.fallback {
+            LeaderFallback::Discard => ReturnCommand::DiscardDeferred(indices),
+            LeaderFallback::Replay => ReturnCommand::None,
+        }
+    }
+}
+
+#[async_trait]
+impl FunctionInterface for Leader {
+    async fn event(&mut self, state: State) -> ReturnCommand {
+        if state.rising(self.prev_state) {
+            self.captured = Some(Capture { keys: String::new(), indices: vec![], start: Instant::now() });
+        }
+
+        self.prev_state = state;
+        ReturnCommand::None
+    }
+
+    async fn interrupt(&mut self, idx: usize, pressed: bool, ftype: &FunctionType) -> ReturnCommand {
+        if !pressed {
+            return ReturnCommand::None;
+        }
+
+        let Some(captured) = &mut self.captured else {
+            return ReturnCommand::None;
+        };
+
+        // Releases of the captured presses are dropped with them, events of keys held before the leader are kept
+        captured.indices.push(idx);
+        let FunctionType::Key(key) = ftype else {
+            return self.fail();
+        };
+        captured.keys.push(*key);
+        let keys = captured.keys.clone();
+
+        if let Some(sequence) = self.sequences.iter_mut().find(|sequence| sequence.keys == keys) {
+            let indices = self.captured.take().map(|captured| captured.indices).unwrap_or_default();
+            ReturnCommand::Many(vec![
+                ReturnCommand::DiscardDeferred(indices),
+                forward_event(&mut sequence.function, PRESSED).await,
+                forward_event(&mut sequence.function, RELEASED).await,
+            ])
+        } else if self.sequences.iter().any(|sequence| sequence.keys.starts_with(&keys)) {
+            ReturnCommand::None
+        } else {
+            self.fail()
+        }
+    }
+
+    async fn timeout(&mut self) -> ReturnCommand {
+        if matches!(&self.captured, Some(captured) if captured.start.elapsed() >= *self.timeout.data()) {
+            self.fail()
+        } else {
+            ReturnCommand::None
+        }
+    }
+
+    fn undecided(&self) -> bool {
+        self.captured.is_some()
+    }
+
+    fn ftype(&self) -> FunctionType {
+        let timeout: Data<Duration> = self.timeout.into_data();
+        FunctionType::Leader{
+            sequences: self.sequences.iter()
+                .map(|sequence| LeaderSequence {
+                    keys: sequence.keys.clone(),
+                    function: FunctionType::from_function(&sequence.function),
+                })
+                .collect(),
+            timeout_ms: timeout.map(|timeout| timeout.as_millis() as u64),
+            fallback: self.fallback,
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use virt_hid::key::Modifier;
+
+    use crate::harness::{Harness, row_layout, keys, keyboard};
+
+    /// Layout with a leader typing x after a then b, followed by a, b and c keys and shift
+    fn leader_layout(fallback: &str) -> String {
+        row_layout(json!([[[{"Leader": {
+            "sequences": [{"keys": "ab", "function": {"Key": "x"}}],
+            "timeout_ms": {"Const": 60000},
+            "fallback": fallback,
+        }}, {"Key": "a"}, {"Key": "b"}, {"Key": "c"}, {"Modifier": "LeftShift"}]]]))
+    }
+
+    #[tokio::test]
+    async fn runs_matching_sequence() {
+        let mut harness = Harness::new(&leader_layout("Discard"), 5).await;
+
+        harness.tap(0).await;
+        harness.tap(1).await;
+        harness.assert_reports(&[]).await;
+
+        harness.tap(2).await;
+        harness.assert_reports(&[keys(&['x']), keys(&[])]).await;
+
+        harness.tap(3).await;
+        harness.assert_reports(&[keys(&['c']), keys(&[])]).await;
+    }
+
+    #[tokio::test]
+    async fn falls_back_without_match() {
+        let mut harness = Harness::new(&leader_layout("Discard"), 5).await;
+
+        harness.tap(0).await;
+        harness.tap(3).await;
+        harness.assert_reports(&[]).await;
+
+        let mut harness = Harness::new(&leader_layout("Replay"), 5).await;
+
+        harness.tap(0).await;
+        harness.tap(1).await;
+        harness.tap(3).await;
+        harness.assert_reports(&[keys(&['a']), keys(&[]), keys(&['c']), keys(&[])]).await;
+    }
+
+    #[tokio::test]
+    async fn releases_keys_held_before_leader() {
+        let mut harness = Harness::new(&leader_layout("Discard"), 5).await;
+
+        harness.press(4).await;
+        harness.tap(0).await;
+        harness.tap(1).await;
+        harness.release(4).await;
+        harness.assert_reports(&[keyboard(&[Modifier::LeftShift], &[])]).await;
+
+        harness.tap(2).await;
+        harness.assert_reports(&[
+            keyboard(&[Modifier::LeftShift], &['x']),
+            keyboard(&[Modifier::LeftShift], &[]),
+            keys(&[]),
+        ]).await;
+
+        harness.press(4).await;
+        harness.tap(0).await;
+        harness.release(4).await;
+        harness.tap(3).await;
+        harness.assert_reports(&[keyboard(&[Modifier::LeftShift], &[]), keys(&[])]).await;
+    }
+}
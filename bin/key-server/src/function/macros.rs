@@ -0,0 +1,205 @@
+use std::{sync::Arc, path::{PathBuf, Path}};
+
+use async_trait::async_trait;
+use log::info;
+use serde::{Serialize, Deserialize};
+use tokio::sync::RwLock;
+use virt_hid::{key::{SpecialKey, Modifier}, mouse::MouseDir};
+
+use crate::{variables::Variable, OrLog};
+
+use super::{Function, FunctionInterface, ReturnCommand, FunctionType, State, StateHelpers, hid::HID, mouse::Button, keyboard::MediaKey};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Macro action, a recorded hid action
+pub enum MacroAction {
+    HoldKey(char),
+    HoldSpecial(SpecialKey),
+    HoldModifier(Modifier),
+    ReleaseKey(char),
+    ReleaseSpecial(SpecialKey),
+    ReleaseModifier(Modifier),
+    PressBasicStr(String),
+    PressStr(String, String),
+    ScrollWheel(i8),
+    /// Scroll horizontally
+    Pan(i8),
+    MoveMouse(i8, MouseDir),
+    HoldButton(Button),
+    ReleaseButton(Button),
+    HoldMedia(MediaKey),
+    ReleaseMedia(MediaKey),
+    /// Move the pointer to an absolute position, (x, y), an axis is left unchanged if it is none
+    MoveAbsolute(Option<u16>, Option<u16>),
+    SendKeyboard,
+    SendMouse,
+    /// Wait for a number of milliseconds
+    Delay(u64),
+}
+
+/// Path to a macro slot, slot names must not leave the macros folder
+fn slot_path(macros: &Path, slot: &str) -> Result<PathBuf, String> {
+    if slot.is_empty() || slot.contains(['/', '\\']) || slot.contains("..") {
+        return Err(format!("Invalid macro slot name {:?}", slot));
+    }
+    Ok(macros.join(format!("{}.json", slot)))
+}
+
+/// Load a macro from its slot
+async fn load_macro(macros: &Path, slot: &str) -> Option<Vec<MacroAction>> {
+    let path = slot_path(macros, slot).or_log("Unable to read macro (Macro)")?;
+    let json = tokio::fs::read_to_string(path).await
+        .or_log(&format!("Unable to read macro (Macro), {}", slot))?;
+    serde_json::from_str(&json)
+        .or_log(&format!("Unable to parse macro (Macro), {}", slot))
+}
+
+/// Save a macro to its slot
+async fn save_macro(macros: &Path, slot: &str, actions: &Vec<MacroAction>) -> Option<()> {
+    let path = slot_path(macros, slot).or_log("Unable to save macro (Record Macro)")?;
+    let json = serde_json::to_string_pretty(actions)
+        .or_log(&format!("Unable to serialize macro (Record Macro), {}", slot))?;
+    tokio::fs::write(path, json).await
+        .or_log(&format!("Unable to write macro (Record Macro), {}", slot))
+}
+
+/// Macro function, plays back a recorded macro
+pub struct Macro {
+    slot: Variable<String>,
+    macros: PathBuf,
+    prev_state: u16,
+    hid: Arc<RwLock<HID>>,
+}
+
+impl Macro {
+    /// New
+    pub fn new(slot: Variable<String>, macros: PathBuf, hid: Arc<RwLock<HID>>) -> Function {
+        Some(Box::new(Macro{slot, macros, prev_state: 0, hid}))
+    }
+}
+
+#[async_trait]
+impl FunctionInterface for Macro {
+    async fn event(&mut self, state: State) -> ReturnCommand {
+        if state.rising(self.prev_state) {
+            if let Some(actions) = load_macro(&self.macros, self.slot.data()).await {
+                self.hid.read().await.play_macro(actions);
+            }
+        }
+
+        self.prev_state = state;
+        ReturnCommand::None
+    }
+
+    fn ftype(&self) -> FunctionType {
+        FunctionType::Macro{slot: self.slot.into_data()}
+    }
+}
+
+/// Record macro function, records hid actions until pressed again
+pub struct RecordMacro {
+    slot: Variable<String>,
+    macros: PathBuf,
+    recording: bool,
+    prev_state: u16,
+    hid: Arc<RwLock<HID>>,
+}
+
+impl RecordMacro {
+    /// New
+    pub fn new(slot: Variable<String>, macros: PathBuf, hid: Arc<RwLock<HID>>) -> Function {
+        Some(Box::new(RecordMacro{slot, macros, recording: false, prev_state: 0, hid}))
+    }
+}
+
+#[async_trait]
+impl FunctionInterface for RecordMacro {
+    async fn event(&mut self, state: State) -> ReturnCommand {
+        if state.rising(self.prev_state) {
+            let hid = self.hid.read().await;
+
+            if !self.recording {
+                info!("Recording macro, {}", self.slot.data());
+                hid.start_recording();
+                self.recording = true;
+            } else {
+                self.recording = false;
+                if let Some(actions) = hid.stop_recording().await {
+                    if save_macro(&self.macros, self.slot.data(), &actions).await.is_some() {
+                        info!("Recorded macro, {}", self.slot.data());
+                    }
+                }
+            }
+        }
+
+        self.prev_state = state;
+        ReturnCommand::None
+    }
+
+    fn ftype(&self) -> FunctionType {
+        FunctionType::RecordMacro{slot: self.slot.into_data()}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use crate::harness::{Harness, row_layout, keys};
+
+    use super::*;
+
+    #[test]
+    fn slot_names_stay_in_macros_folder() {
+        let macros = Path::new("/lmk/macros");
+        assert_eq!(slot_path(macros, "copy").unwrap(), macros.join("copy.json"));
+        assert!(slot_path(macros, "../../etc/foo").is_err());
+        assert!(slot_path(macros, "/etc/foo").is_err());
+        assert!(slot_path(macros, "a\\b").is_err());
+        assert!(slot_path(macros, "..").is_err());
+        assert!(slot_path(macros, "").is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn records_and_plays_macro() {
+        let mut harness = Harness::new(&row_layout(json!([[[
+            {"RecordMacro": {"slot": {"Const": "recorded"}}},
+            {"Macro": {"slot": {"Const": "recorded"}}},
+            {"Key": "a"},
+        ]]])), 3).await;
+
+        harness.tap(0).await;
+        harness.tap(2).await;
+        harness.tap(0).await;
+        harness.assert_reports(&[keys(&['a']), keys(&[])]).await;
+        assert!(harness.macros().join("recorded.json").exists());
+
+        // Time is paused, so the macro plays through any recorded delays whilst sleeping
+        harness.tap(1).await;
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        harness.assert_reports(&[keys(&['a']), keys(&[])]).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn delays_only_hold_back_their_macro() {
+        let mut harness = Harness::new(&row_layout(json!([[[{"Macro": {"slot": {"Const": "delayed"}}}, {"Key": "a"}]]])), 2).await;
+        let actions = vec![
+            MacroAction::HoldKey('x'),
+            MacroAction::SendKeyboard,
+            MacroAction::Delay(200),
+            MacroAction::ReleaseKey('x'),
+            MacroAction::SendKeyboard,
+        ];
+        save_macro(harness.macros(), "delayed", &actions).await.unwrap();
+
+        harness.tap(0).await;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        harness.tap(1).await;
+        harness.assert_reports(&[keys(&['x']), keys(&['x', 'a']), keys(&['x'])]).await;
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        harness.assert_reports(&[keys(&[])]).await;
+    }
+}
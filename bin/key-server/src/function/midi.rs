@@ -61,6 +61,11 @@ impl FrontendConfig for MidiController {
 }
 
 impl MidiController {
+    /// New, sending messages to a midi connection. Sending messages fails once the receiver is dropped
+    pub fn from_sender(tx: UnboundedSender<(MidiMsg, oneshot::Sender<Result<(), MidiError>>)>) -> Arc<RwLock<MidiController>> {
+        Arc::new(RwLock::new(MidiController { tx, last_bend: None }))
+    }
+
     /// New
     pub async fn new() -> Result<Arc<RwLock<MidiController>>, MidiError> {
         let (tx, mut rx) = mpsc::unbounded_channel::<(MidiMsg, oneshot::Sender<Result<(), MidiError>>)>();
@@ -102,7 +107,7 @@ impl MidiController {
         });
 
         if let Ok(res) = new_rx.await {
-            res.map(|_| Arc::new(RwLock::new(MidiController { tx, last_bend: None })))
+            res.map(|_| MidiController::from_sender(tx))
         } else {
             Err(MidiError::Channel)
         }
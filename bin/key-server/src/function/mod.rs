@@ -1,4 +1,4 @@
-use std::{sync::Arc};
+use std::{sync::Arc, path::PathBuf};
 
 use crate::{
     driver::DriverManager,
@@ -16,40 +16,60 @@ use virt_hid::{
     mouse::MouseDir,
 };
 
+/// HID backends
+pub mod backend;
 /// Command functions
 pub mod cmd;
 /// HID function controller
 pub mod hid;
 /// Keyboard functions
 pub mod keyboard;
+/// Leader key functions
+pub mod leader;
 /// Log functions
 pub mod log;
+/// Macro functions
+pub mod macros;
 /// Midi functions
 pub mod midi;
 /// Mouse functions
 pub mod mouse;
+/// N-key rollover keyboard reports
+pub mod nkro;
 /// NanoMsg functions
 pub mod nng;
 /// Output functions
 pub mod output;
+/// Mouse reports
+pub mod pointer;
+/// Tap hold functions
+pub mod tap_hold;
 
 use self::{
     cmd::{Bash, CommandPool, Pipe},
     hid::{SendHidCommand, SwitchHid, ToggleHid, HID},
-    keyboard::{BasicString, ComplexString, Key, ModifierKey, Shortcut, Special},
+    keyboard::{BasicString, ComplexString, Key, Media, MediaKey, ModifierKey, OneShotModifier, Shortcut, Special},
+    leader::{Leader, LeaderFallback, LeaderSequence},
     log::{Log, LogLevel},
+    macros::{Macro, RecordMacro},
     midi::{
         note_param, Channel, ConstPitchBend, GMSoundSet, Instrument, MidiController, Note,
         PitchBend,
     },
     mouse::{
-        ConstMove, ConstScroll, ImmediateMove, ImmediateScroll, LeftClick, Move, RightClick, Scroll,
+        AbsoluteMove, Button, Click, ConstMove, ConstScroll, ImmediateMove, ImmediateScroll,
+        LeftClick, Move, RegionMove, RightClick, Scroll, ScrollDir, ABSOLUTE_MAX,
     },
     nng::{DriverData, NanoMessenger, NanoMsg},
     output::{Flip, Output},
+    tap_hold::{TapHold, TapHoldResolution},
 };
 
 const HALF_U16: u16 = u16::MAX / 2;
+/// State used to press nested functions
+pub const PRESSED: State = u16::MAX;
+/// State used to release nested functions
+pub const RELEASED: State = 0;
 
 pub type State = u16;
 pub trait StateHelpers {
@@ -88,10 +108,20 @@ pub enum ReturnCommand {
     Shift(usize),
     // Return from shifted layout
     UnShift(usize),
+    /// Turn a layer on or off
+    ToggleLayer(usize),
+    /// Set the default layer
+    DefaultLayer(usize),
     /// Up layout
     Up,
     /// Down layout
     Down,
+    /// Evaluate many commands in order
+    Many(Vec<ReturnCommand>),
+    /// Drop the key events held back whilst the function was undecided for keys at grid indices
+    DiscardDeferred(Vec<usize>),
+    /// Notify the function after the next key press
+    WatchNextKey,
     /// Return
     None,
 }
@@ -116,6 +146,23 @@ impl ReturnCommand {
             ReturnCommand::UnShift(index) => {
                 layout.unshift(*index);
             }
+            ReturnCommand::ToggleLayer(index) => {
+                layout.toggle_layer(*index);
+            }
+            ReturnCommand::DefaultLayer(index) => {
+                layout.set_default_layer(*index);
+            }
+            ReturnCommand::Many(commands) => {
+                for command in commands {
+                    command.eval(layout);
+                }
+            }
+            ReturnCommand::DiscardDeferred(indices) => {
+                layout.discard_deferred(indices);
+            }
+            ReturnCommand::WatchNextKey => {
+                layout.watch_next_key();
+            }
         }
     }
 }
@@ -133,6 +180,7 @@ pub enum FunctionType {
     Switch(variables::Data<usize>),
     Shift(variables::Data<usize>),
     None,
+    Block,
     LeftClick,
     RightClick,
     ConstScroll {
@@ -226,6 +274,54 @@ pub enum FunctionType {
         name: variables::Data<String>,
         command: variables::Data<String>,
     },
+    TapHold {
+        tap: Box<FunctionType>,
+        hold: Box<FunctionType>,
+        timeout_ms: variables::Data<u64>,
+        #[serde(default)]
+        resolution: TapHoldResolution,
+    },
+    Macro {
+        slot: variables::Data<String>,
+    },
+    RecordMacro {
+        slot: variables::Data<String>,
+    },
+    Leader {
+        sequences: Vec<LeaderSequence<FunctionType>>,
+        timeout_ms: variables::Data<u64>,
+        #[serde(default)]
+        fallback: LeaderFallback,
+    },
+    OneShotModifier(Modifier),
+    OneShotLayer(variables::Data<usize>),
+    ToggleLayer(variables::Data<usize>),
+    DefaultLayer(variables::Data<usize>),
+    Media(MediaKey),
+    AbsoluteMove {
+        x: variables::Data<u16>,
+        y: variables::Data<u16>,
+    },
+    RegionMove {
+        dir: MouseDir,
+        min: variables::Data<u16>,
+        max: variables::Data<u16>,
+        invert: variables::Data<bool>,
+    },
+    Click(Button),
+    ImmediatePan(variables::Data<i8>),
+    ConstPan {
+        amount: variables::Data<i8>,
+        period: variables::Data<u64>,
+    },
+    Pan {
+        period: variables::Data<u64>,
+        invert: variables::Data<bool>,
+        slope_y: variables::Data<f64>,
+        slope_x: variables::Data<f64>,
+        maximum: variables::Data<u16>,
+        threshold: variables::Data<f64>,
+    },
 }
 
 impl FunctionType {
@@ -247,6 +343,7 @@ pub struct FunctionBuilder {
     nano_messenger: Arc<RwLock<NanoMessenger>>,
     module_manager: Arc<ModuleManager>,
     variables: Arc<RwLock<Variables>>,
+    macros: PathBuf,
 }
 
 impl FunctionBuilder {
@@ -259,6 +356,7 @@ impl FunctionBuilder {
         nano_messenger: Arc<RwLock<NanoMessenger>>,
         module_manager: Arc<ModuleManager>,
         variables: Arc<RwLock<Variables>>,
+        macros: PathBuf,
     ) -> Arc<RwLock<FunctionBuilder>> {
         Arc::new(RwLock::new(FunctionBuilder {
             hid,
@@ -268,6 +366,7 @@ impl FunctionBuilder {
             nano_messenger,
             module_manager,
             variables,
+            macros,
         }))
     }
 
@@ -278,6 +377,22 @@ impl FunctionBuilder {
             FunctionType::Key(key) => Key::new(key, self.hid.clone()),
             FunctionType::Special(special) => Special::new(special, self.hid.clone()),
             FunctionType::Modifier(modifier) => ModifierKey::new(modifier, self.hid.clone()),
+            FunctionType::Media(media) => Media::new(media, self.hid.clone()),
+            FunctionType::AbsoluteMove { x, y } => AbsoluteMove::new(
+                x.into_variable(u16::default(), self.variables.clone()).await,
+                y.into_variable(u16::default(), self.variables.clone()).await,
+                self.hid.clone(),
+            ),
+            FunctionType::RegionMove { dir, min, max, invert } => RegionMove::new(
+                dir,
+                min.into_variable(u16::default(), self.variables.clone()).await,
+                max.into_variable(ABSOLUTE_MAX, self.variables.clone()).await,
+                invert.into_variable(bool::default(), self.variables.clone()).await,
+                self.hid.clone(),
+            ),
+            FunctionType::OneShotModifier(modifier) => {
+                OneShotModifier::new(modifier, self.hid.clone())
+            }
             FunctionType::String(str) => BasicString::new(
                 str.into_variable(String::default(), self.variables.clone())
                     .await,
@@ -326,6 +441,7 @@ impl FunctionBuilder {
                 maximum,
                 threshold,
             } => Scroll::new(
+                ScrollDir::Wheel,
                 period
                     .into_variable(u64::default(), self.variables.clone())
                     .await,
@@ -379,6 +495,7 @@ impl FunctionBuilder {
                 amount
                     .into_variable(i8::default(), self.variables.clone())
                     .await,
+                ScrollDir::Wheel,
                 self.hid.clone(),
             ),
             FunctionType::ConstMove { x, y } => ConstMove::new(
@@ -390,6 +507,7 @@ impl FunctionBuilder {
                 amount
                     .into_variable(i8::default(), self.variables.clone())
                     .await,
+                ScrollDir::Wheel,
                 period
                     .into_variable(u64::default(), self.variables.clone())
                     .await,
@@ -397,7 +515,55 @@ impl FunctionBuilder {
             ),
             FunctionType::LeftClick => LeftClick::new(self.hid.clone()),
             FunctionType::RightClick => RightClick::new(self.hid.clone()),
+            FunctionType::Click(button) => Click::new(button, self.hid.clone()),
+            FunctionType::ImmediatePan(amount) => ImmediateScroll::new(
+                amount
+                    .into_variable(i8::default(), self.variables.clone())
+                    .await,
+                ScrollDir::Pan,
+                self.hid.clone(),
+            ),
+            FunctionType::ConstPan { amount, period } => ConstScroll::new(
+                amount
+                    .into_variable(i8::default(), self.variables.clone())
+                    .await,
+                ScrollDir::Pan,
+                period
+                    .into_variable(u64::default(), self.variables.clone())
+                    .await,
+                self.hid.clone(),
+            ),
+            FunctionType::Pan {
+                period,
+                invert,
+                slope_y,
+                slope_x,
+                maximum,
+                threshold,
+            } => Scroll::new(
+                ScrollDir::Pan,
+                period
+                    .into_variable(u64::default(), self.variables.clone())
+                    .await,
+                invert
+                    .into_variable(bool::default(), self.variables.clone())
+                    .await,
+                slope_y
+                    .into_variable(1.0, self.variables.clone())
+                    .await,
+                slope_x
+                    .into_variable(1.0, self.variables.clone())
+                    .await,
+                maximum
+                    .into_variable(u16::MAX, self.variables.clone())
+                    .await,
+                threshold
+                    .into_variable(0.0, self.variables.clone())
+                    .await,
+                self.hid.clone(),
+            ),
             FunctionType::None => None,
+            FunctionType::Block => Block::new(),
             FunctionType::Note {
                 channel,
                 note,
@@ -514,6 +680,18 @@ impl FunctionBuilder {
                 id.into_variable(usize::default(), self.variables.clone())
                     .await,
             ),
+            FunctionType::OneShotLayer(id) => OneShotLayer::new(
+                id.into_variable(usize::default(), self.variables.clone())
+                    .await,
+            ),
+            FunctionType::ToggleLayer(id) => ToggleLayer::new(
+                id.into_variable(usize::default(), self.variables.clone())
+                    .await,
+            ),
+            FunctionType::DefaultLayer(id) => DefaultLayer::new(
+                id.into_variable(usize::default(), self.variables.clone())
+                    .await,
+            ),
             FunctionType::ToggleHid { modes } => ToggleHid::new(
                 modes.into_variable(vec![], self.variables.clone()).await,
                 self.hid.clone(),
@@ -526,6 +704,51 @@ impl FunctionBuilder {
                     .await,
                 self.hid.clone(),
             ),
+            FunctionType::TapHold {
+                tap,
+                hold,
+                timeout_ms,
+                resolution,
+            } => TapHold::new(
+                Box::pin(self.build(*tap)).await,
+                Box::pin(self.build(*hold)).await,
+                timeout_ms
+                    .into_variable(200, self.variables.clone())
+                    .await,
+                resolution,
+            ),
+            FunctionType::Macro { slot } => Macro::new(
+                slot.into_variable(String::default(), self.variables.clone())
+                    .await,
+                self.macros.clone(),
+                self.hid.clone(),
+            ),
+            FunctionType::RecordMacro { slot } => RecordMacro::new(
+                slot.into_variable(String::default(), self.variables.clone())
+                    .await,
+                self.macros.clone(),
+                self.hid.clone(),
+            ),
+            FunctionType::Leader {
+                sequences,
+                timeout_ms,
+                fallback,
+            } => {
+                let mut built = Vec::with_capacity(sequences.len());
+                for sequence in sequences {
+                    built.push(LeaderSequence {
+                        keys: sequence.keys,
+                        function: Box::pin(self.build(sequence.function)).await,
+                    });
+                }
+                Leader::new(
+                    built,
+                    timeout_ms
+                        .into_variable(1000, self.variables.clone())
+                        .await,
+                    fallback,
+                )
+            }
         }
         .or_log_ignore(&format!(
             "Unable to build function (Function Builder), {}",
@@ -539,6 +762,24 @@ impl FunctionBuilder {
 pub trait FunctionInterface {
     /// State poll event
     async fn event(&mut self, state: State) -> ReturnCommand;
+    /// Another key, at a grid index, was pressed or released whilst this function was undecided
+    async fn interrupt(&mut self, _idx: usize, _pressed: bool, _ftype: &FunctionType) -> ReturnCommand {
+        ReturnCommand::None
+    }
+    /// Called on every scan whilst the function is undecided, so it can resolve on a timer
+    /// without waiting for a key to change
+    async fn timeout(&mut self) -> ReturnCommand {
+        ReturnCommand::None
+    }
+    /// Another key was pressed after the function returned ReturnCommand::WatchNextKey
+    async fn next_key(&mut self) -> ReturnCommand {
+        ReturnCommand::None
+    }
+    /// True whilst the function is waiting to decide what to do, key events
+    /// at other positions are held back by the layout until it decides
+    fn undecided(&self) -> bool {
+        false
+    }
     /// Function Type
     fn ftype(&self) -> FunctionType;
 }
@@ -546,6 +787,14 @@ pub trait FunctionInterface {
 /// Function Object
 pub type Function = Option<Box<dyn FunctionInterface + Send + Sync>>;
 
+/// Pass a state to a function, if there is one
+pub async fn forward_event(func: &mut Function, state: State) -> ReturnCommand {
+    match func {
+        Some(func) => func.event(state).await,
+        None => ReturnCommand::None,
+    }
+}
+
 /// Up function
 pub struct Up;
 
@@ -588,6 +837,27 @@ impl FunctionInterface for Down {
     }
 }
 
+/// Block function, does nothing, stopping lower layers from handling the key
+pub struct Block;
+
+impl Block {
+    /// New
+    pub fn new() -> Function {
+        Some(Box::new(Block))
+    }
+}
+
+#[async_trait]
+impl FunctionInterface for Block {
+    async fn event(&mut self, _state: State) -> ReturnCommand {
+        ReturnCommand::None
+    }
+
+    fn ftype(&self) -> FunctionType {
+        FunctionType::Block
+    }
+}
+
 /// Switch function
 pub struct Switch {
     id: Variable<usize>,
@@ -648,3 +918,140 @@ impl FunctionInterface for Shift {
         FunctionType::Shift(self.id.into_data())
     }
 }
+
+/// Toggle layer function, turns a layer on or off
+pub struct ToggleLayer {
+    id: Variable<usize>,
+    prev_state: u16,
+}
+
+impl ToggleLayer {
+    /// New
+    pub fn new(id: Variable<usize>) -> Function {
+        Some(Box::new(ToggleLayer { id, prev_state: 0 }))
+    }
+}
+
+#[async_trait]
+impl FunctionInterface for ToggleLayer {
+    async fn event(&mut self, state: State) -> ReturnCommand {
+        let ret = if state.rising(self.prev_state) {
+            ReturnCommand::ToggleLayer(*self.id.data())
+        } else {
+            ReturnCommand::None
+        };
+
+        self.prev_state = state;
+        ret
+    }
+
+    fn ftype(&self) -> FunctionType {
+        FunctionType::ToggleLayer(self.id.into_data())
+    }
+}
+
+/// Default layer function, sets the default layer
+pub struct DefaultLayer {
+    id: Variable<usize>,
+    prev_state: u16,
+}
+
+impl DefaultLayer {
+    /// New
+    pub fn new(id: Variable<usize>) -> Function {
+        Some(Box::new(DefaultLayer { id, prev_state: 0 }))
+    }
+}
+
+#[async_trait]
+impl FunctionInterface for DefaultLayer {
+    async fn event(&mut self, state: State) -> ReturnCommand {
+        let ret = if state.rising(self.prev_state) {
+            ReturnCommand::DefaultLayer(*self.id.data())
+        } else {
+            ReturnCommand::None
+        };
+
+        self.prev_state = state;
+        ret
+    }
+
+    fn ftype(&self) -> FunctionType {
+        FunctionType::DefaultLayer(self.id.into_data())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// One shot state
+pub enum OneShot {
+    /// Not pressed
+    Released,
+    /// Pressed, no other key has been pressed yet
+    Held,
+    /// Pressed, another key was pressed whilst held
+    Used,
+    /// Tapped, waiting for the next key press
+    Pending,
+}
+
+/// One shot layer function, shifts to a layer for the next key press when tapped or whilst held
+pub struct OneShotLayer {
+    id: Variable<usize>,
+    shifted: usize,
+    one_shot: OneShot,
+    prev_state: u16,
+}
+
+impl OneShotLayer {
+    /// New
+    pub fn new(id: Variable<usize>) -> Function {
+        Some(Box::new(OneShotLayer { id, shifted: 0, one_shot: OneShot::Released, prev_state: 0 }))
+    }
+}
+
+#[async_trait]
+impl FunctionInterface for OneShotLayer {
+    async fn event(&mut self, state: State) -> ReturnCommand {
+        let ret = if state.rising(self.prev_state) {
+            let unshift = if self.one_shot == OneShot::Pending {
+                ReturnCommand::UnShift(self.shifted)
+            } else {
+                ReturnCommand::None
+            };
+            self.shifted = *self.id.data();
+            self.one_shot = OneShot::Held;
+            ReturnCommand::Many(vec![unshift, ReturnCommand::Shift(self.shifted), ReturnCommand::WatchNextKey])
+        } else if state.falling(self.prev_state) {
+            if self.one_shot == OneShot::Held {
+                self.one_shot = OneShot::Pending;
+                ReturnCommand::WatchNextKey
+            } else {
+                self.one_shot = OneShot::Released;
+                ReturnCommand::UnShift(self.shifted)
+            }
+        } else {
+            ReturnCommand::None
+        };
+
+        self.prev_state = state;
+        ret
+    }
+
+    async fn next_key(&mut self) -> ReturnCommand {
+        match self.one_shot {
+            OneShot::Held => {
+                self.one_shot = OneShot::Used;
+                ReturnCommand::None
+            }
+            OneShot::Pending => {
+                self.one_shot = OneShot::Released;
+                ReturnCommand::UnShift(self.shifted)
+            }
+            OneShot::Released | OneShot::Used => ReturnCommand::None,
+        }
+    }
+
+    fn ftype(&self) -> FunctionType {
+        FunctionType::OneShotLayer(self.id.into_data())
+    }
+}
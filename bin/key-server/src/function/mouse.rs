@@ -1,6 +1,7 @@
 use std::{sync::{Arc}, time::{Instant, Duration}};
 
 use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
 use tokio::{sync::RwLock};
 use virt_hid::mouse::{MouseDir, MouseButton};
 
@@ -8,6 +9,57 @@ use crate::variables::{Variable, Data};
 
 use super::{FunctionInterface, HID, ReturnCommand, FunctionType, Function, State, StateHelpers};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// Mouse button, used for serializing mouse buttons
+pub enum Button {
+    Left,
+    Right,
+    Middle,
+    /// Side button, back
+    Side,
+    /// Extra button, forward
+    Extra,
+}
+
+impl Button {
+    /// Virtual hid mouse button, side and extra buttons have none
+    pub fn mouse_button(self) -> Option<MouseButton> {
+        match self {
+            Button::Left => Some(MouseButton::Left),
+            Button::Right => Some(MouseButton::Right),
+            Button::Middle => Some(MouseButton::Middle),
+            Button::Side | Button::Extra => None,
+        }
+    }
+}
+
+impl From<&MouseButton> for Button {
+    fn from(button: &MouseButton) -> Self {
+        match button {
+            MouseButton::Left => Button::Left,
+            MouseButton::Right => Button::Right,
+            MouseButton::Middle => Button::Middle,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Scroll direction, the wheel scrolls vertically and pan scrolls horizontally
+pub enum ScrollDir {
+    Wheel,
+    Pan,
+}
+
+impl ScrollDir {
+    /// Scroll an amount in this direction
+    async fn scroll(self, hid: &HID, amount: i8) {
+        match self {
+            ScrollDir::Wheel => hid.scroll_wheel(amount).await,
+            ScrollDir::Pan => hid.pan(amount).await,
+        }
+    }
+}
+
 /// Immediate Move function, move the mouse a set amount on press
 pub struct ImmediateMove {
     amount: (Variable<i8>, Variable<i8>),
@@ -45,14 +97,15 @@ impl FunctionInterface for ImmediateMove {
 /// Immediate Scroll function, scroll the mouse a set amount on press
 pub struct ImmediateScroll {
     amount: Variable<i8>,
+    dir: ScrollDir,
     prev_state: u16,
     hid: Arc<RwLock<HID>>
 }
 
 impl ImmediateScroll {
     // New
-    pub fn new(amount: Variable<i8>, hid: Arc<RwLock<HID>>) -> Function {
-        Some(Box::new(ImmediateScroll{amount, prev_state: 0, hid}))
+    pub fn new(amount: Variable<i8>, dir: ScrollDir, hid: Arc<RwLock<HID>>) -> Function {
+        Some(Box::new(ImmediateScroll{amount, dir, prev_state: 0, hid}))
     }
 }
 
@@ -62,7 +115,7 @@ impl FunctionInterface for ImmediateScroll {
         if state.rising(self.prev_state) {
             let hid = self.hid.read().await;
 
-            hid.scroll_wheel(*self.amount.data()).await;
+            self.dir.scroll(&hid, *self.amount.data()).await;
             hid.send_mouse();
         }
 
@@ -71,7 +124,10 @@ impl FunctionInterface for ImmediateScroll {
     }
 
     fn ftype(&self) -> FunctionType {
-        return FunctionType::ImmediateScroll(self.amount.into_data())
+        match self.dir {
+            ScrollDir::Wheel => FunctionType::ImmediateScroll(self.amount.into_data()),
+            ScrollDir::Pan => FunctionType::ImmediatePan(self.amount.into_data()),
+        }
     }
 }
 
@@ -111,6 +167,7 @@ impl FunctionInterface for ConstMove {
 /// Const Scroll function, scroll the mouse a set amount whilst pressed
 pub struct ConstScroll {
     amount: Variable<i8>,
+    dir: ScrollDir,
     period: Variable<Duration>,
     prev_time: Instant,
     hid: Arc<RwLock<HID>>
@@ -118,9 +175,9 @@ pub struct ConstScroll {
 
 impl ConstScroll {
     /// New
-    pub fn new(amount: Variable<i8>, period: Variable<u64>, hid: Arc<RwLock<HID>>) -> Function {
+    pub fn new(amount: Variable<i8>, dir: ScrollDir, period: Variable<u64>, hid: Arc<RwLock<HID>>) -> Function {
         let period: Variable<Duration> = period.map(|period| Duration::from_millis(period));
-        Some(Box::new(ConstScroll{amount, period, hid, prev_time: Instant::now()}))
+        Some(Box::new(ConstScroll{amount, dir, period, hid, prev_time: Instant::now()}))
     }
 }
 
@@ -135,7 +192,7 @@ impl FunctionInterface for ConstScroll {
             if now.duration_since(self.prev_time) > *self.period.data() {
                 self.prev_time = now;
 
-                hid.scroll_wheel(*self.amount.data()).await;
+                self.dir.scroll(&hid, *self.amount.data()).await;
     
                 hid.send_mouse();
             }
@@ -146,7 +203,11 @@ impl FunctionInterface for ConstScroll {
 
     fn ftype(&self) -> FunctionType {
         let period: Data<Duration> = self.period.into_data();
-        return FunctionType::ConstScroll{amount: self.amount.into_data(), period: period.map(|period| period.as_millis() as u64)}
+        let (amount, period) = (self.amount.into_data(), period.map(|period| period.as_millis() as u64));
+        match self.dir {
+            ScrollDir::Wheel => FunctionType::ConstScroll{amount, period},
+            ScrollDir::Pan => FunctionType::ConstPan{amount, period},
+        }
     }
 }
 
@@ -231,6 +292,7 @@ impl FunctionInterface for Move {
 
 /// Scroll function, move the scroll in a direction based on the state
 pub struct Scroll {
+    dir: ScrollDir,
     period: Variable<Duration>,
     invert: Variable<bool>,
     slope_x: Variable<f64>,
@@ -243,9 +305,9 @@ pub struct Scroll {
 
 impl Scroll {
     /// New
-    pub fn new(period: Variable<u64>, invert: Variable<bool>, slope_y: Variable<f64>, slope_x: Variable<f64>, maximum: Variable<u16>, threshold: Variable<f64>, hid: Arc<RwLock<HID>>) -> Function {
+    pub fn new(dir: ScrollDir, period: Variable<u64>, invert: Variable<bool>, slope_y: Variable<f64>, slope_x: Variable<f64>, maximum: Variable<u16>, threshold: Variable<f64>, hid: Arc<RwLock<HID>>) -> Function {
         let period: Variable<Duration> = period.map(|period| Duration::from_millis(period));
-        Some(Box::new(Scroll{period, invert, slope_y, slope_x, maximum, threshold, prev_time: Instant::now(), hid}))
+        Some(Box::new(Scroll{dir, period, invert, slope_y, slope_x, maximum, threshold, prev_time: Instant::now(), hid}))
     }
 }
 
@@ -276,7 +338,7 @@ impl FunctionInterface for Scroll {
             
             let val = sigmoid(state, *self.invert.data(), *self.slope_y.data(), *self.slope_x.data());
 
-            hid.scroll_wheel(val).await;
+            self.dir.scroll(&hid, val).await;
             hid.send_mouse();
         }
 
@@ -285,7 +347,98 @@ impl FunctionInterface for Scroll {
 
     fn ftype(&self) -> FunctionType {
         let period: Data<Duration> = self.period.into_data();
-        FunctionType::Scroll{period: period.map(|period| period.as_millis() as u64), invert: self.invert.into_data(), slope_y: self.slope_y.into_data(), slope_x: self.slope_x.into_data(), maximum: self.maximum.into_data(), threshold: self.threshold.into_data()}
+        let period = period.map(|period| period.as_millis() as u64);
+        let (invert, slope_y, slope_x, maximum, threshold) = (self.invert.into_data(), self.slope_y.into_data(), self.slope_x.into_data(), self.maximum.into_data(), self.threshold.into_data());
+        match self.dir {
+            ScrollDir::Wheel => FunctionType::Scroll{period, invert, slope_y, slope_x, maximum, threshold},
+            ScrollDir::Pan => FunctionType::Pan{period, invert, slope_y, slope_x, maximum, threshold},
+        }
+    }
+}
+
+/// Largest absolute pointer coordinate, coordinates are scaled to the screen by the host
+pub const ABSOLUTE_MAX: u16 = 32767;
+
+/// Absolute Move function, move the pointer to a position on press
+pub struct AbsoluteMove {
+    position: (Variable<u16>, Variable<u16>),
+    prev_state: u16,
+    hid: Arc<RwLock<HID>>,
+}
+
+impl AbsoluteMove {
+    /// New
+    pub fn new(x: Variable<u16>, y: Variable<u16>, hid: Arc<RwLock<HID>>) -> Function {
+        Some(Box::new(AbsoluteMove{position: (x, y), prev_state: 0, hid}))
+    }
+}
+
+#[async_trait]
+impl FunctionInterface for AbsoluteMove {
+    async fn event(&mut self, state: State) -> ReturnCommand {
+        if state.rising(self.prev_state) {
+            let x = (*self.position.0.data()).min(ABSOLUTE_MAX);
+            let y = (*self.position.1.data()).min(ABSOLUTE_MAX);
+
+            self.hid.read().await.move_absolute(Some(x), Some(y)).await;
+        }
+
+        self.prev_state = state;
+        ReturnCommand::None
+    }
+
+    fn ftype(&self) -> FunctionType {
+        FunctionType::AbsoluteMove{x: self.position.0.into_data(), y: self.position.1.into_data()}
+    }
+}
+
+/// Region Move function, move the pointer to the position in a region along a direction matching the state,
+/// e.g. a joystick axis
+pub struct RegionMove {
+    dir: MouseDir,
+    min: Variable<u16>,
+    max: Variable<u16>,
+    invert: Variable<bool>,
+    prev_state: Option<u16>,
+    hid: Arc<RwLock<HID>>,
+}
+
+impl RegionMove {
+    /// New
+    pub fn new(dir: MouseDir, min: Variable<u16>, max: Variable<u16>, invert: Variable<bool>, hid: Arc<RwLock<HID>>) -> Function {
+        Some(Box::new(RegionMove{dir, min, max, invert, prev_state: None, hid}))
+    }
+}
+
+/// Position in a region matching a state
+fn region_position(state: State, min: u16, max: u16, invert: bool) -> u16 {
+    let mut state = state as f64 / u16::MAX as f64;
+    if invert {
+        state = 1.0 - state;
+    }
+    let (min, max) = (min.min(ABSOLUTE_MAX) as f64, max.min(ABSOLUTE_MAX) as f64);
+    (min + (max - min) * state).round() as u16
+}
+
+#[async_trait]
+impl FunctionInterface for RegionMove {
+    async fn event(&mut self, state: State) -> ReturnCommand {
+        if self.prev_state != Some(state) {
+            let position = region_position(state, *self.min.data(), *self.max.data(), *self.invert.data());
+            let (x, y) = match self.dir {
+                MouseDir::X => (Some(position), None),
+                MouseDir::Y => (None, Some(position)),
+            };
+
+            self.hid.read().await.move_absolute(x, y).await;
+        }
+
+        self.prev_state = Some(state);
+        ReturnCommand::None
+    }
+
+    fn ftype(&self) -> FunctionType {
+        FunctionType::RegionMove{dir: self.dir.clone(), min: self.min.into_data(), max: self.max.into_data(), invert: self.invert.into_data()}
     }
 }
 
@@ -308,10 +461,10 @@ impl FunctionInterface for LeftClick {
         let hid = self.hid.read().await;
 
         if state.rising(self.prev_state) {
-            hid.hold_button(MouseButton::Left).await;
+            hid.hold_button(Button::Left).await;
             hid.send_mouse();
         } else if state.falling(self.prev_state) {
-            hid.release_button(MouseButton::Left).await;
+            hid.release_button(Button::Left).await;
             hid.send_mouse();
         }
 
@@ -344,10 +497,10 @@ impl FunctionInterface for RightClick {
         let hid = self.hid.read().await;
 
         if state.rising(self.prev_state) {
-            hid.hold_button(MouseButton::Right).await;
+            hid.hold_button(Button::Right).await;
             hid.send_mouse();
         } else if state.falling(self.prev_state) {
-            hid.release_button(MouseButton::Right).await;
+            hid.release_button(Button::Right).await;
             hid.send_mouse();
         }
 
@@ -359,4 +512,112 @@ impl FunctionInterface for RightClick {
     fn ftype(&self) -> FunctionType {
         return FunctionType::RightClick
     }
-}
\ No newline at end of file
+}
+
+/// Click function, hold a mouse button whilst pressed
+pub struct Click {
+    button: Button,
+    hid: Arc<RwLock<HID>>,
+    prev_state: u16,
+}
+
+impl Click {
+    /// New
+    pub fn new(button: Button, hid: Arc<RwLock<HID>>) -> Function {
+        Some(Box::new(Click{button, hid, prev_state: 0}))
+    }
+}
+
+#[async_trait]
+impl FunctionInterface for Click {
+    async fn event(&mut self, state: State) -> super::ReturnCommand {
+        let hid = self.hid.read().await;
+
+        if state.rising(self.prev_state) {
+            hid.hold_button(self.button).await;
+            hid.send_mouse();
+        } else if state.falling(self.prev_state) {
+            hid.release_button(self.button).await;
+            hid.send_mouse();
+        }
+
+        self.prev_state = state;
+
+        return super::ReturnCommand::None
+    }
+
+    fn ftype(&self) -> FunctionType {
+        return FunctionType::Click(self.button)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{sigmoid, region_position, Button};
+    use crate::harness::{Harness, Report, MouseReport, row_layout};
+
+    #[test]
+    fn sigmoid_is_signed_and_bounded() {
+        assert_eq!(sigmoid(0.0, false, 1.0, 1.0), 0);
+        assert_eq!(sigmoid(0.5, false, 1.0, 1.0), 56);
+        assert_eq!(sigmoid(-0.5, false, 1.0, 1.0), -57);
+        assert_eq!(sigmoid(0.5, true, 1.0, 1.0), -57);
+        assert_eq!(sigmoid(100.0, false, 2.0, 1.0), i8::MAX);
+        assert_eq!(sigmoid(-100.0, false, 2.0, 1.0), i8::MIN);
+    }
+
+    #[test]
+    fn region_position_is_scaled_to_region() {
+        assert_eq!(region_position(0, 1000, 3000, false), 1000);
+        assert_eq!(region_position(u16::MAX, 1000, 3000, false), 3000);
+        assert_eq!(region_position(u16::MAX / 2, 1000, 3000, false), 2000);
+        assert_eq!(region_position(0, 1000, 3000, true), 3000);
+        assert_eq!(region_position(u16::MAX, 0, u16::MAX, false), 32767);
+    }
+
+    #[tokio::test]
+    async fn region_move_follows_state() {
+        let mut harness = Harness::new(&row_layout(json!([[[{"RegionMove": {
+            "dir": "Y",
+            "min": {"Const": 0},
+            "max": {"Const": 20000},
+            "invert": {"Const": false},
+        }}]]])), 1).await;
+
+        harness.frame(vec![(0, u16::MAX)]).await;
+        harness.assert_reports(&[Report::Absolute(0, 20000)]).await;
+        harness.frame(vec![(0, u16::MAX)]).await;
+        harness.assert_reports(&[]).await;
+    }
+
+    #[tokio::test]
+    async fn click_side_button_and_pan() {
+        let mut harness = Harness::new(&row_layout(json!([[[{"Click": "Side"}, {"ImmediatePan": {"Const": -3}}]]])), 2).await;
+
+        harness.press(0).await;
+        harness.assert_reports(&[Report::Mouse(MouseReport { buttons: vec![Button::Side], ..Default::default() })]).await;
+        harness.tap(1).await;
+        harness.assert_reports(&[Report::Mouse(MouseReport { buttons: vec![Button::Side], pan: -3, ..Default::default() })]).await;
+        harness.release(0).await;
+        harness.assert_reports(&[Report::Mouse(MouseReport::default())]).await;
+    }
+
+    #[tokio::test]
+    async fn move_past_threshold() {
+        let mut harness = Harness::new(&row_layout(json!([[[{"Move": {
+            "dir": "X",
+            "invert": {"Const": false},
+            "slope_y": {"Const": 1.0},
+            "slope_x": {"Const": 1.0},
+            "maximum": {"Const": 65535},
+            "threshold": {"Const": 0.5},
+        }}]]])), 1).await;
+
+        harness.step().await;
+        harness.assert_reports(&[]).await;
+
+        harness.press(0).await;
+        harness.assert_reports(&[Report::Mouse(MouseReport { x: 89, ..Default::default() })]).await;
+    }
+}
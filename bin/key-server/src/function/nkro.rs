@@ -0,0 +1,407 @@
+use std::{fs::{File, OpenOptions}, io::{self, Write}, os::unix::fs::OpenOptionsExt, time::{Duration, Instant}, collections::VecDeque};
+
+use log::{info, error};
+use virt_hid::key::{SpecialKey, Modifier};
+
+use crate::OrLog;
+
+use super::{backend::{requires_shift, read_leds}, hid::HIDError};
+
+/// Length of a nkro report, a modifier byte followed by a bit per keyboard usage
+const NKRO_REPORT_LEN: usize = 33;
+/// Length of a boot protocol report, modifiers, a reserved byte and up to 6 keys
+const BOOT_REPORT_LEN: usize = 8;
+/// Keys in a boot protocol report
+const BOOT_KEYS: usize = 6;
+/// Boot protocol key usage reported when more keys are held than fit in a report
+const ERROR_ROLL_OVER: u8 = 0x01;
+/// Left shift modifier bit
+const LEFT_SHIFT: u8 = 0x02;
+/// Highest keyboard usage declared by the keyboard and boot keyboard report descriptors
+const USAGE_MAXIMUM: u8 = 0xA4;
+/// Time a nkro report can go unread before falling back to boot protocol. The gadget interface does not
+/// pass SET_PROTOCOL requests on, so the protocol the host selected is never known and this is a guess,
+/// long enough for a host polling every 1-10ms to read a report but short enough that a held key is not lost
+const NKRO_TIMEOUT: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Default, Clone)]
+/// Keyboard state, the keys held, used to build nkro and boot protocol reports
+pub struct KeyboardState {
+    modifiers: u8,
+    /// Held usages in the order they were pressed, with whether the key needs shift
+    keys: Vec<(u8, bool)>,
+}
+
+impl KeyboardState {
+    /// Hold key
+    pub fn hold_key(&mut self, key: char) {
+        if let Some(usage) = char_to_usage(key) {
+            self.hold(usage, requires_shift(key));
+        }
+    }
+
+    /// Release key
+    pub fn release_key(&mut self, key: char) {
+        if let Some(usage) = char_to_usage(key) {
+            self.release(usage);
+        }
+    }
+
+    /// Hold special key
+    pub fn hold_special(&mut self, special: SpecialKey) {
+        if let Some(usage) = special_to_usage(special) {
+            self.hold(usage, false);
+        }
+    }
+
+    /// Release special key
+    pub fn release_special(&mut self, special: SpecialKey) {
+        if let Some(usage) = special_to_usage(special) {
+            self.release(usage);
+        }
+    }
+
+    /// Hold modifier
+    pub fn hold_modifier(&mut self, modifier: Modifier) {
+        self.modifiers |= modifier_bit(modifier);
+    }
+
+    /// Release modifier
+    pub fn release_modifier(&mut self, modifier: Modifier) {
+        self.modifiers &= !modifier_bit(modifier);
+    }
+
+    fn hold(&mut self, usage: u8, shift: bool) {
+        if usage > USAGE_MAXIMUM {
+            return;
+        }
+        if !self.keys.iter().any(|(held, _)| *held == usage) {
+            self.keys.push((usage, shift));
+        }
+    }
+
+    fn release(&mut self, usage: u8) {
+        self.keys.retain(|(held, _)| *held != usage);
+    }
+
+    /// Modifier byte, shift is held whilst a key that needs it is held
+    fn modifier_byte(&self) -> u8 {
+        if self.keys.iter().any(|(_, shift)| *shift) {
+            self.modifiers | LEFT_SHIFT
+        } else {
+            self.modifiers
+        }
+    }
+
+    /// Nkro report
+    pub fn nkro_report(&self) -> [u8; NKRO_REPORT_LEN] {
+        let mut report = [0; NKRO_REPORT_LEN];
+        report[0] = self.modifier_byte();
+        for (usage, _) in &self.keys {
+            report[1 + *usage as usize / 8] |= 1 << (usage % 8);
+        }
+        report
+    }
+
+    /// Boot protocol report, every key reports roll over if more than 6 keys are held
+    pub fn boot_report(&self) -> [u8; BOOT_REPORT_LEN] {
+        let mut report = [0; BOOT_REPORT_LEN];
+        report[0] = self.modifier_byte();
+        if self.keys.len() > BOOT_KEYS {
+            report[2..].fill(ERROR_ROLL_OVER);
+        } else {
+            for (i, (usage, _)) in self.keys.iter().enumerate() {
+                report[2 + i] = *usage;
+            }
+        }
+        report
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Keyboard report protocol the host is using
+enum Protocol {
+    Nkro,
+    Boot,
+}
+
+/// Nkro keyboard, sends nkro reports to the keyboard interface and falls back to the boot keyboard
+/// interface when the host stops reading nkro reports, as hosts using the boot protocol only read
+/// boot keyboard interfaces. Reports the host has not read yet are queued and retried when polled
+pub struct NkroKeyboard {
+    state: KeyboardState,
+    keyboard: File,
+    boot: File,
+    protocol: Protocol,
+    /// Nkro reports waiting for the host, oldest first
+    pending: VecDeque<[u8; NKRO_REPORT_LEN]>,
+    /// When the oldest pending report was first blocked
+    blocked: Option<Instant>,
+}
+
+impl NkroKeyboard {
+    /// New, requires paths to the keyboard and boot keyboard interfaces
+    pub fn new(keyboard: &str, boot: &str) -> Result<NkroKeyboard, HIDError> {
+        let open = |path: &str, read: bool| OpenOptions::new()
+            .read(read)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)
+            .map_err(|e| HIDError::IO(e));
+        Ok(NkroKeyboard {
+            state: KeyboardState::default(),
+            keyboard: open(keyboard, false)?,
+            boot: open(boot, true)?,
+            protocol: Protocol::Nkro,
+            pending: VecDeque::new(),
+            blocked: None,
+        })
+    }
+
+    /// Keyboard state
+    pub fn state(&mut self) -> &mut KeyboardState {
+        &mut self.state
+    }
+
+    /// Write the pending nkro reports until the host stops reading them
+    fn flush(&mut self) {
+        while let Some(report) = self.pending.front() {
+            match self.keyboard.write_all(report) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    self.blocked.get_or_insert_with(Instant::now);
+                    return;
+                },
+                res => {
+                    res.or_log("USB HID error (HID Driver)");
+                    self.pending.pop_front();
+                    self.blocked = None;
+                },
+            }
+        }
+    }
+
+    /// Send the keyboard state with the protocol the host is using
+    pub fn send(&mut self) {
+        match self.protocol {
+            Protocol::Nkro => {
+                self.pending.push_back(self.state.nkro_report());
+                self.flush();
+            },
+            // A nkro report is only attempted once, to not hold back boot reports
+            Protocol::Boot => match self.keyboard.write_all(&self.state.nkro_report()) {
+                Ok(()) => {
+                    info!("Host is reading nkro reports, leaving boot protocol (HID Driver)");
+                    self.protocol = Protocol::Nkro;
+                    self.write_boot(&[0; BOOT_REPORT_LEN]);
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => self.write_boot(&self.state.boot_report()),
+                Err(e) => error!("USB HID error (HID Driver), {}", e),
+            },
+        }
+    }
+
+    /// Retry the pending nkro reports, falling back to boot protocol once a report goes unread for too long.
+    /// Returns the last led report the host sent to the boot keyboard interface, hosts using the boot protocol
+    /// send their led reports there
+    pub fn poll(&mut self) -> io::Result<Option<u8>> {
+        self.flush();
+        if self.blocked.is_some_and(|blocked| blocked.elapsed() >= NKRO_TIMEOUT) {
+            info!("Host is not reading nkro reports, falling back to boot protocol (HID Driver)");
+            self.protocol = Protocol::Boot;
+            self.pending.clear();
+            self.blocked = None;
+            self.write_boot(&self.state.boot_report());
+        }
+
+        read_leds(&mut self.boot)
+    }
+
+    /// Write a boot report, dropped if the host has not read the last one
+    fn write_boot(&self, report: &[u8; BOOT_REPORT_LEN]) {
+        match (&self.boot).write_all(report) {
+            Err(e) if e.kind() != io::ErrorKind::WouldBlock => error!("USB HID error (HID Driver), {}", e),
+            _ => (),
+        }
+    }
+
+    /// Type a string, each character is pressed and released
+    pub fn type_str(&mut self, str: &str) {
+        for key in str.chars() {
+            self.state.hold_key(key);
+            self.send();
+            self.state.release_key(key);
+            self.send();
+        }
+    }
+}
+
+/// Modifier bit in the modifier byte of a report
+fn modifier_bit(modifier: Modifier) -> u8 {
+    match modifier {
+        Modifier::LeftControl => 0x01,
+        Modifier::LeftShift => 0x02,
+        Modifier::LeftAlt => 0x04,
+        Modifier::LeftMeta => 0x08,
+        Modifier::RightControl => 0x10,
+        Modifier::RightShift => 0x20,
+        Modifier::RightAlt => 0x40,
+        Modifier::RightMeta => 0x80,
+    }
+}
+
+/// Keyboard usage of a character, shifted characters share the usage of their key
+fn char_to_usage(key: char) -> Option<u8> {
+    Some(match key.to_ascii_lowercase() {
+        key @ 'a'..='z' => 0x04 + (key as u8 - b'a'),
+        '1' | '!' => 0x1E,
+        '2' | '@' => 0x1F,
+        '3' | '#' => 0x20,
+        '4' | '$' => 0x21,
+        '5' | '%' => 0x22,
+        '6' | '^' => 0x23,
+        '7' | '&' => 0x24,
+        '8' | '*' => 0x25,
+        '9' | '(' => 0x26,
+        '0' | ')' => 0x27,
+        '\n' => 0x28,
+        '\t' => 0x2B,
+        ' ' => 0x2C,
+        '-' | '_' => 0x2D,
+        '=' | '+' => 0x2E,
+        '[' | '{' => 0x2F,
+        ']' | '}' => 0x30,
+        '\\' | '|' => 0x31,
+        ';' | ':' => 0x33,
+        '\'' | '"' => 0x34,
+        '`' | '~' => 0x35,
+        ',' | '<' => 0x36,
+        '.' | '>' => 0x37,
+        '/' | '?' => 0x38,
+        _ => return None,
+    })
+}
+
+/// Keyboard usage of a special key
+fn special_to_usage(special: SpecialKey) -> Option<u8> {
+    Some(match special {
+        SpecialKey::ReturnEnter => 0x28,
+        SpecialKey::Escape => 0x29,
+        SpecialKey::Backspace => 0x2A,
+        SpecialKey::Tab => 0x2B,
+        SpecialKey::Spacebar => 0x2C,
+        SpecialKey::NONUSHashAndTilda => 0x32,
+        SpecialKey::CapsLock => 0x39,
+        SpecialKey::F1 => 0x3A,
+        SpecialKey::F2 => 0x3B,
+        SpecialKey::F3 => 0x3C,
+        SpecialKey::F4 => 0x3D,
+        SpecialKey::F5 => 0x3E,
+        SpecialKey::F6 => 0x3F,
+        SpecialKey::F7 => 0x40,
+        SpecialKey::F8 => 0x41,
+        SpecialKey::F9 => 0x42,
+        SpecialKey::F10 => 0x43,
+        SpecialKey::F11 => 0x44,
+        SpecialKey::F12 => 0x45,
+        SpecialKey::PrintScreen => 0x46,
+        SpecialKey::ScrollLock => 0x47,
+        SpecialKey::Pause => 0x48,
+        SpecialKey::Insert => 0x49,
+        SpecialKey::Home => 0x4A,
+        SpecialKey::PageUp => 0x4B,
+        SpecialKey::DeleteForward => 0x4C,
+        SpecialKey::End => 0x4D,
+        SpecialKey::PageDown => 0x4E,
+        SpecialKey::RightArrow => 0x4F,
+        SpecialKey::LeftArrow => 0x50,
+        SpecialKey::DownArrow => 0x51,
+        SpecialKey::UpArrow => 0x52,
+        SpecialKey::NumLockAndClear => 0x53,
+        SpecialKey::Enter => 0x58,
+        SpecialKey::NonUSSlashAndPipe => 0x64,
+        SpecialKey::Application => 0x65,
+        SpecialKey::Power => 0x66,
+        SpecialKey::EqualsSign => 0x67,
+        SpecialKey::F13 => 0x68,
+        SpecialKey::F14 => 0x69,
+        SpecialKey::F15 => 0x6A,
+        SpecialKey::F16 => 0x6B,
+        SpecialKey::F17 => 0x6C,
+        SpecialKey::F18 => 0x6D,
+        SpecialKey::F19 => 0x6E,
+        SpecialKey::F20 => 0x6F,
+        SpecialKey::F21 => 0x70,
+        SpecialKey::F22 => 0x71,
+        SpecialKey::F23 => 0x72,
+        SpecialKey::F24 => 0x73,
+        SpecialKey::Execute => 0x74,
+        SpecialKey::Help => 0x75,
+        SpecialKey::Menu => 0x76,
+        SpecialKey::Select => 0x77,
+        SpecialKey::Stop => 0x78,
+        SpecialKey::Again => 0x79,
+        SpecialKey::Undo => 0x7A,
+        SpecialKey::Cut => 0x7B,
+        SpecialKey::Copy => 0x7C,
+        SpecialKey::Paste => 0x7D,
+        SpecialKey::Find => 0x7E,
+        SpecialKey::Mute => 0x7F,
+        SpecialKey::VolumeUp => 0x80,
+        SpecialKey::VolumeDown => 0x81,
+        SpecialKey::Comma => 0x85,
+        SpecialKey::Return => 0x9E,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nkro_report_holds_every_key() {
+        let mut state = KeyboardState::default();
+        for key in "stkpwhr".chars() {
+            state.hold_key(key);
+        }
+        state.hold_modifier(Modifier::LeftControl);
+
+        let report = state.nkro_report();
+        assert_eq!(report[0], 0x01);
+        let held: Vec<u8> = (0..=u8::MAX).filter(|usage| report[1 + *usage as usize / 8] & (1 << (usage % 8)) != 0).collect();
+        assert_eq!(held, vec![0x07, 0x0B, 0x0E, 0x13, 0x15, 0x16, 0x1A]);
+    }
+
+    #[test]
+    fn boot_report_rolls_over() {
+        let mut state = KeyboardState::default();
+        state.hold_key('A');
+        assert_eq!(state.boot_report(), [LEFT_SHIFT, 0, 0x04, 0, 0, 0, 0, 0]);
+
+        for key in "bcdefg".chars() {
+            state.hold_key(key);
+        }
+        assert_eq!(state.boot_report(), [LEFT_SHIFT, 0, 1, 1, 1, 1, 1, 1]);
+
+        state.release_key('a');
+        state.release_key('g');
+        assert_eq!(state.boot_report(), [0, 0, 0x05, 0x06, 0x07, 0x08, 0x09, 0]);
+    }
+
+    #[test]
+    fn special_keys_fit_descriptor() {
+        let specials = [
+            SpecialKey::Undo, SpecialKey::Cut, SpecialKey::Copy, SpecialKey::Paste, SpecialKey::Find,
+            SpecialKey::Mute, SpecialKey::VolumeUp, SpecialKey::VolumeDown, SpecialKey::Comma, SpecialKey::Return,
+        ];
+        let mut state = KeyboardState::default();
+        for special in specials {
+            state.hold_special(special);
+        }
+
+        let report = state.nkro_report();
+        let held: Vec<u8> = (0..=u8::MAX).filter(|usage| report[1 + *usage as usize / 8] & (1 << (usage % 8)) != 0).collect();
+        assert_eq!(held, vec![0x7A, 0x7B, 0x7C, 0x7D, 0x7E, 0x7F, 0x80, 0x81, 0x85, 0x9E]);
+        assert!(held.iter().all(|usage| *usage <= USAGE_MAXIMUM));
+    }
+}
@@ -105,6 +105,11 @@ impl NanoMessenger {
         Ok((socket, endpoint))
     }
 
+    /// New, sending messages to a socket. Sending messages fails once the receiver is dropped
+    pub fn from_sender(tx: UnboundedSender<Vec<u8>>, pub_addr: String, sub_addr: String, timeout: isize) -> Arc<RwLock<NanoMessenger>> {
+        Arc::new(RwLock::new(NanoMessenger { tx, pub_addr, sub_addr, timeout }))
+    }
+
     /// New
     pub async fn new(pub_addr: String, sub_addr: String, timeout: isize) -> Result<Arc<RwLock<NanoMessenger>>, NanoMsgError> {
         let (tx, mut rx) = mpsc::unbounded_channel::<Vec<u8>>();
@@ -157,7 +162,7 @@ impl NanoMessenger {
         });
 
         if let Ok(res) = new_rx.await {
-            res.map(|_| Arc::new(RwLock::new(NanoMessenger{tx, pub_addr: paddr, sub_addr: saddr, timeout: time})))
+            res.map(|_| NanoMessenger::from_sender(tx, paddr, saddr, time))
                 .map_err(|e| NanoMsgError::Controller(e))
         } else {
             Err(NanoMsgError::ChannelError)
@@ -197,6 +202,7 @@ impl FunctionInterface for NanoMsg {
                 if let Some(driver) = self.driver_manager.read().await
                     .get(&driver_data.name)
                     .or_log_ignore(&format!("Unable to find driver {} (NanoMsg Function)", driver_data.name)) {
+                        let driver = driver.read().await;
                         let mut states = Vec::with_capacity(driver_data.idx.len());
                         for idx in &driver_data.idx {
                             states.push(driver.poll(*idx));
@@ -26,9 +26,8 @@ impl Output {
 impl FunctionInterface for Output {
     async fn event(&mut self, state: State) -> ReturnCommand {
         if state.rising(self.prev_state) {
-            let mut driver_manager = self.driver_manager.write().await;
-            if let Some(driver) = driver_manager.get_mut(&self.name) {
-                driver.set(*self.idx.data(), *self.state.data()).await;
+            if let Some(driver) = self.driver_manager.read().await.get(&self.name) {
+                driver.write().await.set(*self.idx.data(), *self.state.data()).await;
             }
         }
 
@@ -56,8 +55,8 @@ impl Flip {
 impl FunctionInterface for Flip {
     async fn event(&mut self, state: State) -> ReturnCommand {
         if state.rising(self.prev_state) {
-            let mut driver_manager = self.driver_manager.write().await;
-            if let Some(driver) = driver_manager.get_mut(&self.name) {
+            if let Some(driver) = self.driver_manager.read().await.get(&self.name) {
+                let mut driver = driver.write().await;
                 let mut state = driver.poll(*self.idx.data());
                 
                 if state == 0 {
@@ -0,0 +1,161 @@
+use std::{fs::{File, OpenOptions}, io::Write};
+
+use virt_hid::mouse::MouseDir;
+
+use crate::OrLog;
+
+use super::{hid::HIDError, mouse::Button};
+
+/// Length of a mouse report, a button byte followed by x, y, wheel and pan movement
+const MOUSE_REPORT_LEN: usize = 5;
+/// Resolution multiplier the mouse interface declares for the wheel and pan, hosts that enable it divide each report by it
+const RESOLUTION_MULTIPLIER: i32 = 8;
+
+#[derive(Debug, Default, Clone)]
+/// Mouse state, the buttons held and movement since the last report
+pub struct MouseState {
+    buttons: u8,
+    x: i8,
+    y: i8,
+    wheel: i8,
+    pan: i8,
+}
+
+impl MouseState {
+    /// Hold button
+    pub fn hold_button(&mut self, button: Button) {
+        self.buttons |= button_bit(button);
+    }
+
+    /// Release button
+    pub fn release_button(&mut self, button: Button) {
+        self.buttons &= !button_bit(button);
+    }
+
+    /// Move mouse
+    pub fn move_mouse(&mut self, amount: i8, dir: MouseDir) {
+        match dir {
+            MouseDir::X => self.x = self.x.saturating_add(amount),
+            MouseDir::Y => self.y = self.y.saturating_add(amount),
+        }
+    }
+
+    /// Scroll wheel
+    pub fn scroll_wheel(&mut self, amount: i8) {
+        self.wheel = self.wheel.saturating_add(amount);
+    }
+
+    /// Scroll horizontally
+    pub fn pan(&mut self, amount: i8) {
+        self.pan = self.pan.saturating_add(amount);
+    }
+
+    /// Mouse report, the movement is cleared once reported
+    pub fn report(&mut self) -> [u8; MOUSE_REPORT_LEN] {
+        let report = [self.buttons, self.x as u8, self.y as u8, self.wheel as u8, self.pan as u8];
+        *self = MouseState { buttons: self.buttons, ..Default::default() };
+        report
+    }
+}
+
+/// Usb mouse, sends mouse reports to the mouse interface
+pub struct UsbMouse {
+    state: MouseState,
+    mouse: File,
+    wheel_multiplier: i32,
+    /// Units per notch, the resolution multiplier if the host is known to enable it, otherwise 1
+    resolution: i32,
+    /// Wheel and pan movement not yet reported, in steps times the resolution
+    scroll: (i32, i32),
+}
+
+impl UsbMouse {
+    /// New, requires path to the mouse interface, the scroll steps per wheel notch and whether to scroll in
+    /// high-resolution units, which only scroll a notch on hosts that enable the resolution multiplier
+    pub fn new(mouse: &str, wheel_multiplier: u8, hi_res_scroll: bool) -> Result<UsbMouse, HIDError> {
+        let mouse = OpenOptions::new().write(true).open(mouse).map_err(|e| HIDError::IO(e))?;
+        let resolution = if hi_res_scroll { RESOLUTION_MULTIPLIER } else { 1 };
+        Ok(UsbMouse { state: MouseState::default(), mouse, wheel_multiplier: wheel_multiplier.max(1) as i32, resolution, scroll: (0, 0) })
+    }
+
+    /// Mouse state
+    pub fn state(&mut self) -> &mut MouseState {
+        &mut self.state
+    }
+
+    /// Scroll wheel, in steps
+    pub fn scroll_wheel(&mut self, amount: i8) {
+        let units = scale_steps(&mut self.scroll.0, amount, self.wheel_multiplier, self.resolution);
+        self.state.scroll_wheel(units);
+    }
+
+    /// Scroll horizontally, in steps
+    pub fn pan(&mut self, amount: i8) {
+        let units = scale_steps(&mut self.scroll.1, amount, self.wheel_multiplier, self.resolution);
+        self.state.pan(units);
+    }
+
+    /// Send the mouse state
+    pub fn send(&mut self) {
+        self.mouse.write_all(&self.state.report()).or_log("USB HID error (HID Driver)");
+    }
+}
+
+/// Scale scroll steps to units, `wheel_multiplier` steps scroll one notch of `resolution` units,
+/// the remainder is kept for the next scroll
+fn scale_steps(remainder: &mut i32, amount: i8, wheel_multiplier: i32, resolution: i32) -> i8 {
+    *remainder += amount as i32 * resolution;
+    let units = *remainder / wheel_multiplier;
+    *remainder -= units * wheel_multiplier;
+    units.clamp(i8::MIN as i32, i8::MAX as i32) as i8
+}
+
+/// Button bit in the button byte of a report, side and extra are buttons 4 and 5 (back and forward)
+fn button_bit(button: Button) -> u8 {
+    match button {
+        Button::Left => 0x01,
+        Button::Right => 0x02,
+        Button::Middle => 0x04,
+        Button::Side => 0x08,
+        Button::Extra => 0x10,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_clears_movement() {
+        let mut state = MouseState::default();
+        state.hold_button(Button::Side);
+        state.hold_button(Button::Left);
+        state.move_mouse(-3, MouseDir::X);
+        state.scroll_wheel(100);
+        state.scroll_wheel(100);
+        state.pan(-2);
+
+        assert_eq!(state.report(), [0x09, 0xFD, 0, 0x7F, 0xFE]);
+        state.release_button(Button::Left);
+        assert_eq!(state.report(), [0x08, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn steps_scale_to_notches() {
+        let mut remainder = 0;
+        assert_eq!(scale_steps(&mut remainder, 1, 1, 1), 1);
+        assert_eq!(scale_steps(&mut remainder, -2, 1, 1), -2);
+        assert_eq!(scale_steps(&mut remainder, 1, 2, 1), 0);
+        assert_eq!(scale_steps(&mut remainder, 1, 2, 1), 1);
+        assert_eq!(remainder, 0);
+
+        assert_eq!(scale_steps(&mut remainder, 1, 1, RESOLUTION_MULTIPLIER), 8);
+        assert_eq!(scale_steps(&mut remainder, -2, 1, RESOLUTION_MULTIPLIER), -16);
+        assert_eq!(scale_steps(&mut remainder, 3, 8, RESOLUTION_MULTIPLIER), 3);
+
+        assert_eq!(scale_steps(&mut remainder, 1, 3, RESOLUTION_MULTIPLIER), 2);
+        assert_eq!(scale_steps(&mut remainder, 1, 3, RESOLUTION_MULTIPLIER), 3);
+        assert_eq!(scale_steps(&mut remainder, 1, 3, RESOLUTION_MULTIPLIER), 3);
+        assert_eq!(remainder, 0);
+    }
+}
@@ -0,0 +1,218 @@
+use std::time::{Instant, Duration};
+
+use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
+
+use crate::variables::{Variable, Data};
+
+use super::{Function, FunctionInterface, ReturnCommand, FunctionType, State, StateHelpers, forward_event, PRESSED, RELEASED};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// How a tap hold decides it is being held before its timeout runs out
+pub enum TapHoldResolution {
+    /// Only the timeout decides
+    #[default]
+    Timeout,
+    /// Held if another key is pressed and released whilst undecided
+    PermissiveHold,
+    /// Held if another key is pressed whilst undecided
+    HoldOnOtherKeyPress,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Tap hold decision
+enum Decision {
+    Released,
+    Undecided,
+    Hold,
+}
+
+/// Tap hold function, runs the tap function if released before the timeout and the hold function otherwise
+pub struct TapHold {
+    tap: Function,
+    hold: Function,
+    timeout: Variable<Duration>,
+    resolution: TapHoldResolution,
+    decision: Decision,
+    pressed_at: Instant,
+    /// Grid indices of the keys pressed whilst undecided
+    interrupts: Vec<usize>,
+    prev_state: u16,
+}
+
+impl TapHold {
+    /// New
+    pub fn new(tap: Function, hold: Function, timeout: Variable<u64>, resolution: TapHoldResolution) -> Function {
+        let timeout: Variable<Duration> = timeout.map(|timeout| Duration::from_millis(timeout));
+        Some(Box::new(TapHold{
+            tap,
+            hold,
+            timeout,
+            resolution,
+            decision: Decision::Released,
+            pressed_at: Instant::now(),
+            interrupts: vec![],
+            prev_state: 0
+        }))
+    }
+
+    /// Resolve to hold, pressing the hold function
+    async fn resolve_hold(&mut self) -> ReturnCommand {
+        self.decision = Decision::Hold;
+        forward_event(&mut self.hold, PRESSED).await
+    }
+}
+
+#[async_trait]
+impl FunctionInterface for TapHold {
+    async fn event(&mut self, state: State) -> ReturnCommand {
+        let ret = if state.rising(self.prev_state) {
+            self.decision = Decision::Undecided;
+            self.pressed_at = Instant::now();
+            self.interrupts.clear();
+            ReturnCommand::None
+        } else if state.falling(self.prev_state) {
+            let decision = self.decision;
+            self.decision = Decision::Released;
+            match decision {
+                Decision::Undecided => ReturnCommand::Many(vec![
+                    forward_event(&mut self.tap, PRESSED).await,
+                    forward_event(&mut self.tap, RELEASED).await,
+                ]),
+                Decision::Hold => forward_event(&mut self.hold, state).await,
+                Decision::Released => ReturnCommand::None,
+            }
+        } else if self.decision == Decision::Hold {
+            forward_event(&mut self.hold, state).await
+        } else {
+            ReturnCommand::None
+        };
+
+        self.prev_state = state;
+        ret
+    }
+
+    async fn interrupt(&mut self, idx: usize, pressed: bool, _ftype: &FunctionType) -> ReturnCommand {
+        if self.decision != Decision::Undecided {
+            return ReturnCommand::None;
+        }
+
+        if pressed && !self.interrupts.contains(&idx) {
+            self.interrupts.push(idx);
+        }
+
+        match self.resolution {
+            TapHoldResolution::Timeout => ReturnCommand::None,
+            TapHoldResolution::HoldOnOtherKeyPress if pressed => self.resolve_hold().await,
+            // Only keys pressed after the tap hold count, keys already held may be released at any time
+            TapHoldResolution::PermissiveHold if !pressed && self.interrupts.contains(&idx) => self.resolve_hold().await,
+            _ => ReturnCommand::None,
+        }
+    }
+
+    async fn timeout(&mut self) -> ReturnCommand {
+        if self.decision == Decision::Undecided && self.pressed_at.elapsed() >= *self.timeout.data() {
+            self.resolve_hold().await
+        } else {
+            ReturnCommand::None
+        }
+    }
+
+    fn undecided(&self) -> bool {
+        self.decision == Decision::Undecided
+    }
+
+    fn ftype(&self) -> FunctionType {
+        let timeout: Data<Duration> = self.timeout.into_data();
+        FunctionType::TapHold{
+            tap: Box::new(FunctionType::from_function(&self.tap)),
+            hold: Box::new(FunctionType::from_function(&self.hold)),
+            timeout_ms: timeout.map(|timeout| timeout.as_millis() as u64),
+            resolution: self.resolution,
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use virt_hid::key::Modifier;
+
+    use crate::harness::{Harness, row_layout, keys, keyboard};
+
+    /// Layout with a tap hold, tapping a and holding shift, and a b key
+    fn tap_hold_layout(timeout_ms: u64, resolution: &str) -> String {
+        row_layout(json!([[[{"TapHold": {
+            "tap": {"Key": "a"},
+            "hold": {"Modifier": "LeftShift"},
+            "timeout_ms": {"Const": timeout_ms},
+            "resolution": resolution,
+        }}, {"Key": "b"}]]]))
+    }
+
+    #[tokio::test]
+    async fn taps_before_timeout() {
+        let mut harness = Harness::new(&tap_hold_layout(60000, "Timeout"), 2).await;
+
+        harness.press(0).await;
+        harness.tap(1).await;
+        harness.assert_reports(&[]).await;
+
+        harness.release(0).await;
+        harness.assert_reports(&[keys(&['a']), keys(&[]), keys(&['b']), keys(&[])]).await;
+    }
+
+    #[tokio::test]
+    async fn holds_after_timeout() {
+        let mut harness = Harness::new(&tap_hold_layout(0, "Timeout"), 2).await;
+
+        // Resolved by the scan the key was pressed in, no other key has to change
+        harness.press(0).await;
+        harness.assert_reports(&[keyboard(&[Modifier::LeftShift], &[])]).await;
+
+        harness.step().await;
+        harness.tap(1).await;
+        harness.release(0).await;
+        harness.assert_reports(&[
+            keyboard(&[Modifier::LeftShift], &['b']),
+            keyboard(&[Modifier::LeftShift], &[]),
+            keys(&[]),
+        ]).await;
+    }
+
+    #[tokio::test]
+    async fn holds_on_other_key_press() {
+        let mut harness = Harness::new(&tap_hold_layout(60000, "HoldOnOtherKeyPress"), 2).await;
+
+        harness.press(0).await;
+        harness.press(1).await;
+        harness.assert_reports(&[
+            keyboard(&[Modifier::LeftShift], &[]),
+            keyboard(&[Modifier::LeftShift], &['b']),
+        ]).await;
+
+        harness.release(1).await;
+        harness.release(0).await;
+        harness.assert_reports(&[keyboard(&[Modifier::LeftShift], &[]), keys(&[])]).await;
+    }
+
+    #[tokio::test]
+    async fn permissive_hold_ignores_keys_held_before() {
+        let mut harness = Harness::new(&tap_hold_layout(60000, "PermissiveHold"), 2).await;
+
+        harness.press(1).await;
+        harness.press(0).await;
+        harness.release(1).await;
+        harness.release(0).await;
+        harness.assert_reports(&[keys(&['b']), keys(&['b', 'a']), keys(&['b']), keys(&[])]).await;
+
+        harness.press(0).await;
+        harness.tap(1).await;
+        harness.release(0).await;
+        harness.assert_reports(&[
+            keyboard(&[Modifier::LeftShift], &[]),
+            keyboard(&[Modifier::LeftShift], &['b']),
+            keyboard(&[Modifier::LeftShift], &[]),
+            keys(&[]),
+        ]).await;
+    }
+}
@@ -0,0 +1,335 @@
+use std::{sync::{Arc, Mutex, mpsc as std_mpsc}, collections::HashMap, path::Path};
+
+use tempfile::TempDir;
+use tokio::sync::{RwLock, mpsc};
+use virt_hid::{key::{Modifier, SpecialKey}, mouse::MouseDir};
+
+use crate::{
+    driver::{DriverManager, Driver, led::Leds, sim::{SimDriver, SimScript, SimInput, Frame}},
+    function::{
+        FunctionBuilder, PRESSED, RELEASED,
+        hid::{HID, HidConfig},
+        backend::{HidBackend, requires_shift},
+        keyboard::MediaKey,
+        mouse::Button,
+        midi::MidiController,
+        cmd::CommandPool,
+        nng::NanoMessenger,
+    },
+    layout::{Layout, LayoutBuilder},
+    modules::ModuleManager,
+    variables::Variables,
+    events::Events,
+};
+
+/// Name of the simulation driver layouts under test are bound to
+pub const SIM: &str = "sim";
+
+/// Test harness, runs a layout against a simulation driver and records the hid reports it sends
+pub struct Harness {
+    pub layout: Arc<RwLock<Layout>>,
+    pub input: SimInput,
+    recorder: HidRecorder,
+    macros: TempDir,
+}
+
+impl Harness {
+    /// New, from layout json bound to a simulation driver named "sim" with a number of inputs
+    pub async fn new(layout: &str, inputs: usize) -> Harness {
+        let events = Events::new();
+        let (driver, input) = SimDriver::new(SimScript { inputs, ..Default::default() });
+        let driver: Driver = Box::new(driver);
+        let driver_manager = Arc::new(RwLock::new(DriverManager::new(HashMap::from([(SIM.to_string(), driver)]))));
+        let (hid, recorder) = recorder().await;
+        let macros = tempfile::tempdir().expect("Unable to create macros folder");
+
+        // Midi, commands and nanomsg are offline, their messages are dropped
+        let function_builder = FunctionBuilder::new(
+            hid,
+            MidiController::from_sender(mpsc::unbounded_channel().0),
+            Arc::new(RwLock::new(CommandPool::default())),
+            driver_manager.clone(),
+            NanoMessenger::from_sender(mpsc::unbounded_channel().0, String::new(), String::new(), 0),
+            ModuleManager::empty(events.clone()),
+            Variables::new(events.clone()),
+            macros.path().to_path_buf(),
+        );
+
+        let builder: LayoutBuilder = serde_json::from_str(layout).expect("Invalid test layout");
+        let layout = builder.build(driver_manager, function_builder, events).await;
+
+        Harness { layout, input, recorder, macros }
+    }
+
+    /// Folder macros are recorded to and played from, removed with the harness
+    pub fn macros(&self) -> &Path {
+        self.macros.path()
+    }
+
+    /// Run one iteration of the event loop, applying the next queued frame
+    pub async fn step(&mut self) {
+        self.layout.write().await.tick().await;
+        self.layout.write().await.poll().await;
+    }
+
+    /// Queue a frame and run it
+    pub async fn frame(&mut self, frame: Frame) {
+        self.input.queue(frame);
+        self.step().await;
+    }
+
+    /// Press an input
+    pub async fn press(&mut self, idx: usize) {
+        self.frame(vec![(idx, PRESSED)]).await;
+    }
+
+    /// Release an input
+    pub async fn release(&mut self, idx: usize) {
+        self.frame(vec![(idx, RELEASED)]).await;
+    }
+
+    /// Press then release an input
+    pub async fn tap(&mut self, idx: usize) {
+        self.press(idx).await;
+        self.release(idx).await;
+    }
+
+    /// Reports sent since the last call
+    pub async fn reports(&mut self) -> Vec<Report> {
+        self.recorder.reports().await
+    }
+
+    /// Assert the reports sent since the last call
+    pub async fn assert_reports(&mut self, expected: &[Report]) {
+        assert_eq!(self.reports().await, expected);
+    }
+}
+
+/// Keyboard report with keys held
+pub fn keys(keys: &[char]) -> Report {
+    keyboard(&[], keys)
+}
+
+/// Keyboard report with modifiers and keys held
+pub fn keyboard(modifiers: &[Modifier], keys: &[char]) -> Report {
+    Report::Keyboard(KeyboardReport { modifiers: modifiers.to_vec(), keys: keys.to_vec(), specials: vec![] })
+}
+
+/// Layout json with a single row bound to the inputs of the simulation driver
+pub fn row_layout(layers: serde_json::Value) -> String {
+    let width = layers[0][0].as_array().map(|row| row.len()).unwrap_or(0);
+    serde_json::json!({
+        "width": width,
+        "height": 1,
+        "bound": [{"DriverRow": {"name": SIM, "input": (0..width).collect::<Vec<usize>>(), "root": 0}}],
+        "layers": layers,
+    }).to_string()
+}
+
+/// Command a recorder backend answers instead of recording, once the hid thread has applied every earlier action
+const FLUSH: &str = "harness-flush";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// Keyboard report, the keys held when a keyboard report was sent
+pub struct KeyboardReport {
+    pub modifiers: Vec<Modifier>,
+    pub keys: Vec<char>,
+    pub specials: Vec<SpecialKey>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// Mouse report, the buttons held and movement since the last mouse report
+pub struct MouseReport {
+    pub buttons: Vec<Button>,
+    pub x: i32,
+    pub y: i32,
+    pub wheel: i32,
+    pub pan: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Report recorded by a hid recorder
+pub enum Report {
+    Keyboard(KeyboardReport),
+    Mouse(MouseReport),
+    /// Consumer control report, the media key held
+    Consumer(Option<MediaKey>),
+    /// Absolute pointer report, (x, y)
+    Absolute(u16, u16),
+    /// Command sent to a hid, (hid name, data)
+    Command(String, String),
+}
+
+/// Recorder backend, records the reports a hid would send instead of sending them
+struct RecorderBackend {
+    name: String,
+    keyboard: KeyboardReport,
+    mouse: MouseReport,
+    media: Vec<MediaKey>,
+    absolute: (u16, u16),
+    reports: Arc<Mutex<Vec<Report>>>,
+    flushed: std_mpsc::Sender<()>,
+}
+
+impl RecorderBackend {
+    /// Toggle a held value
+    fn hold<T: PartialEq>(held: &mut Vec<T>, value: T, hold: bool) {
+        held.retain(|v| *v != value);
+        if hold {
+            held.push(value);
+        }
+    }
+
+    /// Record a report
+    fn record(&self, report: Report) {
+        self.reports.lock().unwrap().push(report);
+    }
+
+    /// Press and release each character of a string
+    fn type_str(&mut self, str: &str) {
+        for key in str.chars() {
+            let mut report = self.keyboard.clone();
+            if requires_shift(key) {
+                Self::hold(&mut report.modifiers, Modifier::LeftShift, true);
+            }
+            Self::hold(&mut report.keys, key.to_ascii_lowercase(), true);
+            self.record(Report::Keyboard(report));
+            self.record(Report::Keyboard(self.keyboard.clone()));
+        }
+    }
+}
+
+impl HidBackend for RecorderBackend {
+    fn hold_key(&mut self, key: char) {
+        Self::hold(&mut self.keyboard.keys, key, true);
+    }
+
+    fn hold_special(&mut self, special: SpecialKey) {
+        Self::hold(&mut self.keyboard.specials, special, true);
+    }
+
+    fn hold_modifier(&mut self, modifier: Modifier) {
+        Self::hold(&mut self.keyboard.modifiers, modifier, true);
+    }
+
+    fn release_key(&mut self, key: char) {
+        Self::hold(&mut self.keyboard.keys, key, false);
+    }
+
+    fn release_special(&mut self, special: SpecialKey) {
+        Self::hold(&mut self.keyboard.specials, special, false);
+    }
+
+    fn release_modifier(&mut self, modifier: Modifier) {
+        Self::hold(&mut self.keyboard.modifiers, modifier, false);
+    }
+
+    fn press_basic_str(&mut self, str: &str) {
+        self.type_str(str);
+    }
+
+    fn press_str(&mut self, _layout: &str, str: &str) {
+        self.type_str(str);
+    }
+
+    fn scroll_wheel(&mut self, amount: i8) {
+        self.mouse.wheel += amount as i32;
+    }
+
+    fn pan(&mut self, amount: i8) {
+        self.mouse.pan += amount as i32;
+    }
+
+    fn move_mouse(&mut self, amount: i8, dir: MouseDir) {
+        match dir {
+            MouseDir::X => self.mouse.x += amount as i32,
+            MouseDir::Y => self.mouse.y += amount as i32,
+        }
+    }
+
+    fn hold_button(&mut self, button: Button) {
+        Self::hold(&mut self.mouse.buttons, button, true);
+    }
+
+    fn release_button(&mut self, button: Button) {
+        Self::hold(&mut self.mouse.buttons, button, false);
+    }
+
+    fn hold_media(&mut self, media: MediaKey) {
+        Self::hold(&mut self.media, media, true);
+        self.record(Report::Consumer(self.media.last().copied()));
+    }
+
+    fn release_media(&mut self, media: MediaKey) {
+        let last = self.media.last().copied();
+        Self::hold(&mut self.media, media, false);
+        if self.media.last().copied() != last {
+            self.record(Report::Consumer(self.media.last().copied()));
+        }
+    }
+
+    fn move_absolute(&mut self, x: Option<u16>, y: Option<u16>) {
+        self.absolute = (x.unwrap_or(self.absolute.0), y.unwrap_or(self.absolute.1));
+        self.record(Report::Absolute(self.absolute.0, self.absolute.1));
+    }
+
+    fn send_keyboard(&mut self) {
+        self.record(Report::Keyboard(self.keyboard.clone()));
+    }
+
+    fn send_mouse(&mut self) {
+        self.record(Report::Mouse(self.mouse.clone()));
+        self.mouse = MouseReport { buttons: self.mouse.buttons.clone(), ..Default::default() };
+    }
+
+    fn send_command(&mut self, data: String) {
+        if data == FLUSH {
+            self.flushed.send(()).ok();
+        } else {
+            self.record(Report::Command(self.name.clone(), data));
+        }
+    }
+}
+
+/// Hid recorder, the reports recorded by a recorder backend
+pub struct HidRecorder {
+    name: String,
+    hid: Arc<RwLock<HID>>,
+    reports: Arc<Mutex<Vec<Report>>>,
+    flushed: std_mpsc::Receiver<()>,
+}
+
+impl HidRecorder {
+    /// Reports recorded since the last call, waits for the hid thread without yielding to the runtime
+    /// so tasks and timers do not run whilst waiting
+    pub async fn reports(&self) -> Vec<Report> {
+        self.hid.read().await.send_command(self.name.clone(), FLUSH.to_string());
+        self.flushed.recv().expect("Hid thread stopped");
+        std::mem::take(&mut *self.reports.lock().unwrap())
+    }
+}
+
+/// New hid with a recorder backend registered under each name, the first is the current hid
+pub async fn recorders(names: &[&str], mirror: &[&str]) -> (Arc<RwLock<HID>>, Vec<HidRecorder>) {
+    let events = Events::new();
+    let config = HidConfig { mirror: mirror.iter().map(|name| name.to_string()).collect(), ..Default::default() };
+    let hid = HID::new(config, ModuleManager::empty(events.clone()), events, Leds::new());
+
+    let mut recorders = vec![];
+    for name in names {
+        let reports = Arc::new(Mutex::new(vec![]));
+        let (tx, flushed) = std_mpsc::channel();
+        let backend = RecorderBackend { name: name.to_string(), keyboard: KeyboardReport::default(), mouse: MouseReport::default(), media: vec![], absolute: (0, 0), reports: reports.clone(), flushed: tx };
+        hid.read().await.register(name, Box::new(backend));
+        recorders.push(HidRecorder { name: name.to_string(), hid: hid.clone(), reports, flushed });
+    }
+    hid.read().await.switch(names[0].to_string());
+
+    (hid, recorders)
+}
+
+/// New hid that sends its reports to a recorder
+pub async fn recorder() -> (Arc<RwLock<HID>>, HidRecorder) {
+    let (hid, mut recorders) = recorders(&["recorder"], &[]).await;
+    (hid, recorders.remove(0))
+}
@@ -1,39 +1,92 @@
-use std::{ops::{Range}, fmt::Display, sync::Arc };
+use std::{ops::{Range}, fmt::Display, sync::Arc, collections::VecDeque, time::{Instant, Duration}, mem };
 
 use itertools::Itertools;
 use serde::{Serialize, Deserialize, de};
 use slab::Slab;
 use tokio::{sync::{RwLock}};
 
-use crate::{function::{Function, ReturnCommand, FunctionType, FunctionBuilder}, driver::DriverManager};
+use crate::{function::{Function, FunctionType, FunctionBuilder, State, StateHelpers, forward_event, PRESSED, RELEASED}, driver::DriverManager, debounce::{Debounce, Debouncer}, events::Events};
+use key_rpc::Event;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 /// Driver state address. Used to index a state/s of a driver
-enum Address {
+pub enum Address {
     DriverMatrix {
         name: String,
         input: Range<usize>,
         width: usize,
         root: (usize, usize),
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        debounce: Option<Debounce>,
     },
     DriverRow {
         name: String,
         input: Vec<usize>,
         root: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        debounce: Option<Debounce>,
     },
     DriverColumn {
         name: String,
         input: Vec<usize>,
         root: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        debounce: Option<Debounce>,
     },
     DriverAddr {
         name: String,
         input: usize,
         root: (usize, usize),
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        debounce: Option<Debounce>,
     },
     None,
 }
 
+impl Address {
+    /// Name of the driver an address reads and the highest input it reads, none if it reads no driver
+    pub fn driver_input(&self) -> Option<(&str, Option<usize>)> {
+        match self {
+            Address::DriverMatrix { name, input, .. } => Some((name, input.end.checked_sub(1))),
+            Address::DriverRow { name, input, .. } |
+            Address::DriverColumn { name, input, .. } => Some((name, input.iter().max().copied())),
+            Address::DriverAddr { name, input, .. } => Some((name, Some(*input))),
+            Address::None => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Combo type, used for serializing combos
+pub struct ComboType {
+    /// Grid coordinates that must be pressed together
+    keys: Vec<(usize, usize)>,
+    /// Function to run instead of the functions bound to the keys
+    function: FunctionType,
+    /// Milliseconds the keys must all be pressed within
+    #[serde(default = "ComboType::default_window")]
+    window_ms: u64,
+}
+
+impl ComboType {
+    /// Default combo window
+    fn default_window() -> u64 {
+        50
+    }
+
+    /// Function run when the combo fires
+    pub fn function(&self) -> &FunctionType {
+        &self.function
+    }
+}
+
+/// Combo, a function bound to a set of grid positions pressed together
+struct Combo {
+    keys: Vec<usize>,
+    window: Duration,
+    func: Function,
+}
+
 #[derive(Debug)]
 /// Layout error
 pub enum LayoutError {
@@ -43,6 +96,14 @@ pub enum LayoutError {
     InUse,
     /// Address Matrix was of an invalid size
     InvalidSize,
+    /// Combo had less than two keys
+    InvalidCombo,
+    /// Layer does not exist
+    InvalidLayer,
+    /// Address reads a driver that is not loaded
+    UnknownDriver,
+    /// Address reads an input the driver does not have
+    InvalidInput,
 }
 
 impl Display for LayoutError {
@@ -51,6 +112,10 @@ impl Display for LayoutError {
             LayoutError::OutsideBounds => f.write_str("Binding outside bounds of layout"),
             LayoutError::InUse => f.write_str("Section already in use"),
             LayoutError::InvalidSize => f.write_str("Binding is an invalid size"),
+            LayoutError::InvalidCombo => f.write_str("Combo needs atleast two keys"),
+            LayoutError::InvalidLayer => f.write_str("Layer does not exist"),
+            LayoutError::UnknownDriver => f.write_str("Driver does not exist"),
+            LayoutError::InvalidInput => f.write_str("Driver input out of range"),
         }
     }
 }
@@ -61,7 +126,9 @@ pub struct LayoutBuilder {
     none: usize,
     addresses: Slab<Address>,
     layout: Vec<usize>,
-    layers: Vec<Vec<Option<FunctionType>>>
+    layers: Vec<Vec<Option<FunctionType>>>,
+    combos: Vec<Vec<ComboType>>,
+    default_layer: usize,
 }
 
 impl LayoutBuilder {
@@ -69,11 +136,64 @@ impl LayoutBuilder {
     pub fn new( width: usize, height: usize) -> LayoutBuilder {
         let mut addresses =  Slab::new();
         let none = addresses.insert(Address::None);
-        LayoutBuilder { width, height, none, addresses, layout: vec![none; width * height], layers: vec![] }
+        LayoutBuilder { width, height, none, addresses, layout: vec![none; width * height], layers: vec![], combos: vec![], default_layer: 0 }
+    }
+
+    /// Width
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Layers, each layer is stored row by row
+    pub fn layers(&self) -> &Vec<Vec<Option<FunctionType>>> {
+        &self.layers
+    }
+
+    /// Bound addresses
+    pub fn bound(&self) -> impl Iterator<Item = &Address> {
+        self.addresses.iter().map(|(_, address)| address)
+    }
+
+    /// Combos on each layer
+    pub fn combos(&self) -> &Vec<Vec<ComboType>> {
+        &self.combos
+    }
+
+    /// Layer active when the layout is loaded
+    pub fn default_layer(&self) -> usize {
+        self.default_layer
+    }
+
+    /// Replace the layers, combos on removed layers are dropped and the default layer is reset to the first
+    /// layer if it was removed
+    pub fn set_layers(&mut self, layers: Vec<Vec<Option<FunctionType>>>) {
+        self.combos.resize_with(layers.len(), Vec::new);
+        if self.default_layer >= layers.len() {
+            self.default_layer = 0;
+        }
+        self.layers = layers;
+    }
+
+    /// Check a combo is inside the bounds of the layout
+    fn check_combo(&self, combo: &ComboType) -> Result<(), LayoutError> {
+        if combo.keys.len() < 2 {
+            return Err(LayoutError::InvalidCombo)
+        }
+
+        if combo.keys.iter().any(|(x, y)| *x >= self.width || *y >= self.height) {
+            return Err(LayoutError::OutsideBounds)
+        }
+
+        Ok(())
     }
 
     /// Add bind point
-    pub fn add_point(&mut self, name: &str, idx: usize, location: (usize, usize)) -> Result<(), LayoutError> {
+    pub fn add_point(&mut self, name: &str, idx: usize, location: (usize, usize), debounce: Option<Debounce>) -> Result<(), LayoutError> {
         let (x, y) = location;
 
         if x >= self.width || y >= self.height {
@@ -86,13 +206,13 @@ impl LayoutBuilder {
             return Err(LayoutError::InUse);
         }
 
-        let id = self.addresses.insert(Address::DriverAddr { name: name.to_string(), input: idx, root: location });
+        let id = self.addresses.insert(Address::DriverAddr { name: name.to_string(), input: idx, root: location, debounce });
         self.layout[i] = id;
         Ok(())
     }
 
     /// Add a matrix of bind points
-    pub fn add_matrix(&mut self, name: &str, range: Range<usize>, width: usize, location: (usize, usize)) -> Result<(), LayoutError> {
+    pub fn add_matrix(&mut self, name: &str, range: Range<usize>, width: usize, location: (usize, usize), debounce: Option<Debounce>) -> Result<(), LayoutError> {
         let (x, y) = location;
         let size = range.len();
         let height =  size / width;
@@ -118,7 +238,7 @@ impl LayoutBuilder {
             i += self.width;
         }
 
-        let id = self.addresses.insert(Address::DriverMatrix { name: name.to_string(), input: range, width: width, root: location });
+        let id = self.addresses.insert(Address::DriverMatrix { name: name.to_string(), input: range, width: width, root: location, debounce });
 
         let mut i = x + y * self.width;
         
@@ -135,7 +255,12 @@ impl LayoutBuilder {
     }
 
     /// Build layout
-    pub async fn build(self, driver_manager: Arc<RwLock<DriverManager>>, function_builder: Arc<RwLock<FunctionBuilder>>) -> Arc<RwLock<Layout>> {
+    pub async fn build(self, driver_manager: Arc<RwLock<DriverManager>>, function_builder: Arc<RwLock<FunctionBuilder>>, events: Events) -> Arc<RwLock<Layout>> {
+        Arc::new(RwLock::new(self.build_layout(driver_manager, function_builder, events).await))
+    }
+
+    /// Build layout without wrapping it, used to replace a running layout
+    pub async fn build_layout(self, driver_manager: Arc<RwLock<DriverManager>>, function_builder: Arc<RwLock<FunctionBuilder>>, events: Events) -> Layout {
         let mut layer_stack = Vec::new();
         for layer in self.layers.into_iter() {
             let mut built_layer = Vec::new();
@@ -148,16 +273,45 @@ impl LayoutBuilder {
             }
             layer_stack.push(built_layer);
         }
-        Arc::new(RwLock::new(Layout { 
+        let mut combos = Vec::new();
+        for layer in self.combos.into_iter() {
+            combos.push(Layout::build_combos(self.width, layer, &*function_builder.read().await).await);
+        }
+        combos.resize_with(layer_stack.len(), Vec::new);
+        let debouncers = self.layout.iter()
+            .map(|id| match self.addresses.get(*id) {
+                Some(Address::DriverMatrix { debounce, .. }) |
+                Some(Address::DriverRow { debounce, .. }) |
+                Some(Address::DriverColumn { debounce, .. }) |
+                Some(Address::DriverAddr { debounce, .. }) => debounce.map(Debouncer::new),
+                _ => None,
+            })
+            .collect();
+        Layout { 
             width: self.width, 
             height: self.height, 
             addresses: self.addresses, 
             driver_manager: driver_manager, 
             function_builder: function_builder,
+            active_layers: vec![false; layer_stack.len()],
             layer_stack,
-            cur_layer: 0,
-            shift_from: vec![],
-        }))
+            default_layer: self.default_layer,
+            combos,
+            chord: vec![],
+            chord_start: Instant::now(),
+            active_combos: vec![],
+            suppressed: vec![false; self.width * self.height],
+            prev_states: vec![0; self.width * self.height],
+            debouncers,
+            undecided: None,
+            deferred: VecDeque::new(),
+            key_states: vec![0; self.width * self.height],
+            pressed_on: vec![None; self.width * self.height],
+            origin: None,
+            watching: vec![],
+            events,
+            reported_layer: self.default_layer,
+        }
     }
 }
 
@@ -173,6 +327,8 @@ impl Serialize for LayoutBuilder {
             height: usize,
             bound: Vec<&'a Address>,
             layers: Vec<Vec<Vec<Option<FunctionType>>>>,
+            combos: &'a Vec<Vec<ComboType>>,
+            default_layer: usize,
         }
         let layers: Vec<Vec<Vec<Option<FunctionType>>>> = self.layers.iter()
             .map(|layer| {
@@ -184,7 +340,7 @@ impl Serialize for LayoutBuilder {
                 .collect::<Vec<Vec<Option<FunctionType>>>>()
         }).collect();
         let bound = self.addresses.iter().map(|(_, a)| a).collect::<Vec<&Address>>();    
-        Layout{width: self.width, height: self.height, bound, layers: layers}.serialize(serializer)
+        Layout{width: self.width, height: self.height, bound, layers: layers, combos: &self.combos, default_layer: self.default_layer}.serialize(serializer)
     }
 }
 
@@ -199,30 +355,34 @@ impl<'de> Deserialize<'de> for LayoutBuilder {
             height: usize,
             bound: Vec<Address>,
             layers: Vec<Vec<Vec<Option<FunctionType>>>>,
+            #[serde(default)]
+            combos: Vec<Vec<ComboType>>,
+            #[serde(default)]
+            default_layer: usize,
         }
         let layout = Layout::deserialize(deserializer)?;
         let mut builder = LayoutBuilder::new(layout.width, layout.height);
 
         for (i, address) in layout.bound.into_iter().enumerate() {
            match address {
-                Address::DriverMatrix { name, input, width, root } => 
-                    if let Err(e) = builder.add_matrix(&name, input, width, root) {
+                Address::DriverMatrix { name, input, width, root, debounce } => 
+                    if let Err(e) = builder.add_matrix(&name, input, width, root, debounce) {
                         return Err(de::Error::custom(format!("Error adding bound address at {}, {}", i, e)))
                     },
-                Address::DriverRow { name, input, root } => 
+                Address::DriverRow { name, input, root, debounce } => 
                     for (x, input) in input.iter().enumerate() {
-                        if let Err(e) = builder.add_point(&name, *input, (x, root)) {
+                        if let Err(e) = builder.add_point(&name, *input, (x, root), debounce) {
                             return Err(de::Error::custom(format!("Error adding bound address at {}, {}", i, e)))
                         }
                     },
-                Address::DriverColumn { name, input, root } => 
+                Address::DriverColumn { name, input, root, debounce } => 
                     for (x, input) in input.iter().enumerate() {
-                        if let Err(e) = builder.add_point(&name, *input, (root, x)) {
+                        if let Err(e) = builder.add_point(&name, *input, (root, x), debounce) {
                             return Err(de::Error::custom(format!("Error adding bound address at {}, {}", i, e)))
                         }
                     },
-                Address::DriverAddr { name, input, root } => 
-                    if let Err(e) = builder.add_point(&name, input, root) {
+                Address::DriverAddr { name, input, root, debounce } => 
+                    if let Err(e) = builder.add_point(&name, input, root, debounce) {
                         return Err(de::Error::custom(format!("Error adding bound address at {}, {}", i, e)))
                     },
                 Address::None => continue,
@@ -242,6 +402,22 @@ impl<'de> Deserialize<'de> for LayoutBuilder {
             }
             builder.layers.push(new_layer);
         }
+        if layout.combos.len() > builder.layers.len() {
+            return Err(de::Error::custom("There are more combo layers than layers."))
+        }
+        for (i, layer) in layout.combos.iter().enumerate() {
+            for (j, combo) in layer.iter().enumerate() {
+                if let Err(e) = builder.check_combo(combo) {
+                    return Err(de::Error::custom(format!("Error adding combo {} on layer {}, {}", j, i, e)))
+                }
+            }
+        }
+        builder.combos = layout.combos;
+        builder.combos.resize_with(builder.layers.len(), Vec::new);
+        if layout.default_layer > 0 && layout.default_layer >= builder.layers.len() {
+            return Err(de::Error::custom(format!("Default layer {} does not exist.", layout.default_layer)))
+        }
+        builder.default_layer = layout.default_layer;
 
         Ok(builder)
     }
@@ -257,77 +433,122 @@ pub struct Layout {
     function_builder: Arc<RwLock<FunctionBuilder>>,
 
     layer_stack: Vec<Vec<Function>>,
-    cur_layer: usize,
-
-    shift_from: Vec<(usize, usize)>,
+    active_layers: Vec<bool>,
+    default_layer: usize,
+
+    combos: Vec<Vec<Combo>>,
+    chord: Vec<(usize, State)>,
+    chord_start: Instant,
+    active_combos: Vec<(usize, usize)>,
+    suppressed: Vec<bool>,
+
+    prev_states: Vec<State>,
+    debouncers: Vec<Option<Debouncer>>,
+    undecided: Option<(usize, usize)>,
+    deferred: VecDeque<(usize, State)>,
+
+    key_states: Vec<State>,
+    pressed_on: Vec<Option<usize>>,
+    origin: Option<(usize, usize)>,
+    watching: Vec<(usize, usize)>,
+
+    events: Events,
+    reported_layer: usize,
 }
 
 impl Layout {
-    /// Switch layout
+    /// True if a layer is the default layer or has been activated
+    fn is_active(&self, index: usize) -> bool {
+        index == self.default_layer || self.active_layers.get(index).map(|active| *active).unwrap_or(false)
+    }
+
+    /// Active layers, in priority order (lowest first)
+    pub fn active_layers(&self) -> Vec<usize> {
+        (0..self.layer_stack.len()).filter(|layer| self.is_active(*layer)).collect()
+    }
+
+    /// Publish the highest active layer if it changed
+    fn notify_layer(&mut self) {
+        let layer = self.curr_layer();
+        if layer != self.reported_layer {
+            self.reported_layer = layer;
+            self.events.send(Event::Layer(layer));
+        }
+    }
+
+    /// Switch layout, deactivating every layer other than the default layer and the given layer
     pub fn switch_layer(&mut self,  index: usize) -> Option<()> {
         if index >= self.layer_stack.len() {
             None
         } else {
-            self.cur_layer = index;
+            self.active_layers.iter_mut().for_each(|active| *active = false);
+            self.active_layers[index] = true;
+            self.notify_layer();
             Some(())
         }
     }
 
-    /// Move one layer above
+    /// Switch to the layer above the highest active layer
     pub fn up_layer(&mut self) -> Option<()> {
-        if self.cur_layer + 1 >= self.layer_stack.len() {
+        let cur_layer = self.curr_layer();
+        if cur_layer + 1 >= self.layer_stack.len() {
             None
         } else {
-            self.cur_layer += 1;
-            Some(())
+            self.switch_layer(cur_layer + 1)
         }
     }
 
-    /// Move one layer down
+    /// Switch to the layer below the highest active layer
     pub fn down_layer(&mut self) -> Option<()> {
-        if self.cur_layer - 1 >= self.layer_stack.len() {
+        let cur_layer = self.curr_layer();
+        if cur_layer == 0 {
             None
         } else {
-            self.cur_layer -= 1;
-            Some(())
+            self.switch_layer(cur_layer - 1)
         }
     }
 
-
+    /// Activate a layer until it is unshifted
     pub fn shift(&mut self, index: usize) -> Option<()> {
         if index >= self.layer_stack.len() {
             None
         } else {
-            self.shift_from.push((self.cur_layer, index));
-            self.cur_layer = index;
+            self.active_layers[index] = true;
+            self.notify_layer();
             Some(())
         }
     }
 
+    /// Deactivate a shifted layer
     pub fn unshift(&mut self, index: usize) -> Option<()> {
-        if index >= self.layer_stack.len() || self.shift_from.len() == 0  {
+        if index >= self.layer_stack.len() || !self.active_layers[index] {
             None
         } else {
-            if let Some((start, curr)) = self.shift_from.last() {
-                if *curr == index {
-                    self.switch_layer(*start);
-                    self.shift_from.pop();
-                    return Some(())
-                }
-            }
-            let mut i = self.shift_from.len() -1;
-            loop {
-                let (_, curr) = self.shift_from[i];
-                if curr == index {
-                    self.shift_from.remove(i);
-                    return Some(())
-                }
-                if i == 0 {
-                    break;
-                }
-                i -= 1;
-            }
+            self.active_layers[index] = false;
+            self.notify_layer();
+            Some(())
+        }
+    }
+
+    /// Turn a layer on or off
+    pub fn toggle_layer(&mut self, index: usize) -> Option<()> {
+        if index >= self.layer_stack.len() {
             None
+        } else {
+            self.active_layers[index] = !self.active_layers[index];
+            self.notify_layer();
+            Some(())
+        }
+    }
+
+    /// Set the default layer, the layer always active below every other active layer
+    pub fn set_default_layer(&mut self, index: usize) -> Option<()> {
+        if index >= self.layer_stack.len() {
+            None
+        } else {
+            self.default_layer = index;
+            self.notify_layer();
+            Some(())
         }
     }
 
@@ -338,7 +559,23 @@ impl Layout {
         }
 
         let layer = self.layer_stack.remove(index);
+        self.active_layers.remove(index);
+        if self.default_layer >= index && self.default_layer != 0 {
+            self.default_layer -= 1;
+        }
+        self.pressed_on.iter_mut().for_each(|pressed_on| {
+            *pressed_on = match *pressed_on {
+                Some(layer) if layer == index => None,
+                Some(layer) if layer > index => Some(layer - 1),
+                pressed_on => pressed_on,
+            }
+        });
+        self.combos.remove(index);
+        self.active_combos.clear();
+        self.undecided = None;
+        self.watching.clear();
 
+        self.notify_layer();
         Some(layer)
     }
 
@@ -347,9 +584,17 @@ impl Layout {
         self.layer_stack.len()
     }
 
-    /// Remove layer
+    /// Highest active layer
     pub fn curr_layer(&self) -> usize {
-        self.cur_layer
+        (0..self.layer_stack.len())
+            .rev()
+            .find(|layer| self.is_active(*layer))
+            .unwrap_or(self.default_layer)
+    }
+
+    /// Default layer
+    pub fn default_layer(&self) -> usize {
+        self.default_layer
     }
 
     /// Number of layers
@@ -357,8 +602,8 @@ impl Layout {
         self.layer_stack.len()
     }
 
-    /// Add layer
-    pub async fn add_layer(&mut self, layer: Vec<Vec<FunctionType>>, index: usize) -> Result<usize, LayoutError> {
+    /// Build a layer, padding short rows and layers with no function
+    async fn build_layer(&self, layer: Vec<Vec<FunctionType>>) -> Result<Vec<Function>, LayoutError> {
         if layer.len() > self.height {
             return Err(LayoutError::InvalidSize)
         }
@@ -371,29 +616,241 @@ impl Layout {
             if row.len() > self.width {
                 return Err(LayoutError::InvalidSize)
             }
+            let padding = self.width - row.len();
             for ftype in row {
                 built_layer.push(function_builder.build(ftype).await)
             }
+            built_layer.extend((0..padding).map(|_| None));
         }
+        built_layer.resize_with(self.width*self.height, || None);
+
+        Ok(built_layer)
+    }
+
+    /// Add layer
+    pub async fn add_layer(&mut self, layer: Vec<Vec<FunctionType>>, index: usize) -> Result<usize, LayoutError> {
+        let built_layer = self.build_layer(layer).await?;
 
-        if index > self.layer_stack.len() {
+        if index >= self.layer_stack.len() {
             self.layer_stack.push(built_layer);
-            Ok(self.layer_stack.len())
+            self.active_layers.push(false);
+            self.combos.push(vec![]);
+            self.notify_layer();
+            Ok(self.layer_stack.len() - 1)
         } else {
             self.layer_stack.insert(index, built_layer);
+            self.active_layers.insert(index, false);
+            if self.default_layer >= index && self.layer_stack.len() > 1 {
+                self.default_layer += 1;
+            }
+            self.pressed_on.iter_mut()
+                .filter_map(|pressed_on| pressed_on.as_mut())
+                .filter(|layer| **layer >= index)
+                .for_each(|layer| *layer += 1);
+            self.combos.insert(index, vec![]);
+            self.notify_layer();
             Ok(index)
         }
     }
 
+    /// Replace a layer
+    pub async fn set_layer(&mut self, layer: Vec<Vec<FunctionType>>, index: usize) -> Result<(), LayoutError> {
+        if index >= self.layer_stack.len() {
+            return Err(LayoutError::InvalidLayer)
+        }
+
+        let built_layer = self.build_layer(layer).await?;
+
+        for idx in 0..built_layer.len() {
+            self.release_binding(index, idx).await;
+        }
+        self.layer_stack[index] = built_layer;
+        self.undecided = self.undecided.filter(|(layer, _)| *layer != index);
+        self.watching.retain(|(layer, _)| *layer != index);
+
+        Ok(())
+    }
+
+    /// Bind a function to a grid coordinate on a layer, a function type of None clears the binding
+    pub async fn set_binding(&mut self, index: usize, location: (usize, usize), ftype: FunctionType) -> Result<(), LayoutError> {
+        let (x, y) = location;
+
+        if index >= self.layer_stack.len() {
+            return Err(LayoutError::InvalidLayer)
+        }
+
+        if x >= self.width || y >= self.height {
+            return Err(LayoutError::OutsideBounds)
+        }
+
+        let idx = x + y * self.width;
+        let func = self.function_builder.read().await.build(ftype).await;
+
+        self.release_binding(index, idx).await;
+        self.layer_stack[index][idx] = func;
+        self.undecided = self.undecided.filter(|undecided| *undecided != (index, idx));
+        self.watching.retain(|watching| *watching != (index, idx));
+
+        Ok(())
+    }
+
+    /// Release a held key if it was pressed on the binding being replaced
+    async fn release_binding(&mut self, index: usize, idx: usize) {
+        if self.pressed_on[idx] != Some(index) || !self.key_states[idx].high() {
+            return;
+        }
+
+        self.pressed_on[idx] = None;
+
+        let res = forward_event(&mut self.layer_stack[index][idx], RELEASED).await;
+        self.origin = Some((index, idx));
+        res.eval(self);
+    }
+
+    /// Move a layer to a new index, shifting the layers in between
+    pub fn move_layer(&mut self, from: usize, to: usize) -> Option<()> {
+        if from >= self.layer_stack.len() || to >= self.layer_stack.len() {
+            return None;
+        }
+
+        let moved = |layer: usize| {
+            if layer == from {
+                to
+            } else {
+                let layer = if layer > from { layer - 1 } else { layer };
+                if layer >= to { layer + 1 } else { layer }
+            }
+        };
+
+        let layer = self.layer_stack.remove(from);
+        self.layer_stack.insert(to, layer);
+        let active = self.active_layers.remove(from);
+        self.active_layers.insert(to, active);
+        let combos = self.combos.remove(from);
+        self.combos.insert(to, combos);
+
+        self.default_layer = moved(self.default_layer);
+        self.pressed_on.iter_mut()
+            .filter_map(|pressed_on| pressed_on.as_mut())
+            .for_each(|layer| *layer = moved(*layer));
+        self.active_combos.iter_mut().for_each(|(layer, _)| *layer = moved(*layer));
+        self.watching.iter_mut().for_each(|(layer, _)| *layer = moved(*layer));
+        if let Some((layer, _)) = &mut self.undecided {
+            *layer = moved(*layer);
+        }
+
+        self.notify_layer();
+        Some(())
+    }
+
+    /// Grid indices bound by an address
+    fn address_points(&self, address: &Address) -> Result<Vec<usize>, LayoutError> {
+        let points = match address {
+            Address::DriverMatrix { input, width, root, .. } => {
+                if *width == 0 || input.len() % width != 0 {
+                    return Err(LayoutError::InvalidSize);
+                }
+                (0..input.len()).map(|i| (root.0 + i % width, root.1 + i / width)).collect_vec()
+            },
+            Address::DriverRow { input, root, .. } => (0..input.len()).map(|x| (x, *root)).collect_vec(),
+            Address::DriverColumn { input, root, .. } => (0..input.len()).map(|y| (*root, y)).collect_vec(),
+            Address::DriverAddr { root, .. } => vec![*root],
+            Address::None => vec![],
+        };
+
+        if points.iter().any(|(x, y)| *x >= self.width || *y >= self.height) {
+            return Err(LayoutError::OutsideBounds);
+        }
+
+        Ok(points.into_iter().map(|(x, y)| x + y * self.width).collect())
+    }
+
+    /// Driver addresses bound to the layout
+    pub fn addresses(&self) -> Vec<&Address> {
+        self.addresses.iter().map(|(_, address)| address).collect()
+    }
+
+    /// Bind a driver address to the layout, the driver must be loaded and have the inputs read
+    pub async fn add_address(&mut self, address: Address) -> Result<(), LayoutError> {
+        if let Some((name, last)) = address.driver_input() {
+            let driver_manager = self.driver_manager.read().await;
+            let driver = driver_manager.get(name).ok_or(LayoutError::UnknownDriver)?;
+            let len = driver.read().await.iter().len();
+            if last.is_some_and(|last| last >= len) {
+                return Err(LayoutError::InvalidInput);
+            }
+        }
+
+        let points = self.address_points(&address)?;
+
+        for (_, bound) in self.addresses.iter() {
+            let bound = self.address_points(bound)?;
+            if points.iter().any(|point| bound.contains(point)) {
+                return Err(LayoutError::InUse);
+            }
+        }
+
+        let debounce = match &address {
+            Address::DriverMatrix { debounce, .. } |
+            Address::DriverRow { debounce, .. } |
+            Address::DriverColumn { debounce, .. } |
+            Address::DriverAddr { debounce, .. } => *debounce,
+            Address::None => None,
+        };
+
+        for idx in points {
+            self.debouncers[idx] = debounce.map(Debouncer::new);
+        }
+        self.addresses.insert(address);
+
+        Ok(())
+    }
+
+    /// Unbind the driver address bound at a grid coordinate, releasing its held keys.
+    /// None if no address is bound there or the coordinate is outside the layout
+    pub async fn remove_address(&mut self, location: (usize, usize)) -> Option<Address> {
+        let (x, y) = location;
+        if x >= self.width || y >= self.height {
+            return None
+        }
+        let idx = x + y * self.width;
+
+        let (id, points) = self.addresses.iter()
+            .find_map(|(id, address)| {
+                self.address_points(address).ok()
+                    .filter(|points| points.contains(&idx))
+                    .map(|points| (id, points))
+            })?;
+
+        for idx in points {
+            self.debouncers[idx] = None;
+
+            if !self.prev_states[idx].high() {
+                continue;
+            }
+            self.prev_states[idx] = RELEASED;
+            self.chord.retain(|(chorded, _)| *chorded != idx);
+            self.deferred.retain(|(deferred, _)| *deferred != idx);
+
+            if self.suppressed[idx] {
+                self.combo_event(idx, RELEASED).await;
+            } else {
+                self.event(idx, RELEASED).await;
+            }
+        }
+
+        Some(self.addresses.remove(id))
+    }
+
     /// Tick drivers
     pub async fn tick(&mut self) {
-        self.driver_manager.write().await.tick().await;
+        self.driver_manager.read().await.tick().await;
     }
 
     /// Get string representing layout
     pub fn layout_string(&self) -> Option<String> {
         serde_json::to_string(
-            &self.layer_stack[self.cur_layer].iter()
+            &self.layer_stack.get(self.curr_layer())?.iter()
                 .map(|func| {
                     func.as_ref().map(|func| func.ftype())
                 })
@@ -417,153 +874,386 @@ impl Layout {
         ).ok()
     }
 
+    /// Build a layers combos
+    async fn build_combos(width: usize, combos: Vec<ComboType>, function_builder: &FunctionBuilder) -> Vec<Combo> {
+        let mut built = Vec::with_capacity(combos.len());
+        for combo in combos {
+            built.push(Combo {
+                keys: combo.keys.iter().map(|(x, y)| x + y * width).collect(),
+                window: Duration::from_millis(combo.window_ms),
+                func: function_builder.build(combo.function).await,
+            });
+        }
+        built
+    }
+
+    /// Combos of the active layers, with their layer and index
+    fn active_layer_combos(&self) -> impl Iterator<Item = (usize, usize, &Combo)> {
+        self.combos
+            .iter()
+            .enumerate()
+            .filter(|(layer, _)| self.is_active(*layer))
+            .flat_map(|(layer, combos)| combos.iter().enumerate().map(move |(i, combo)| (layer, i, combo)))
+    }
+
+    /// True if a grid index is part of a combo on an active layer
+    fn in_combo(&self, idx: usize) -> bool {
+        self.active_layer_combos().any(|(_, _, combo)| combo.keys.contains(&idx))
+    }
+
+    /// Fire a combo if the chord matches one, or give up on the chord if it can't
+    async fn resolve_chord(&mut self) {
+        let candidates = self.active_layer_combos()
+            .filter(|(_, _, combo)| self.chord.iter().all(|(idx, _)| combo.keys.contains(idx)))
+            .map(|(layer, i, combo)| (layer, i, combo.keys.len(), combo.window))
+            .collect_vec();
+
+        let matched = candidates.iter().find(|(_, _, len, _)| *len == self.chord.len());
+        let window = candidates.iter().map(|(_, _, _, window)| *window).max();
+
+        if let Some(&(layer, i, _, _)) = matched {
+            self.fire_combo(layer, i).await;
+        } else if window.map(|window| self.chord_start.elapsed() > window).unwrap_or(true) {
+            self.break_chord().await;
+        }
+    }
+
+    /// Press a combo, suppressing its keys until they are released
+    async fn fire_combo(&mut self, layer: usize, i: usize) {
+        self.chord.clear();
+        self.active_combos.push((layer, i));
+
+        let combo = &mut self.combos[layer][i];
+        for key in &combo.keys {
+            self.suppressed[*key] = true;
+        }
+
+        let res = forward_event(&mut combo.func, PRESSED).await;
+        self.origin = None;
+        res.eval(self);
+    }
+
+    /// Dispatch the keys held for a chord as normal key presses
+    async fn break_chord(&mut self) {
+        for (idx, state) in mem::take(&mut self.chord) {
+            self.dispatch(idx, state, true).await;
+        }
+    }
+
+    /// State of a key suppressed by a combo, releasing the combo when any of its keys are released
+    async fn combo_event(&mut self, idx: usize, state: State) {
+        if state.low() {
+            self.suppressed[idx] = false;
+        }
+
+        let Some(i) = self.active_combos.iter().position(|(layer, combo)| {
+            self.combos.get(*layer)
+                .and_then(|combos| combos.get(*combo))
+                .map(|combo| combo.keys.contains(&idx))
+                .unwrap_or(false)
+        }) else {
+            return;
+        };
+
+        let (layer, combo) = self.active_combos[i];
+        let combo = &mut self.combos[layer][combo];
+
+        let res = if state.low() {
+            self.active_combos.remove(i);
+            forward_event(&mut combo.func, RELEASED).await
+        } else if combo.keys.first() == Some(&idx) {
+            forward_event(&mut combo.func, state).await
+        } else {
+            return;
+        };
+
+        self.origin = None;
+        res.eval(self);
+    }
+
+    /// Dispatch a key state, holding it back if a function is undecided
+    async fn dispatch(&mut self, idx: usize, state: State, edge: bool) {
+        match self.undecided {
+            Some((_, undecided)) if undecided != idx && (edge || self.deferred.iter().any(|(deferred, _)| *deferred == idx)) => {
+                self.deferred.push_back((idx, state));
+                if edge {
+                    self.interrupt(idx, state.high()).await;
+                }
+            },
+            _ => self.event(idx, state).await,
+        }
+
+        self.flush().await;
+    }
+
+    /// Find the highest active layer with a function bound at a grid index, positions without a function are transparent
+    fn find(&self, idx: usize) -> Option<usize> {
+        (0..self.layer_stack.len())
+            .rev()
+            .filter(|layer| self.is_active(*layer))
+            .find(|layer| matches!(self.layer_stack.get(*layer).and_then(|layer| layer.get(idx)), Some(Some(_))))
+    }
+
+    /// Pass a state to the function bound at a grid index and evaluate its return,
+    /// a key pressed on a layer keeps going to that layer until it is released
+    async fn event(&mut self, idx: usize, state: State) {
+        let rising = state.rising(self.key_states[idx]);
+        self.key_states[idx] = state;
+
+        let layer = if rising {
+            self.pressed_on[idx] = self.find(idx);
+            self.pressed_on[idx]
+        } else if state.high() {
+            self.pressed_on[idx].or_else(|| self.find(idx))
+        } else {
+            self.pressed_on[idx].take().or_else(|| self.find(idx))
+        };
+
+        let Some(layer) = layer else {
+            return;
+        };
+
+        let Some(func) = &mut self.layer_stack[layer][idx] else {
+            return;
+        };
+
+        let res = func.event(state).await;
+
+        if func.undecided() {
+            self.undecided = Some((layer, idx));
+        } else if matches!(self.undecided, Some((_, undecided)) if undecided == idx) {
+            self.undecided = None;
+        }
+
+        self.origin = Some((layer, idx));
+        res.eval(self);
+
+        if rising {
+            self.next_key(idx).await;
+        }
+    }
+
+    /// Tell the functions watching for the next key press that a key at a grid index was pressed
+    async fn next_key(&mut self, idx: usize) {
+        let (watching, notify): (Vec<_>, Vec<_>) = mem::take(&mut self.watching)
+            .into_iter()
+            .partition(|(_, watching)| *watching == idx);
+        self.watching = watching;
+
+        for (layer, watching) in notify {
+            let Some(Some(func)) = self.layer_stack.get_mut(layer).and_then(|layer| layer.get_mut(watching)) else {
+                continue;
+            };
+
+            let res = func.next_key().await;
+
+            self.origin = Some((layer, watching));
+            res.eval(self);
+        }
+    }
+
+    /// Notify the function that returned the command being evaluated after the next key press
+    pub fn watch_next_key(&mut self) {
+        if let Some(origin) = self.origin {
+            if !self.watching.contains(&origin) {
+                self.watching.push(origin);
+            }
+        }
+    }
+
+    /// Tell the undecided function the key at a grid index was pressed or released
+    async fn interrupt(&mut self, idx: usize, pressed: bool) {
+        let Some((layer, undecided)) = self.undecided else {
+            return;
+        };
+
+        let ftype = self.find(idx)
+            .map(|layer| FunctionType::from_function(&self.layer_stack[layer][idx]))
+            .unwrap_or(FunctionType::None);
+
+        let Some(Some(func)) = self.layer_stack.get_mut(layer).and_then(|layer| layer.get_mut(undecided)) else {
+            self.undecided = None;
+            return;
+        };
+
+        let res = func.interrupt(idx, pressed, &ftype).await;
+
+        if !func.undecided() {
+            self.undecided = None;
+        }
+
+        self.origin = Some((layer, undecided));
+        res.eval(self);
+    }
+
+    /// Give the undecided function a chance to resolve on a timer, even when no key changed this scan
+    async fn undecided_timeout(&mut self) {
+        let Some((layer, undecided)) = self.undecided else {
+            return;
+        };
+
+        let Some(Some(func)) = self.layer_stack.get_mut(layer).and_then(|layer| layer.get_mut(undecided)) else {
+            self.undecided = None;
+            return;
+        };
+
+        let res = func.timeout().await;
+
+        if !func.undecided() {
+            self.undecided = None;
+        }
+
+        self.origin = Some((layer, undecided));
+        res.eval(self);
+        self.flush().await;
+    }
+
+    /// Drop events held back whilst a function was undecided for keys at grid indices, events of other keys are kept
+    pub fn discard_deferred(&mut self, indices: &[usize]) {
+        self.deferred.retain(|(idx, _)| !indices.contains(idx));
+    }
+
+    /// Dispatch held back events until a function becomes undecided
+    async fn flush(&mut self) {
+        while self.undecided.is_none() {
+            let Some((idx, state)) = self.deferred.pop_front() else {
+                break;
+            };
+            self.event(idx, state).await;
+        }
+    }
+
+    /// Release every held key, so functions are left in a released state before the layout is replaced
+    pub async fn release_all(&mut self) {
+        self.chord.clear();
+        self.deferred.clear();
+        self.undecided = None;
+
+        for idx in 0..self.prev_states.len() {
+            if !self.prev_states[idx].high() {
+                continue;
+            }
+            self.prev_states[idx] = RELEASED;
+
+            if self.suppressed[idx] {
+                self.combo_event(idx, RELEASED).await;
+            } else {
+                self.event(idx, RELEASED).await;
+            }
+        }
+    }
+
     /// Poll the layout states and call corresponding functions
     pub async fn poll(&mut self) {
         if self.layer_stack.len() == 0 {
             return;
         }
         
-        let mut commands = vec![];
+        let mut states = vec![];
 
-        for (_, address) in self.addresses.iter_mut() {
+        let driver_manager = self.driver_manager.read().await;
+        for (_, address) in self.addresses.iter() {
             match address {
-                Address::DriverMatrix { name, input, width, root} => {
-                    let driver_manager = self.driver_manager.read().await;
-                    let Some(driver) = driver_manager.get(name) else {
+                Address::DriverMatrix { name, input, width, root, .. } => {
+                    // skip a driver whilst its tick task is updating it
+                    let Some(Ok(driver)) = driver_manager.get(name).map(|driver| driver.try_read()) else {
                         continue;
                     };
 
                     let Some(state) = driver.poll_range(input) else {
                         continue;
                     };
-                    
-                    let state = state.to_vec();
-
-                    drop(driver);
-                    drop(driver_manager);
-
-                    let (mut x, mut y) = root;
-
-                    for state in state {
-                        for layer in self.layer_stack[..self.cur_layer+1].iter_mut().rev() {
-                            match &mut layer[x + (y * self.width)] {
-                                Some(func) => {
-                                    let res = func.event(state).await;
-                                    if !matches!(res, ReturnCommand::None) {
-                                        commands.push(res);
-                                    }
-                                    break;
-                                },
-                                None=> {
-                                    continue;
-                                },
-                            }
-                        }
-                        
-                        x += 1;
-                        if x % *width == 0 {
-                            x = 0;
-                            y += 1;
-                        }
+
+                    let (x, y) = root;
+
+                    for (i, state) in state.iter().enumerate() {
+                        states.push((x + (i % width) + ((y + i / width) * self.width), *state));
                     }
                 },
-                Address::DriverRow { name, input, root } => {
-                    let driver_manager = self.driver_manager.read().await;
-                    let Some(driver) = driver_manager.get(name) else {
+                Address::DriverRow { name, input, root, .. } => {
+                    let Some(Ok(driver)) = driver_manager.get(name).map(|driver| driver.try_read()) else {
                         continue;
                     };
 
-                    let Some(states) = driver.poll_list(&input) else {
+                    let Some(row) = driver.poll_list(&input) else {
                         continue;
                     };
 
-                    drop(driver);
-                    drop(driver_manager);
-
-                    for (x, state) in states.iter().enumerate() {
-                        for layer in self.layer_stack[..self.cur_layer+1].iter_mut().rev() {
-                            match &mut layer[x + (*root * self.width)] {
-                                Some(func) => {
-                                    let res = func.event(*state).await;
-                                    if !matches!(res, ReturnCommand::None) {
-                                        commands.push(res);
-                                    }
-                                    break;
-                                },
-                                None=> {
-                                    continue;
-                                },
-                            }
-                        }
+                    for (x, state) in row.into_iter().enumerate() {
+                        states.push((x + (*root * self.width), state));
                     }
-                    
                 },
-                Address::DriverColumn { name, input, root } => {
-                    let driver_manager = self.driver_manager.read().await;
-                    let Some(driver) = driver_manager.get(name) else {
+                Address::DriverColumn { name, input, root, .. } => {
+                    let Some(Ok(driver)) = driver_manager.get(name).map(|driver| driver.try_read()) else {
                         continue;
                     };
 
-                    let Some(states) = driver.poll_list(&input) else {
+                    let Some(column) = driver.poll_list(&input) else {
                         continue;
                     };
 
-                    drop(driver);
-                    drop(driver_manager);
-
-                    for (y, state) in states.iter().enumerate() {
-                        for layer in self.layer_stack[..self.cur_layer+1].iter_mut().rev() {
-                            match &mut layer[*root + (y * self.width)] {
-                                Some(func) => {
-                                    let res = func.event(*state).await;
-                                    if !matches!(res, ReturnCommand::None) {
-                                        commands.push(res);
-                                    }
-                                    break;
-                                },
-                                None=> {
-                                    continue;
-                                },
-                            }
-                        }
+                    for (y, state) in column.into_iter().enumerate() {
+                        states.push((*root + (y * self.width), state));
                     }
-                    
                 },
-                Address::DriverAddr { name, input, root} => {
-                    let driver_manager = self.driver_manager.read().await;
-                    let Some(driver) = driver_manager.get(name) else {
+                Address::DriverAddr { name, input, root, .. } => {
+                    let Some(Ok(driver)) = driver_manager.get(name).map(|driver| driver.try_read()) else {
                         continue;
                     };
 
                     let (x, y) = root;
 
-                    let state = driver.poll(*input);
-
-                    drop(driver);
-                    drop(driver_manager);
-
-                    for layer in self.layer_stack[..self.cur_layer+1].iter_mut().rev() {
-                        match &mut layer[*x + (*y * self.width)] {
-                            Some(func) => {
-                                let res = func.event(state).await;
-                                if !matches!(res, ReturnCommand::None) {
-                                    commands.push(res);
-                                }
-                                break;
-                            },
-                            None=> {
-                                continue;
-                            },
-                        }
-                    }
+                    states.push((x + (y * self.width), driver.poll(*input)));
                 },
                 Address::None => continue,
             }
         }
+        drop(driver_manager);
+
+        for (idx, state) in states {
+            let state = match self.debouncers.get_mut(idx) {
+                Some(Some(debouncer)) => debouncer.debounce(state),
+                _ => state,
+            };
+            let Some(prev_state) = self.prev_states.get_mut(idx) else {
+                continue;
+            };
+            let edge = state.high() != prev_state.high();
+            *prev_state = state;
+
+            if edge {
+                self.events.send(Event::Key { x: idx % self.width, y: idx / self.width, pressed: state.high() });
+            }
 
-        for command in commands {
-            command.eval(self);
+            if self.suppressed[idx] {
+                self.combo_event(idx, state).await;
+                continue;
+            }
+
+            if self.chord.iter().any(|(chorded, _)| *chorded == idx) {
+                if !edge {
+                    continue;
+                }
+                self.break_chord().await;
+            } else if edge && state.high() && self.in_combo(idx) {
+                if self.chord.is_empty() {
+                    self.chord_start = Instant::now();
+                }
+                self.chord.push((idx, state));
+                self.resolve_chord().await;
+                continue;
+            } else if edge && !self.chord.is_empty() {
+                self.break_chord().await;
+            }
+
+            self.dispatch(idx, state, edge).await;
+        }
+
+        if !self.chord.is_empty() {
+            self.resolve_chord().await;
         }
+
+        self.undecided_timeout().await;
     }
 
     pub fn to_json(&self) -> Result<String, serde_json::Error> {
@@ -581,7 +1271,9 @@ impl Serialize for Layout {
             width: usize,
             height: usize,
             bound: Vec<&'a Address>,
-            layers: Vec<Vec<Vec<FunctionType>>>
+            layers: Vec<Vec<Vec<FunctionType>>>,
+            combos: Vec<Vec<ComboType>>,
+            default_layer: usize,
         }
         let layers: Vec<Vec<Vec<FunctionType>>> = self.layer_stack.iter()
             .map(|layer| {
@@ -592,7 +1284,211 @@ impl Serialize for Layout {
                 .map(|a| a.to_vec())
                 .collect::<Vec<Vec<FunctionType>>>()
         }).collect();
+        let combos: Vec<Vec<ComboType>> = self.combos.iter()
+            .map(|combos| {
+                combos.iter()
+                .map(|combo| ComboType {
+                    keys: combo.keys.iter().map(|idx| (idx % self.width, idx / self.width)).collect(),
+                    function: FunctionType::from_function(&combo.func),
+                    window_ms: combo.window.as_millis() as u64,
+                })
+                .collect()
+        }).collect();
         let bound = self.addresses.iter().map(|(_, a)| a).collect::<Vec<&Address>>();    
-        Layout{width: self.width, height: self.height, bound, layers: layers}.serialize(serializer)
+        Layout{width: self.width, height: self.height, bound, layers: layers, combos, default_layer: self.default_layer}.serialize(serializer)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use virt_hid::key::Modifier;
+
+    use crate::{harness::{Harness, Report, row_layout, keys, keyboard, SIM}, function::{PRESSED, RELEASED, keyboard::MediaKey}};
+
+    use super::{Address, LayoutError};
+
+    #[tokio::test]
+    async fn sends_keys_while_held() {
+        let mut harness = Harness::new(&row_layout(json!([[[{"Key": "a"}, {"Key": "b"}]]])), 2).await;
+
+        harness.press(0).await;
+        harness.press(1).await;
+        harness.release(0).await;
+        harness.release(1).await;
+
+        harness.assert_reports(&[keys(&['a']), keys(&['a', 'b']), keys(&['b']), keys(&[])]).await;
+    }
+
+    #[tokio::test]
+    async fn shift_activates_layer_while_held() {
+        let mut harness = Harness::new(&row_layout(json!([
+            [[{"Shift": {"Const": 1}}, {"Key": "a"}]],
+            [[null, {"Key": "b"}]],
+        ])), 2).await;
+
+        harness.press(0).await;
+        harness.tap(1).await;
+        harness.release(0).await;
+        harness.tap(1).await;
+
+        harness.assert_reports(&[keys(&['b']), keys(&[]), keys(&['a']), keys(&[])]).await;
+        assert_eq!(harness.layout.read().await.curr_layer(), 0);
+    }
+
+    #[tokio::test]
+    async fn releases_key_on_layer_it_was_pressed_on() {
+        let mut harness = Harness::new(&row_layout(json!([
+            [[{"Shift": {"Const": 1}}, {"Key": "a"}]],
+            [[null, {"Key": "b"}]],
+        ])), 2).await;
+
+        harness.press(0).await;
+        harness.press(1).await;
+        harness.release(0).await;
+        harness.release(1).await;
+
+        harness.assert_reports(&[keys(&['b']), keys(&[])]).await;
+    }
+
+    #[tokio::test]
+    async fn transparent_and_blocked_positions() {
+        let mut harness = Harness::new(&row_layout(json!([
+            [[{"ToggleLayer": {"Const": 1}}, {"Key": "a"}, {"Key": "c"}]],
+            [[null, null, "Block"]],
+        ])), 3).await;
+
+        harness.tap(0).await;
+        harness.tap(1).await;
+        harness.tap(2).await;
+        harness.assert_reports(&[keys(&['a']), keys(&[])]).await;
+
+        harness.tap(0).await;
+        harness.tap(2).await;
+        harness.assert_reports(&[keys(&['c']), keys(&[])]).await;
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn reports_last_held_media_key() {
+        let mut harness = Harness::new(&row_layout(json!([[[{"Media": "Mute"}, {"Media": "VolumeUp"}]]])), 2).await;
+
+        harness.press(0).await;
+        harness.press(1).await;
+        harness.release(1).await;
+        harness.release(0).await;
+
+        harness.assert_reports(&[
+            Report::Consumer(Some(MediaKey::Mute)),
+            Report::Consumer(Some(MediaKey::VolumeUp)),
+            Report::Consumer(Some(MediaKey::Mute)),
+            Report::Consumer(None),
+        ]).await;
+    }
+
+    #[tokio::test]
+    async fn remove_address_outside_layout() {
+        let layout = json!({
+            "width": 2,
+            "height": 2,
+            "bound": [{"DriverRow": {"name": SIM, "input": [0, 1], "root": 1}}],
+            "layers": [[[null, null], [{"Key": "a"}, {"Key": "b"}]]],
+        });
+        let harness = Harness::new(&layout.to_string(), 2).await;
+
+        assert!(harness.layout.write().await.remove_address((2, 0)).await.is_none());
+        assert_eq!(harness.layout.read().await.addresses().len(), 1);
+        assert!(harness.layout.write().await.remove_address((0, 1)).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn modifiers_apply_to_keys() {
+        let mut harness = Harness::new(&row_layout(json!([[[{"Modifier": "LeftShift"}, {"Key": "a"}]]])), 2).await;
+
+        harness.press(0).await;
+        harness.tap(1).await;
+        harness.release(0).await;
+
+        harness.assert_reports(&[
+            keyboard(&[Modifier::LeftShift], &[]),
+            keyboard(&[Modifier::LeftShift], &['a']),
+            keyboard(&[Modifier::LeftShift], &[]),
+            keys(&[]),
+        ]).await;
+    }
+
+    #[tokio::test]
+    async fn combo_fires_or_breaks_chord() {
+        let mut layout: serde_json::Value = serde_json::from_str(&row_layout(json!([[[{"Key": "a"}, {"Key": "b"}, {"Key": "c"}]]]))).unwrap();
+        layout["combos"] = json!([[{"keys": [[0, 0], [1, 0]], "function": {"Key": "x"}}]]);
+        let mut harness = Harness::new(&layout.to_string(), 3).await;
+
+        harness.frame(vec![(0, PRESSED), (1, PRESSED)]).await;
+        harness.frame(vec![(0, RELEASED), (1, RELEASED)]).await;
+        harness.assert_reports(&[keys(&['x']), keys(&[])]).await;
+
+        harness.press(0).await;
+        harness.assert_reports(&[]).await;
+        harness.release(0).await;
+        harness.assert_reports(&[keys(&['a']), keys(&[])]).await;
+
+        harness.press(0).await;
+        harness.press(2).await;
+        harness.frame(vec![(0, RELEASED), (2, RELEASED)]).await;
+        harness.assert_reports(&[keys(&['a']), keys(&['a', 'c']), keys(&['c']), keys(&[])]).await;
+    }
+
+    #[tokio::test]
+    async fn one_shot_layer_shifts_for_next_key() {
+        let mut harness = Harness::new(&row_layout(json!([
+            [[{"OneShotLayer": {"Const": 1}}, {"Key": "a"}]],
+            [[null, {"Key": "b"}]],
+        ])), 2).await;
+
+        harness.tap(0).await;
+        harness.tap(1).await;
+        harness.tap(1).await;
+
+        harness.assert_reports(&[keys(&['b']), keys(&[]), keys(&['a']), keys(&[])]).await;
+        assert_eq!(harness.layout.read().await.curr_layer(), 0);
+    }
+
+    #[tokio::test]
+    async fn debounce_filters_chatter() {
+        let address = |input: usize, algorithm: &str, window_ms: u64| json!({"DriverAddr": {
+            "name": SIM,
+            "input": input,
+            "root": [input, 0],
+            "debounce": {"algorithm": algorithm, "window_ms": window_ms},
+        }});
+        let layout = json!({
+            "width": 3,
+            "height": 1,
+            "bound": [address(0, "Eager", 60000), address(1, "Deferred", 60000), address(2, "Deferred", 0)],
+            "layers": [[[{"Key": "a"}, {"Key": "b"}, {"Key": "c"}]]],
+        });
+        let mut harness = Harness::new(&layout.to_string(), 3).await;
+
+        harness.press(0).await;
+        harness.release(0).await;
+        harness.assert_reports(&[keys(&['a'])]).await;
+
+        harness.tap(1).await;
+        harness.assert_reports(&[]).await;
+
+        harness.tap(2).await;
+        harness.assert_reports(&[keys(&['c']), keys(&[])]).await;
+    }
+
+    #[tokio::test]
+    async fn add_address_needs_loaded_driver_input() {
+        let harness = Harness::new(&row_layout(json!([[[{"Key": "a"}, {"Key": "b"}]]])), 2).await;
+        let mut layout = harness.layout.write().await;
+        layout.remove_address((0, 0)).await.expect("Row is bound");
+
+        let missing = Address::DriverRow { name: "missing".to_string(), input: vec![0, 1], root: 0, debounce: None };
+        assert!(matches!(layout.add_address(missing).await, Err(LayoutError::UnknownDriver)));
+        let out_of_range = Address::DriverRow { name: SIM.to_string(), input: vec![0, 2], root: 0, debounce: None };
+        assert!(matches!(layout.add_address(out_of_range).await, Err(LayoutError::InvalidInput)));
+        let row = Address::DriverRow { name: SIM.to_string(), input: vec![0, 1], root: 0, debounce: None };
+        assert!(layout.add_address(row).await.is_ok());
+    }
+}
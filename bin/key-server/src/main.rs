@@ -2,19 +2,21 @@
 
 use std::{process::exit, time::Duration, path::PathBuf, str::FromStr, fmt::Display, fs, io::Write, sync::Arc};
 
-use clap::Parser;
-use driver::{DriverManager};
+use clap::{Parser, Subcommand};
+use driver::{DriverManager, led::{self, Leds}};
 use function::{FunctionBuilder};
 use log::{error};
 use tokio::{sync::RwLock, fs::read_to_string};
 use variables::VarDef;
 
-use crate::{function::{midi::MidiController, cmd::CommandPool, hid::HID, nng::NanoMessenger}, modules::ModuleManager, config_rpc::ConfigRPC, variables::Variables, frontend::{FrontendConfiguration, FrontendConfig}};
+use crate::{function::{midi::MidiController, cmd::CommandPool, hid::HID, nng::NanoMessenger}, modules::ModuleManager, config_rpc::ConfigRPC, variables::Variables, frontend::{FrontendConfiguration, FrontendConfig}, scheduler::Scheduler, reload::{ConfigWatcher, SavedConfigs, WatchedConfig}, events::Events, check::Checker};
 
 /// Driver module
 mod driver;
 /// Layout module
 mod layout;
+/// Debounce module
+mod debounce;
 /// Function module
 mod function;
 /// Plugin modules
@@ -25,13 +27,47 @@ mod config_rpc;
 mod variables;
 /// Frontend Config module
 mod frontend;
+/// Event loop scheduler module
+mod scheduler;
+/// Config hot reload module
+mod reload;
+/// Server event module
+mod events;
+/// QMK keymap module
+mod qmk;
+/// Config check module
+mod check;
+#[cfg(test)]
+/// Layout test harness
+mod harness;
 
 #[derive(Parser)]
 /// Cli Args
 struct Args {
     #[arg(short, long)]
     /// Path to config directory
-    config: Option<String>
+    config: Option<String>,
+    #[arg(long)]
+    /// Check the configuration and report every problem found without starting the key-server
+    check: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+/// Cli Commands, the key-server runs when no command is given
+enum Command {
+    /// Replace the layers in layout.json with the layers of a QMK keymap.json or VIA export
+    ImportQmk {
+        file: PathBuf,
+    },
+    /// Export the layers in layout.json as a QMK keymap.json
+    ExportQmk {
+        file: PathBuf,
+        #[arg(short, long, default_value = "")]
+        /// Keyboard name written to the keymap
+        keyboard: String,
+    },
 }
 
 /// Turns a result into a option containing the ok value. 
@@ -155,9 +191,11 @@ async fn main() {
     const LAYOUT_JSON: &str = "layout.json";
     const FRONTEND_JSON: &str = "frontend.json";
     const MODULES: &str = "modules";
+    const MACROS: &str = "macros";
 
     const CONFIG_FRONT: &str = "ipc:///lmk/ksf.ipc";
     const CONFIG_BACK: &str = "ipc:///lmk/ksb.ipc";
+    const EVENTS: &str = "ipc:///lmk/kse.ipc";
 
     // Load configuration
     let args = Args::parse();
@@ -220,23 +258,82 @@ async fn main() {
             .or_exit("Unable to create modules folder");
     }
 
+    if !config.join(MACROS).exists() {
+        fs::create_dir(config.join(MACROS))
+            .or_exit("Unable to create macros folder");
+    }
+
+    match args.command {
+        Some(Command::ImportQmk { file }) => {
+            let builder: layout::LayoutBuilder = serde_json::from_reader(fs::File::open(config.join(LAYOUT_JSON))
+                .or_exit_print("Unable to read layout config"))
+                .or_exit_print("Unable to parse layout config");
+            let builder = qmk::import(&fs::read_to_string(file).or_exit_print("Unable to read keymap"), builder)
+                .or_exit_print("Unable to import keymap");
+            fs::write(
+                config.join(LAYOUT_JSON),
+                serde_json::to_string_pretty(&builder).or_exit_print("Unable to serialize layout")
+            ).or_exit_print("Unable to write layout config");
+            return;
+        },
+        Some(Command::ExportQmk { file, keyboard }) => {
+            let builder: layout::LayoutBuilder = serde_json::from_reader(fs::File::open(config.join(LAYOUT_JSON))
+                .or_exit_print("Unable to read layout config"))
+                .or_exit_print("Unable to parse layout config");
+            fs::write(file, qmk::export(&builder, &keyboard).or_exit_print("Unable to export keymap"))
+                .or_exit_print("Unable to write keymap");
+            return;
+        },
+        None => (),
+    }
+
+    if args.check {
+        let module_manager = ModuleManager::new(config.join(MODULES), Events::new()).or_exit_print("Unable to create module manager");
+        let problems = Checker::check(
+            &config.join(LAYOUT_JSON),
+            &config.join(VARIABLES_JSON),
+            &config.join(FRONTEND_JSON),
+            &config.join(DRIVERS),
+            module_manager,
+        ).await;
+        for problem in &problems {
+            println!("{}", problem);
+        }
+        if !problems.is_empty() {
+            println!("Found {} problems", problems.len());
+            exit(1);
+        }
+        println!("No problems found");
+        return;
+    }
+
     // init key-server
-    let module_manager = ModuleManager::new(config.join(MODULES)).or_exit("Unable to create module manager");
+    let events = Events::new();
+    let _events_thread = events.start(EVENTS.to_string()).or_exit("Unable to start event publisher");
+
+    let module_manager = ModuleManager::new(config.join(MODULES), events.clone()).or_exit("Unable to create module manager");
+    let leds = Leds::new();
 
-    let driver_manager = DriverManager::load(&config.join(DRIVERS), module_manager.clone()).await
-        .or_exit("Unable to load drivers");
-    let driver_manager: Arc<RwLock<DriverManager>> = Arc::new(RwLock::new(driver_manager));
-    
     let function_config: FrontendConfiguration = FrontendConfiguration::new(&fs::read_to_string(config.join(FRONTEND_JSON))
-        .or_exit("Unable to read frontend config"), module_manager.clone())
+        .or_exit("Unable to read frontend config"), module_manager.clone(), events.clone(), leds.clone())
         .or_exit("Unable to parse frontend config");
 
-    let variables = Variables::new();
+    let mut scheduler = Scheduler::from_config(&function_config).await.or_exit("Unable to create scheduler");
+
+    let mut driver_manager = DriverManager::load(&config.join(DRIVERS), module_manager.clone(), &leds).await
+        .or_exit("Unable to load drivers");
+    for (name, interval) in scheduler.tick_intervals() {
+        driver_manager.set_tick_interval(name, interval);
+    }
+    let driver_manager: Arc<RwLock<DriverManager>> = Arc::new(RwLock::new(driver_manager));
+
+    let variables = Variables::new(events.clone());
     let default_variables: Vec<VarDef> = serde_json::from_str(
         &read_to_string(config.join(VARIABLES_JSON)).await
         .or_exit("Unable to load variables config")
     ).or_log("Unable to parse variables, continuing anyway").unwrap_or_else(|| Vec::<VarDef>::new());
     variables.write().await.create_many(default_variables);
+    tokio::spawn(led::update_variables(variables.clone(), leds.clone()));
     
     let command_pool = CommandPool::from_config(&function_config).await.or_exit("Unable to create command pool");
     let hid = HID::from_config(&function_config).await.or_exit("Unable to create hid");
@@ -250,14 +347,30 @@ async fn main() {
         driver_manager.clone(), 
         nano_messanger, 
         module_manager.clone(),
-        variables.clone()
+        variables.clone(),
+        config.join(MACROS),
     );
 
     let builder: layout::LayoutBuilder = serde_json::from_reader(fs::File::open(config.join(LAYOUT_JSON))
         .or_exit("Unable to read layout config"))
         .or_exit("Unable to parse layout config");
 
-    let layout = builder.build(driver_manager, func_builder.clone()).await;
+    let layout = builder.build(driver_manager.clone(), func_builder.clone(), events.clone()).await;
+
+    let saved_configs = SavedConfigs::default();
+    let _config_watcher = ConfigWatcher::start(WatchedConfig {
+        layout_path: config.join(LAYOUT_JSON),
+        variables_path: config.join(VARIABLES_JSON),
+        drivers_path: config.join(DRIVERS),
+        layout: layout.clone(),
+        variables: variables.clone(),
+        driver_manager,
+        leds,
+        function_builder: func_builder.clone(),
+        module_manager: module_manager.clone(),
+        events,
+        saved: saved_configs.clone(),
+    });
 
     let _config_thread = ConfigRPC::start(
         CONFIG_FRONT.to_string(), 
@@ -265,11 +378,13 @@ async fn main() {
         layout.clone(), 
         config.join(LAYOUT_JSON),
         variables,
-        config.join(VARIABLES_JSON)
+        config.join(VARIABLES_JSON),
+        saved_configs,
     ).await.or_exit("Unable to start Config RPC");
 
-    // event loop]
+    // event loop
     loop {
+        scheduler.wait().await;
         layout.write().await.tick().await;
         layout.write().await.poll().await;
     }
@@ -3,11 +3,12 @@ use std::{collections::HashMap, io, path::PathBuf, fs, fmt::Display, sync::Arc,
 use pyo3::{prelude::*};
 use async_trait::async_trait;
 use key_module::{Data, function, driver, hid};
+use key_rpc::Event;
 use serde::{Serialize, Deserialize};
 use tokio::sync::{mpsc::{self, UnboundedSender}, oneshot::{self, Sender, Receiver}};
 use virt_hid::{key::{SpecialKey, Modifier}, mouse::{MouseDir, MouseButton}};
 
-use crate::{OrLogIgnore, function::{FunctionInterface, ReturnCommand, FunctionType, Function, State}, OrLog, driver::{DriverInterface, DriverData, Driver, DriverError}};
+use crate::{OrLogIgnore, function::{FunctionInterface, ReturnCommand, FunctionType, Function, State}, OrLog, driver::{DriverInterface, DriverData, Driver, DriverError}, events::Events};
 
 #[derive(Debug, Serialize, Deserialize)]
 /// Interface type
@@ -261,6 +262,7 @@ pub struct ModuleManager {
     hid_modules: HashMap<String, UnboundedSender<HidCommand>>,
     function_modules: HashMap<String, UnboundedSender<FuncCommand>>,
     driver_modules: HashMap<String, UnboundedSender<DriverCommand>>,
+    events: Events,
 }
 
 /// Module meta file name
@@ -301,9 +303,9 @@ const PY_SEND_MOUSE: &'static str = "send_mouse";
 
 impl ModuleManager {
     /// New
-    pub fn new(plugin_dir: PathBuf) -> Result<Arc<ModuleManager>, ModError> {
+    pub fn new(plugin_dir: PathBuf, events: Events) -> Result<Arc<ModuleManager>, ModError> {
         let contents = fs::read_dir(plugin_dir).map_err(|e| ModError::IO(e))?;
-        let mut modules = ModuleManager{hid_modules: HashMap::new(), function_modules: HashMap::new(), driver_modules: HashMap::new()};
+        let mut modules = ModuleManager{hid_modules: HashMap::new(), function_modules: HashMap::new(), driver_modules: HashMap::new(), events};
         
         for entry in contents {
             let entry = entry.map_err(|e| ModError::IO(e))?;
@@ -313,6 +315,12 @@ impl ModuleManager {
         Ok(Arc::new(modules))
     }
 
+    #[cfg(test)]
+    /// New, without any modules
+    pub fn empty(events: Events) -> Arc<ModuleManager> {
+        Arc::new(ModuleManager{hid_modules: HashMap::new(), function_modules: HashMap::new(), driver_modules: HashMap::new(), events})
+    }
+
     pub fn is_hid(&self, name: &str) -> bool {
         self.hid_modules.contains_key(name)
     }
@@ -665,9 +673,12 @@ impl ModuleManager {
     }
 
     /// Receive command respond from channel
-    async fn receive<T>(rx: Receiver<Result<T, String>>) -> Result<T, ModError>{
+    async fn receive<T>(&self, rx: Receiver<Result<T, String>>) -> Result<T, ModError>{
         match rx.await {
-            Ok(res) => res.map_err(|e| ModError::Module(e.into())),
+            Ok(res) => res.map_err(|e| {
+                self.events.send(Event::ModuleError(e.clone()));
+                ModError::Module(e.into())
+            }),
             Err(e) => Err(ModError::Channel(e.to_string())),
         }
     } 
@@ -677,7 +688,7 @@ impl ModuleManager {
         let module = self.find_function_module(module_name)?;
         let (tx, rx) = oneshot::channel();
         module.send(FuncCommand::LoadData(data, tx)).map_err(|e| ModError::Channel(e.to_string()))?;
-        ModuleManager::receive(rx).await
+        self.receive(rx).await
     }
 
     /// Trigger function event. Calls event
@@ -685,7 +696,7 @@ impl ModuleManager {
         let module = self.find_function_module(module_name)?;
         let (tx, rx) = oneshot::channel();
         module.send(FuncCommand::Event(id, state, tx)).map_err(|e| ModError::Channel(e.to_string()))?;
-        ModuleManager::receive(rx).await
+        self.receive(rx).await
     }
 
     /// Load driver from data. Calls load_data
@@ -693,7 +704,7 @@ impl ModuleManager {
         let module = self.find_driver_module(module_name)?;
         let (tx, rx) = oneshot::channel();
         module.send(DriverCommand::LoadData(data, tx)).map_err(|e| ModError::Channel(e.to_string()))?;
-        ModuleManager::receive(rx).await
+        self.receive(rx).await
     }
 
     /// Poll a driver. Calls poll
@@ -701,7 +712,7 @@ impl ModuleManager {
         let module = self.find_driver_module(module_name)?;
         let (tx, rx) = oneshot::channel();
         module.send(DriverCommand::Poll(id, tx)).map_err(|e| ModError::Channel(e.to_string()))?;
-        ModuleManager::receive(rx).await
+        self.receive(rx).await
     }
 
     /// Set a driver. Calls set
@@ -709,7 +720,7 @@ impl ModuleManager {
         let module = self.find_driver_module(module_name)?;
         let (tx, rx) = oneshot::channel();
         module.send(DriverCommand::Set(id, idx, state, tx)).map_err(|e| ModError::Channel(e.to_string()))?;
-        ModuleManager::receive(rx).await
+        self.receive(rx).await
     }
     
     pub async fn hold_key(&self, module_name: &str, key: char) -> Result<(), ModError> {
@@ -0,0 +1,421 @@
+use std::fmt::Display;
+
+use log::warn;
+use serde::{Serialize, Deserialize};
+use virt_hid::key::{BasicKey, KeyOrigin, SpecialKey, Modifier};
+
+use crate::{layout::LayoutBuilder, function::{FunctionType, tap_hold::TapHoldResolution, keyboard::MediaKey}, variables::Data};
+
+/// QMK default tapping term, used for layer and mod taps
+const TAPPING_TERM: u64 = 200;
+
+/// Keycodes for characters, the first name for a character is used on export
+const CHARS: &[(&str, char)] = &[
+    ("KC_MINS", '-'), ("KC_MINUS", '-'),
+    ("KC_EQL", '='), ("KC_EQUAL", '='),
+    ("KC_LBRC", '['), ("KC_LEFT_BRACKET", '['),
+    ("KC_RBRC", ']'), ("KC_RIGHT_BRACKET", ']'),
+    ("KC_BSLS", '\\'), ("KC_BACKSLASH", '\\'),
+    ("KC_SCLN", ';'), ("KC_SEMICOLON", ';'),
+    ("KC_QUOT", '\''), ("KC_QUOTE", '\''),
+    ("KC_GRV", '`'), ("KC_GRAVE", '`'),
+    ("KC_COMM", ','), ("KC_COMMA", ','),
+    ("KC_DOT", '.'),
+    ("KC_SLSH", '/'), ("KC_SLASH", '/'),
+    ("KC_TILD", '~'), ("KC_TILDE", '~'),
+    ("KC_EXLM", '!'), ("KC_EXCLAIM", '!'),
+    ("KC_AT", '@'),
+    ("KC_HASH", '#'),
+    ("KC_DLR", '$'), ("KC_DOLLAR", '$'),
+    ("KC_PERC", '%'), ("KC_PERCENT", '%'),
+    ("KC_CIRC", '^'), ("KC_CIRCUMFLEX", '^'),
+    ("KC_AMPR", '&'), ("KC_AMPERSAND", '&'),
+    ("KC_ASTR", '*'), ("KC_ASTERISK", '*'),
+    ("KC_LPRN", '('), ("KC_LEFT_PAREN", '('),
+    ("KC_RPRN", ')'), ("KC_RIGHT_PAREN", ')'),
+    ("KC_UNDS", '_'), ("KC_UNDERSCORE", '_'),
+    ("KC_PLUS", '+'),
+    ("KC_LCBR", '{'), ("KC_LEFT_CURLY_BRACE", '{'),
+    ("KC_RCBR", '}'), ("KC_RIGHT_CURLY_BRACE", '}'),
+    ("KC_PIPE", '|'),
+    ("KC_COLN", ':'), ("KC_COLON", ':'),
+    ("KC_DQUO", '"'), ("KC_DQT", '"'), ("KC_DOUBLE_QUOTE", '"'),
+    ("KC_LT", '<'), ("KC_LABK", '<'),
+    ("KC_GT", '>'), ("KC_RABK", '>'),
+    ("KC_QUES", '?'), ("KC_QUESTION", '?'),
+];
+
+/// Keycodes for special keys, the first name for a key is used on export
+const SPECIAL_KEYS: &[(&str, SpecialKey)] = &[
+    ("KC_ENT", SpecialKey::ReturnEnter), ("KC_ENTER", SpecialKey::ReturnEnter),
+    ("KC_ESC", SpecialKey::Escape), ("KC_ESCAPE", SpecialKey::Escape),
+    ("KC_BSPC", SpecialKey::Backspace), ("KC_BACKSPACE", SpecialKey::Backspace),
+    ("KC_TAB", SpecialKey::Tab),
+    ("KC_SPC", SpecialKey::Spacebar), ("KC_SPACE", SpecialKey::Spacebar),
+    ("KC_CAPS", SpecialKey::CapsLock), ("KC_CAPS_LOCK", SpecialKey::CapsLock),
+    ("KC_F1", SpecialKey::F1), ("KC_F2", SpecialKey::F2), ("KC_F3", SpecialKey::F3),
+    ("KC_F4", SpecialKey::F4), ("KC_F5", SpecialKey::F5), ("KC_F6", SpecialKey::F6),
+    ("KC_F7", SpecialKey::F7), ("KC_F8", SpecialKey::F8), ("KC_F9", SpecialKey::F9),
+    ("KC_F10", SpecialKey::F10), ("KC_F11", SpecialKey::F11), ("KC_F12", SpecialKey::F12),
+    ("KC_F13", SpecialKey::F13), ("KC_F14", SpecialKey::F14), ("KC_F15", SpecialKey::F15),
+    ("KC_F16", SpecialKey::F16), ("KC_F17", SpecialKey::F17), ("KC_F18", SpecialKey::F18),
+    ("KC_F19", SpecialKey::F19), ("KC_F20", SpecialKey::F20), ("KC_F21", SpecialKey::F21),
+    ("KC_F22", SpecialKey::F22), ("KC_F23", SpecialKey::F23), ("KC_F24", SpecialKey::F24),
+    ("KC_UP", SpecialKey::UpArrow),
+    ("KC_DOWN", SpecialKey::DownArrow),
+    ("KC_LEFT", SpecialKey::LeftArrow),
+    ("KC_RGHT", SpecialKey::RightArrow), ("KC_RIGHT", SpecialKey::RightArrow),
+    ("KC_PGUP", SpecialKey::PageUp), ("KC_PAGE_UP", SpecialKey::PageUp),
+    ("KC_PGDN", SpecialKey::PageDown), ("KC_PAGE_DOWN", SpecialKey::PageDown),
+    ("KC_HOME", SpecialKey::Home),
+    ("KC_END", SpecialKey::End),
+    ("KC_INS", SpecialKey::Insert), ("KC_INSERT", SpecialKey::Insert),
+    ("KC_DEL", SpecialKey::DeleteForward), ("KC_DELETE", SpecialKey::DeleteForward),
+    ("KC_PSCR", SpecialKey::PrintScreen), ("KC_PRINT_SCREEN", SpecialKey::PrintScreen),
+    ("KC_SCRL", SpecialKey::ScrollLock), ("KC_SCROLL_LOCK", SpecialKey::ScrollLock),
+    ("KC_PAUS", SpecialKey::Pause), ("KC_PAUSE", SpecialKey::Pause),
+    ("KC_NUM", SpecialKey::NumLockAndClear), ("KC_NUM_LOCK", SpecialKey::NumLockAndClear),
+    ("KC_APP", SpecialKey::Application), ("KC_APPLICATION", SpecialKey::Application),
+    ("KC_VOLU", SpecialKey::VolumeUp), ("KC_AUDIO_VOL_UP", SpecialKey::VolumeUp),
+    ("KC_VOLD", SpecialKey::VolumeDown), ("KC_AUDIO_VOL_DOWN", SpecialKey::VolumeDown),
+    ("KC_MUTE", SpecialKey::Mute), ("KC_AUDIO_MUTE", SpecialKey::Mute),
+];
+
+/// Keycodes for media keys, the first name for a key is used on export
+const MEDIA_KEYS: &[(&str, MediaKey)] = &[
+    ("KC_MPLY", MediaKey::PlayPause), ("KC_MEDIA_PLAY_PAUSE", MediaKey::PlayPause),
+    ("KC_MNXT", MediaKey::NextTrack), ("KC_MEDIA_NEXT_TRACK", MediaKey::NextTrack),
+    ("KC_MPRV", MediaKey::PreviousTrack), ("KC_MEDIA_PREV_TRACK", MediaKey::PreviousTrack),
+    ("KC_MSTP", MediaKey::Stop), ("KC_MEDIA_STOP", MediaKey::Stop),
+    ("KC_MFFD", MediaKey::FastForward), ("KC_MEDIA_FAST_FORWARD", MediaKey::FastForward),
+    ("KC_MRWD", MediaKey::Rewind), ("KC_MEDIA_REWIND", MediaKey::Rewind),
+    ("KC_EJCT", MediaKey::Eject), ("KC_MEDIA_EJECT", MediaKey::Eject),
+    ("KC_BRIU", MediaKey::BrightnessUp), ("KC_BRIGHTNESS_UP", MediaKey::BrightnessUp),
+    ("KC_BRID", MediaKey::BrightnessDown), ("KC_BRIGHTNESS_DOWN", MediaKey::BrightnessDown),
+    ("KC_CALC", MediaKey::Calculator), ("KC_CALCULATOR", MediaKey::Calculator),
+    ("KC_MAIL", MediaKey::Mail),
+    ("KC_WHOM", MediaKey::Browser), ("KC_WWW_HOME", MediaKey::Browser),
+    ("KC_WSCH", MediaKey::Search), ("KC_WWW_SEARCH", MediaKey::Search),
+];
+
+/// Keycodes for modifiers, the first name for a modifier is used on export
+const MODIFIERS: &[(&str, Modifier)] = &[
+    ("KC_LCTL", Modifier::LeftControl), ("KC_LEFT_CTRL", Modifier::LeftControl),
+    ("KC_LSFT", Modifier::LeftShift), ("KC_LEFT_SHIFT", Modifier::LeftShift),
+    ("KC_LALT", Modifier::LeftAlt), ("KC_LEFT_ALT", Modifier::LeftAlt),
+    ("KC_LGUI", Modifier::LeftMeta), ("KC_LEFT_GUI", Modifier::LeftMeta),
+    ("KC_RCTL", Modifier::RightControl), ("KC_RIGHT_CTRL", Modifier::RightControl),
+    ("KC_RSFT", Modifier::RightShift), ("KC_RIGHT_SHIFT", Modifier::RightShift),
+    ("KC_RALT", Modifier::RightAlt), ("KC_RIGHT_ALT", Modifier::RightAlt),
+    ("KC_RGUI", Modifier::RightMeta), ("KC_RIGHT_GUI", Modifier::RightMeta),
+];
+
+/// Modifier prefixes used by modifier wrappers (`LCTL(kc)`), mod taps (`LCTL_T(kc)`) and `OSM(MOD_LCTL)`
+const MODIFIER_PREFIXES: &[(&str, Modifier)] = &[
+    ("LCTL", Modifier::LeftControl),
+    ("LSFT", Modifier::LeftShift),
+    ("LALT", Modifier::LeftAlt),
+    ("LGUI", Modifier::LeftMeta),
+    ("RCTL", Modifier::RightControl),
+    ("RSFT", Modifier::RightShift),
+    ("RALT", Modifier::RightAlt),
+    ("RGUI", Modifier::RightMeta),
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+/// QMK keymap.json, VIA layout exports use the same layers field
+pub struct Keymap {
+    #[serde(default)]
+    pub keyboard: String,
+    #[serde(default)]
+    pub keymap: String,
+    #[serde(default)]
+    pub layout: String,
+    pub layers: Vec<Vec<String>>,
+}
+
+#[derive(Debug)]
+/// QMK conversion error
+pub enum QmkError {
+    /// Keymap json could not be parsed
+    Parse(serde_json::Error),
+    /// Layer has more keys than the layout has positions
+    TooManyKeys(usize),
+}
+
+impl Display for QmkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QmkError::Parse(e) => write!(f, "Unable to parse keymap, {}", e),
+            QmkError::TooManyKeys(layer) => write!(f, "Layer {} has more keys than the layout", layer),
+        }
+    }
+}
+
+/// Import a QMK keymap.json or VIA export into a layout.
+/// Keys fill the layout row by row, the layout's size and bound addresses are kept.
+pub fn import(json: &str, mut builder: LayoutBuilder) -> Result<LayoutBuilder, QmkError> {
+    let keymap: Keymap = serde_json::from_str(json).map_err(QmkError::Parse)?;
+    let size = builder.width() * builder.height();
+
+    let mut layers = Vec::new();
+    for (i, layer) in keymap.layers.iter().enumerate() {
+        if layer.len() > size {
+            return Err(QmkError::TooManyKeys(i));
+        }
+
+        let mut new_layer: Vec<Option<FunctionType>> = layer.iter()
+            .map(|keycode| match from_keycode(keycode.trim()) {
+                Some(function) => function,
+                None => {
+                    warn!("Unknown keycode {} on layer {}, leaving it transparent (QMK)", keycode, i);
+                    None
+                },
+            })
+            .collect();
+        new_layer.resize(size, None);
+        layers.push(new_layer);
+    }
+
+    builder.set_layers(layers);
+    Ok(builder)
+}
+
+/// Export a layout as a QMK keymap.json, keys are read row by row
+pub fn export(builder: &LayoutBuilder, keyboard: &str) -> Result<String, serde_json::Error> {
+    let layers = builder.layers().iter()
+        .enumerate()
+        .map(|(i, layer)| layer.iter()
+            .map(|function| function.as_ref().map_or(Some("KC_TRNS".to_string()), to_keycode).unwrap_or_else(|| {
+                warn!("Function {:?} on layer {} has no QMK keycode, exporting it as KC_NO (QMK)", function, i);
+                "KC_NO".to_string()
+            }))
+            .collect()
+        )
+        .collect();
+
+    serde_json::to_string_pretty(&Keymap {
+        keyboard: keyboard.to_string(),
+        keymap: "key-server".to_string(),
+        layout: "LAYOUT".to_string(),
+        layers,
+    })
+}
+
+/// Arguments of a keycode function, `MO(1)` gives `1`
+fn args<'a>(keycode: &'a str, name: &str) -> Option<&'a str> {
+    keycode.strip_prefix(name)?
+        .strip_prefix('(')?
+        .strip_suffix(')')
+        .map(str::trim)
+}
+
+/// Layer argument of a layer keycode
+fn layer(keycode: &str, name: &str) -> Option<Data<usize>> {
+    args(keycode, name)?.parse().ok().map(Data::Const)
+}
+
+/// Basic key of a keycode, used inside shortcuts
+fn basic_key(keycode: &str) -> Option<BasicKey> {
+    match from_keycode(keycode)?? {
+        FunctionType::Key(key) => Some(BasicKey::Char(key, KeyOrigin::Keyboard)),
+        FunctionType::Special(special) => Some(BasicKey::Special(special)),
+        _ => None,
+    }
+}
+
+/// Convert a keycode to a function, `Some(None)` is a transparent key
+fn from_keycode(keycode: &str) -> Option<Option<FunctionType>> {
+    match keycode {
+        "KC_TRNS" | "KC_TRANSPARENT" | "_______" => return Some(None),
+        "KC_NO" | "XXXXXXX" => return Some(Some(FunctionType::Block)),
+        _ => (),
+    }
+
+    if let Some(key) = keycode.strip_prefix("KC_").filter(|key| key.len() == 1) {
+        let key = key.chars().next()?;
+        if key.is_ascii_alphanumeric() {
+            return Some(Some(FunctionType::Key(key.to_ascii_lowercase())));
+        }
+    }
+
+    if let Some((_, key)) = CHARS.iter().find(|(name, _)| *name == keycode) {
+        return Some(Some(FunctionType::Key(*key)));
+    }
+
+    if let Some((_, special)) = SPECIAL_KEYS.iter().find(|(name, _)| *name == keycode) {
+        return Some(Some(FunctionType::Special(*special)));
+    }
+
+    if let Some((_, media)) = MEDIA_KEYS.iter().find(|(name, _)| *name == keycode) {
+        return Some(Some(FunctionType::Media(*media)));
+    }
+
+    if let Some((_, modifier)) = MODIFIERS.iter().find(|(name, _)| *name == keycode) {
+        return Some(Some(FunctionType::Modifier(*modifier)));
+    }
+
+    let function = if let Some(layer) = layer(keycode, "MO") {
+        FunctionType::Shift(layer)
+    } else if let Some(layer) = layer(keycode, "TG") {
+        FunctionType::ToggleLayer(layer)
+    } else if let Some(layer) = layer(keycode, "TO") {
+        FunctionType::Switch(layer)
+    } else if let Some(layer) = layer(keycode, "DF") {
+        FunctionType::DefaultLayer(layer)
+    } else if let Some(layer) = layer(keycode, "OSL") {
+        FunctionType::OneShotLayer(layer)
+    } else if let Some(modifier) = args(keycode, "OSM")
+        .and_then(|modifier| modifier.strip_prefix("MOD_"))
+        .and_then(|modifier| MODIFIER_PREFIXES.iter().find(|(name, _)| *name == modifier))
+    {
+        FunctionType::OneShotModifier(modifier.1)
+    } else if let Some((layer, tap)) = args(keycode, "LT").and_then(|args| args.split_once(',')) {
+        FunctionType::TapHold {
+            tap: Box::new(from_keycode(tap.trim())??),
+            hold: Box::new(FunctionType::Shift(Data::Const(layer.trim().parse().ok()?))),
+            timeout_ms: Data::Const(TAPPING_TERM),
+            resolution: TapHoldResolution::default(),
+        }
+    } else if let Some((modifier, tap)) = MODIFIER_PREFIXES.iter()
+        .find_map(|(name, modifier)| args(keycode, &format!("{}_T", name)).map(|tap| (*modifier, tap)))
+    {
+        FunctionType::TapHold {
+            tap: Box::new(from_keycode(tap)??),
+            hold: Box::new(FunctionType::Modifier(modifier)),
+            timeout_ms: Data::Const(TAPPING_TERM),
+            resolution: TapHoldResolution::default(),
+        }
+    } else if let Some((modifier, key)) = MODIFIER_PREFIXES.iter()
+        .find_map(|(name, modifier)| args(keycode, name).map(|key| (*modifier, key)))
+    {
+        match from_keycode(key)?? {
+            FunctionType::Shortcut { mut modifiers, keys } => {
+                modifiers.insert(0, modifier);
+                FunctionType::Shortcut { modifiers, keys }
+            },
+            _ => FunctionType::Shortcut { modifiers: vec![modifier], keys: vec![basic_key(key)?] },
+        }
+    } else {
+        return None;
+    };
+
+    Some(Some(function))
+}
+
+/// Layer argument for export, variables export their default value
+fn layer_arg(layer: &Data<usize>) -> Option<usize> {
+    match layer {
+        Data::Const(layer) => Some(*layer),
+        Data::VarDef { default, .. } => Some(*default),
+        Data::Var(_) => None,
+    }
+}
+
+/// Keycode of a basic key, used inside shortcuts
+fn basic_keycode(key: &BasicKey) -> Option<String> {
+    match key {
+        BasicKey::Char(key, _) => to_keycode(&FunctionType::Key(*key)),
+        BasicKey::Special(special) => to_keycode(&FunctionType::Special(*special)),
+    }
+}
+
+/// Modifier prefix of a modifier
+fn modifier_prefix(modifier: &Modifier) -> Option<&'static str> {
+    MODIFIER_PREFIXES.iter().find(|(_, m)| m == modifier).map(|(name, _)| *name)
+}
+
+/// Convert a function to a keycode
+fn to_keycode(function: &FunctionType) -> Option<String> {
+    let keycode = match function {
+        FunctionType::None => "KC_TRNS".to_string(),
+        FunctionType::Block => "KC_NO".to_string(),
+        FunctionType::Key(key) if key.is_ascii_lowercase() || key.is_ascii_digit() => format!("KC_{}", key.to_ascii_uppercase()),
+        FunctionType::Key(key) if key.is_ascii_uppercase() => format!("LSFT(KC_{})", key),
+        FunctionType::Key(key) => CHARS.iter().find(|(_, c)| c == key)?.0.to_string(),
+        FunctionType::Special(special) => SPECIAL_KEYS.iter().find(|(_, s)| s == special)?.0.to_string(),
+        FunctionType::Media(media) => MEDIA_KEYS.iter().find(|(_, m)| m == media)?.0.to_string(),
+        FunctionType::Modifier(modifier) => MODIFIERS.iter().find(|(_, m)| m == modifier)?.0.to_string(),
+        FunctionType::Shift(layer) => format!("MO({})", layer_arg(layer)?),
+        FunctionType::ToggleLayer(layer) => format!("TG({})", layer_arg(layer)?),
+        FunctionType::Switch(layer) => format!("TO({})", layer_arg(layer)?),
+        FunctionType::DefaultLayer(layer) => format!("DF({})", layer_arg(layer)?),
+        FunctionType::OneShotLayer(layer) => format!("OSL({})", layer_arg(layer)?),
+        FunctionType::OneShotModifier(modifier) => format!("OSM(MOD_{})", modifier_prefix(modifier)?),
+        FunctionType::TapHold { tap, hold, .. } => match hold.as_ref() {
+            FunctionType::Shift(layer) => format!("LT({}, {})", layer_arg(layer)?, to_keycode(tap)?),
+            FunctionType::Modifier(modifier) => format!("{}_T({})", modifier_prefix(modifier)?, to_keycode(tap)?),
+            _ => return None,
+        },
+        FunctionType::Shortcut { modifiers, keys } if keys.len() == 1 => modifiers.iter()
+            .rev()
+            .try_fold(basic_keycode(&keys[0])?, |keycode, modifier| {
+                Some(format!("{}({})", modifier_prefix(modifier)?, keycode))
+            })?,
+        _ => return None,
+    };
+
+    Some(keycode)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::harness::row_layout;
+
+    use super::*;
+
+    /// Layout with a single row of transparent keys
+    fn blank_layout(width: usize) -> LayoutBuilder {
+        serde_json::from_str(&row_layout(json!([[vec![serde_json::Value::Null; width]]]))).unwrap()
+    }
+
+    /// Import keymap layers into a layout the width of the first layer
+    fn import_layers(layers: &[Vec<&str>]) -> LayoutBuilder {
+        let keymap = json!({"layers": layers}).to_string();
+        import(&keymap, blank_layout(layers[0].len())).unwrap()
+    }
+
+    /// Layers of an exported keymap
+    fn export_layers(builder: &LayoutBuilder) -> Vec<Vec<String>> {
+        serde_json::from_str::<Keymap>(&export(builder, "test").unwrap()).unwrap().layers
+    }
+
+    #[test]
+    fn round_trips_keycodes() {
+        let layers = vec![
+            vec!["KC_A", "KC_1", "KC_SCLN", "KC_ENT", "KC_MPLY", "KC_LCTL", "LCTL(KC_A)", "LCTL(LSFT(KC_DEL))", "LT(1, KC_SPC)", "LSFT_T(KC_ESC)", "KC_TRNS", "KC_NO"],
+            vec!["MO(1)", "TG(2)", "TO(0)", "DF(1)", "OSL(1)", "OSM(MOD_LSFT)", "KC_TRNS", "KC_TRNS", "KC_TRNS", "KC_TRNS", "KC_TRNS", "KC_TRNS"],
+        ];
+        let builder = import_layers(&layers);
+
+        let imported = &builder.layers()[0];
+        assert!(matches!(&imported[6], Some(FunctionType::Shortcut { modifiers, .. }) if modifiers == &vec![Modifier::LeftControl]));
+        assert!(matches!(&imported[7], Some(FunctionType::Shortcut { modifiers, .. }) if modifiers == &vec![Modifier::LeftControl, Modifier::LeftShift]));
+        assert!(matches!(&imported[8], Some(FunctionType::TapHold { hold, .. }) if matches!(hold.as_ref(), FunctionType::Shift(Data::Const(1)))));
+        assert!(matches!(&imported[9], Some(FunctionType::TapHold { hold, .. }) if matches!(hold.as_ref(), FunctionType::Modifier(Modifier::LeftShift))));
+        assert!(imported[10].is_none());
+        assert!(matches!(&imported[11], Some(FunctionType::Block)));
+
+        assert_eq!(export_layers(&builder), layers);
+    }
+
+    #[test]
+    fn imports_aliases_as_canonical_keycodes() {
+        let builder = import_layers(&[vec!["KC_ENTER", "_______", "XXXXXXX", "KC_LEFT_CTRL", "LSFT(KC_A)", "KC_UNKNOWN", "KC_B"]]);
+        assert_eq!(builder.layers()[0].len(), 7);
+
+        assert_eq!(export_layers(&builder), vec![vec!["KC_ENT", "KC_TRNS", "KC_NO", "KC_LCTL", "LSFT(KC_A)", "KC_TRNS", "KC_B"]]);
+    }
+
+    #[test]
+    fn pads_short_layers_and_rejects_long_layers() {
+        let keymap = json!({"layers": [["KC_A"]]}).to_string();
+        let builder = import(&keymap, blank_layout(3)).unwrap();
+        assert_eq!(export_layers(&builder), vec![vec!["KC_A", "KC_TRNS", "KC_TRNS"]]);
+
+        let keymap = json!({"layers": [["KC_A"], ["KC_A", "KC_B", "KC_C", "KC_D"]]}).to_string();
+        assert!(matches!(import(&keymap, blank_layout(3)), Err(QmkError::TooManyKeys(1))));
+    }
+}
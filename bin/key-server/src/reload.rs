@@ -0,0 +1,145 @@
+use std::{sync::{Arc, Mutex}, path::{PathBuf, Path}, time::Duration, collections::HashMap};
+
+use key_rpc::Event;
+use log::info;
+use tokio::{sync::RwLock, task::JoinHandle};
+
+use crate::{layout::{Layout, LayoutBuilder}, driver::{DriverManager, led::Leds}, function::FunctionBuilder, variables::{Variables, VarDef}, modules::ModuleManager, events::Events, OrLog};
+
+/// Time between checks for changed config files. The files are polled rather than watched with inotify, a
+/// change is picked up within a period and polling works on any filesystem the config folder is on
+const WATCH_PERIOD: Duration = Duration::from_secs(1);
+
+/// Config files written by the server itself, the watcher does not reload these
+#[derive(Debug, Default, Clone)]
+pub struct SavedConfigs(Arc<Mutex<HashMap<PathBuf, String>>>);
+
+impl SavedConfigs {
+    /// Record the contents about to be written to a config file
+    pub fn record(&self, path: &Path, contents: &str) {
+        if let Ok(mut saved) = self.0.lock() {
+            saved.insert(path.to_path_buf(), contents.to_string());
+        }
+    }
+
+    /// Whether the contents of a config file were written by the server
+    fn matches(&self, path: &Path, contents: &str) -> bool {
+        self.0.lock().is_ok_and(|saved| saved.get(path).is_some_and(|saved| saved == contents))
+    }
+}
+
+/// Config files to watch and the state they are reloaded into
+pub struct WatchedConfig {
+    pub layout_path: PathBuf,
+    pub variables_path: PathBuf,
+    pub drivers_path: PathBuf,
+    pub layout: Arc<RwLock<Layout>>,
+    pub variables: Arc<RwLock<Variables>>,
+    pub driver_manager: Arc<RwLock<DriverManager>>,
+    pub leds: Leds,
+    pub function_builder: Arc<RwLock<FunctionBuilder>>,
+    pub module_manager: Arc<ModuleManager>,
+    pub events: Events,
+    /// Config files written by the server, not reloaded
+    pub saved: SavedConfigs,
+}
+
+/// Config watcher, reloads the layout, variables and drivers when their config files change
+pub struct ConfigWatcher {
+    layout_path: PathBuf,
+    variables_path: PathBuf,
+    drivers_path: PathBuf,
+    layout_json: Option<String>,
+    variables_json: Option<String>,
+    drivers_data: Option<Vec<(PathBuf, String)>>,
+}
+
+impl ConfigWatcher {
+    /// Start watching, changes are applied to the given layout, variables and driver manager
+    pub fn start(config: WatchedConfig) -> JoinHandle<()> {
+        let WatchedConfig {
+            layout_path, variables_path, drivers_path, layout, variables, driver_manager, leds, function_builder, module_manager, events, saved,
+        } = config;
+
+        tokio::spawn(async move {
+            let mut watcher = ConfigWatcher {
+                layout_json: read_file(&layout_path).await,
+                variables_json: read_file(&variables_path).await,
+                drivers_data: read_dir(&drivers_path).await,
+                layout_path,
+                variables_path,
+                drivers_path,
+            };
+
+            loop {
+                tokio::time::sleep(WATCH_PERIOD).await;
+
+                if let Some(drivers_data) = watcher.changed_drivers().await {
+                    if let Some(new_drivers) = DriverManager::load(&watcher.drivers_path, module_manager.clone(), &leds).await
+                        .or_log("Unable to load drivers, keeping previous drivers (Config Watcher)")
+                    {
+                        driver_manager.write().await.replace_drivers(new_drivers);
+                        info!("Reloaded drivers");
+                    }
+                    watcher.drivers_data = Some(drivers_data);
+                }
+
+                if let Some(variables_json) = watcher.changed(&watcher.variables_path, &watcher.variables_json).await {
+                    if saved.matches(&watcher.variables_path, &variables_json) {
+                        // saved over rpc, the variables already match the file
+                    } else if let Some(definitions) = serde_json::from_str::<Vec<VarDef>>(&variables_json)
+                        .or_log("Unable to parse variables, keeping previous variables (Config Watcher)")
+                    {
+                        variables.write().await.reload(definitions);
+                        info!("Reloaded variables");
+                    }
+                    watcher.variables_json = Some(variables_json);
+                }
+
+                if let Some(layout_json) = watcher.changed(&watcher.layout_path, &watcher.layout_json).await {
+                    if saved.matches(&watcher.layout_path, &layout_json) {
+                        // saved over rpc, the layout already matches the file
+                    } else if let Some(builder) = serde_json::from_str::<LayoutBuilder>(&layout_json)
+                        .or_log("Unable to parse layout, keeping previous layout (Config Watcher)")
+                    {
+                        let new_layout = builder.build_layout(driver_manager.clone(), function_builder.clone(), events.clone()).await;
+                        let mut layout = layout.write().await;
+                        layout.release_all().await;
+                        *layout = new_layout;
+                        events.send(Event::Layer(layout.curr_layer()));
+                        info!("Reloaded layout");
+                    }
+                    watcher.layout_json = Some(layout_json);
+                }
+            }
+        })
+    }
+
+    /// Contents of a config file, if it changed since it was last loaded
+    async fn changed(&self, path: &Path, loaded: &Option<String>) -> Option<String> {
+        read_file(path).await.filter(|contents| Some(contents) != loaded.as_ref())
+    }
+
+    /// Contents of the driver folder, if it changed since it was last loaded
+    async fn changed_drivers(&self) -> Option<Vec<(PathBuf, String)>> {
+        read_dir(&self.drivers_path).await.filter(|contents| Some(contents) != self.drivers_data.as_ref())
+    }
+}
+
+/// Read a config file
+async fn read_file(path: &Path) -> Option<String> {
+    tokio::fs::read_to_string(path).await.ok()
+}
+
+/// Read every file in a config folder, sorted by path
+async fn read_dir(path: &Path) -> Option<Vec<(PathBuf, String)>> {
+    let mut entries = tokio::fs::read_dir(path).await.ok()?;
+    let mut contents = vec![];
+    while let Some(entry) = entries.next_entry().await.ok()? {
+        if let Some(data) = read_file(&entry.path()).await {
+            contents.push((entry.path(), data));
+        }
+    }
+    contents.sort();
+    Some(contents)
+}
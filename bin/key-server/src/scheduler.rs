@@ -0,0 +1,111 @@
+use std::{collections::HashMap, time::{Duration, Instant}, convert::Infallible};
+
+use async_trait::async_trait;
+use key_rpc::Event;
+use log::{info, warn};
+use tokio::time::{self, Interval, MissedTickBehavior};
+
+use crate::{frontend::{FrontendConfig, FrontendConfigData, FrontendConfiguration}, events::Events};
+
+/// Scans per second when not configured
+const DEFAULT_SCAN_RATE: u32 = 1000;
+/// Seconds between scan rate reports when not configured
+const DEFAULT_METRICS_PERIOD: u64 = 60;
+/// Highest scan rate, faster rates are clamped so the scan period stays above zero
+pub const MAX_SCAN_RATE: u32 = 1_000_000;
+
+/// Default scan rate, used when the scheduler config does not set one
+pub fn default_scan_rate() -> u32 {
+    DEFAULT_SCAN_RATE
+}
+
+/// Default metrics period, used when the scheduler config does not set one
+pub fn default_metrics_period() -> u64 {
+    DEFAULT_METRICS_PERIOD
+}
+
+/// Event loop scheduler, paces layout scans and publishes the scan rate achieved
+pub struct Scheduler {
+    scan_rate: u32,
+    tick_intervals: HashMap<String, u64>,
+    metrics_period: u64,
+    interval: Option<Interval>,
+    scans: u32,
+    measured_from: Instant,
+    events: Events,
+}
+
+#[async_trait]
+impl FrontendConfig for Scheduler {
+    type Output = Scheduler;
+
+    type Error = Infallible;
+
+    fn to_config_data(&self) -> FrontendConfigData {
+        FrontendConfigData::Scheduler{
+            scan_rate: self.scan_rate,
+            tick_intervals: self.tick_intervals.clone(),
+            metrics_period: self.metrics_period,
+        }
+    }
+
+    async fn from_config(function_config: &FrontendConfiguration) -> Result<Self::Output, Self::Error> {
+        let Some(FrontendConfigData::Scheduler { scan_rate, tick_intervals, metrics_period }) = function_config
+            .get(|config| matches!(config, FrontendConfigData::Scheduler { .. })) else {
+                return Ok(Scheduler::new(DEFAULT_SCAN_RATE, HashMap::new(), DEFAULT_METRICS_PERIOD, function_config.events.clone()))
+        };
+        Ok(Scheduler::new(*scan_rate, tick_intervals.clone(), *metrics_period, function_config.events.clone()))
+    }
+}
+
+impl Scheduler {
+    /// New, a scan rate of 0 scans as fast as possible and a metrics period of 0 disables scan rate reports
+    pub fn new(scan_rate: u32, tick_intervals: HashMap<String, u64>, metrics_period: u64, events: Events) -> Scheduler {
+        let scan_rate = if scan_rate > MAX_SCAN_RATE {
+            warn!("Scan rate {}Hz is above the maximum, using {}Hz (Scheduler)", scan_rate, MAX_SCAN_RATE);
+            MAX_SCAN_RATE
+        } else {
+            scan_rate
+        };
+
+        let interval = (scan_rate > 0).then(|| {
+            let mut interval = time::interval(Duration::from_secs(1) / scan_rate);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            interval
+        });
+
+        Scheduler {
+            scan_rate,
+            tick_intervals,
+            metrics_period,
+            interval,
+            scans: 0,
+            measured_from: Instant::now(),
+            events,
+        }
+    }
+
+    /// Tick interval of each driver configured with one
+    pub fn tick_intervals(&self) -> impl Iterator<Item = (&str, Duration)> {
+        self.tick_intervals.iter().map(|(name, interval)| (name.as_str(), Duration::from_millis(*interval)))
+    }
+
+    /// Wait until the next scan is due
+    pub async fn wait(&mut self) {
+        match &mut self.interval {
+            Some(interval) => { interval.tick().await; },
+            None => tokio::task::yield_now().await,
+        }
+
+        self.scans += 1;
+
+        let elapsed = self.measured_from.elapsed();
+        if self.metrics_period > 0 && elapsed >= Duration::from_secs(self.metrics_period) {
+            let achieved = self.scans as f64 / elapsed.as_secs_f64();
+            info!("Achieved scan rate, {:.1}Hz of {}Hz", achieved, self.scan_rate);
+            self.events.send(Event::ScanRate { achieved, target: self.scan_rate });
+            self.scans = 0;
+            self.measured_from = Instant::now();
+        }
+    }
+}
@@ -4,16 +4,19 @@ use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use tokio::{sync::{RwLock, watch}};
 
-use crate::OrLog;
+use key_rpc::Event;
+
+use crate::{OrLog, events::Events};
 
 pub struct Variables {
     pub data: HashMap<String, Vec<(watch::Sender<String>, watch::Receiver<String>)>>,
     savable: Vec<VarDef>,
+    events: Events,
 }
 
 impl Variables {
-    pub fn new() -> Arc<RwLock<Variables>> {
-        Arc::new(RwLock::new(Variables{ data: HashMap::new(), savable: Vec::new() }))
+    pub fn new(events: Events) -> Arc<RwLock<Variables>> {
+        Arc::new(RwLock::new(Variables{ data: HashMap::new(), savable: Vec::new(), events }))
     }
 
     pub fn to_json(&self) -> Result<String, serde_json::Error> {
@@ -40,11 +43,34 @@ impl Variables {
         }
     }
 
+    /// Replace the global variable definitions, updating the value of variables that already exist and removing
+    /// variables that are no longer defined. Functions already reading a removed variable keep its last value
+    pub fn reload(&mut self, variables: Vec<VarDef>) {
+        let removed = self.savable.iter()
+            .filter(|old| !variables.iter().any(|new| new.name == old.name))
+            .map(|old| old.name.clone())
+            .collect_vec();
+        for name in removed {
+            self.data.remove(&name);
+        }
+
+        self.savable = variables.clone();
+
+        for definition in variables {
+            if self.data.contains_key(&definition.name) {
+                self.update(&definition.name, definition.default);
+            } else {
+                self.data.insert(definition.name, vec![watch::channel(definition.default)]);
+            }
+        }
+    }
+
     pub fn update(&self, name: &str, value: String) -> Option<()> {
         if let Some(watching) = self.data.get(name) {
             for (send, _) in watching {
                 send.send_replace(value.to_string());
             }
+            self.events.send(Event::Variable(name.to_string(), value));
             Some(())
         } else {
             None
@@ -205,3 +231,44 @@ pub struct VarDef {
     name: String, 
     default: String
 }
+
+impl VarDef {
+    /// Variable name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Default value, encoded as json
+    pub fn default(&self) -> &str {
+        &self.default
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definitions(json: serde_json::Value) -> Vec<VarDef> {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[tokio::test]
+    async fn reload_updates_and_removes_variables() {
+        let variables = Variables::new(Events::new());
+        let mut variables = variables.write().await;
+        variables.create_many(definitions(serde_json::json!([
+            {"name": "speed", "default": "1"},
+            {"name": "removed", "default": "2"},
+        ])));
+
+        variables.reload(definitions(serde_json::json!([
+            {"name": "speed", "default": "3"},
+            {"name": "added", "default": "4"},
+        ])));
+
+        assert_eq!(variables.get("speed"), Some("3".to_string()));
+        assert_eq!(variables.get("added"), Some("4".to_string()));
+        assert_eq!(variables.get("removed"), None);
+        assert_eq!(variables.variables().sorted().collect_vec(), vec!["added", "speed"]);
+    }
+}
@@ -0,0 +1,134 @@
+use std::{sync::{Arc, Mutex}, time::Duration};
+
+use crate::{Client, ClientError, DEFAULT_TIMEOUT};
+
+#[derive(Clone)]
+/// Async client, a blocking adapter around `Client` for use with tokio. Nanomsg sockets only block, so each
+/// call runs the blocking client on the tokio blocking thread pool instead of the calling task, occupying a
+/// blocking thread for up to the timeout. Calls on one client and its clones are made one at a time, use a
+/// client per server to call several servers at once
+pub struct AsyncClient {
+    client: Arc<Mutex<Client>>,
+}
+
+impl AsyncClient {
+    pub async fn new(socket_str: &str) -> Result<AsyncClient, ClientError> {
+        AsyncClient::with_timeout(socket_str, DEFAULT_TIMEOUT).await
+    }
+
+    /// New, with a send and receive timeout
+    pub async fn with_timeout(socket_str: &str, timeout: Duration) -> Result<AsyncClient, ClientError> {
+        let socket_str = socket_str.to_string();
+        let client = tokio::task::spawn_blocking(move || Client::with_timeout(&socket_str, timeout)).await
+            .map_err(|e| ClientError::Task(e.to_string()))??;
+
+        Ok(AsyncClient { client: Arc::new(Mutex::new(client)) })
+    }
+
+    /// Run a blocking call
+    async fn run<T, F>(&self, call: F) -> Result<T, ClientError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Client) -> Result<T, ClientError> + Send + 'static,
+    {
+        let client = self.client.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut client = client.lock().map_err(|e| ClientError::Task(e.to_string()))?;
+            call(&mut client)
+        }).await
+            .map_err(|e| ClientError::Task(e.to_string()))?
+    }
+
+    /// Protocol version of the server
+    pub async fn version(&self) -> Result<u32, ClientError> {
+        self.run(move |client| client.version()).await
+    }
+
+    pub async fn layer(&self) -> Result<String, ClientError> {
+        self.run(move |client| client.layer()).await
+    }
+
+    pub async fn get_layer(&self, index: usize) -> Result<String, ClientError> {
+        self.run(move |client| client.get_layer(index)).await
+    }
+
+    pub async fn layer_idx(&self) -> Result<usize, ClientError> {
+        self.run(move |client| client.layer_idx()).await
+    }
+
+    pub async fn num_layers(&self) -> Result<usize, ClientError> {
+        self.run(move |client| client.num_layers()).await
+    }
+
+    pub async fn add_layer(&self, layer: String) -> Result<(), ClientError> {
+        self.run(move |client| client.add_layer(layer)).await
+    }
+
+    pub async fn insert_layer(&self, idx: usize, layer: String) -> Result<(), ClientError> {
+        self.run(move |client| client.insert_layer(idx, layer)).await
+    }
+
+    pub async fn set_layer(&self, idx: usize, layer: String) -> Result<(), ClientError> {
+        self.run(move |client| client.set_layer(idx, layer)).await
+    }
+
+    pub async fn move_layer(&self, from: usize, to: usize) -> Result<(), ClientError> {
+        self.run(move |client| client.move_layer(from, to)).await
+    }
+
+    pub async fn remove_layer(&self, idx: usize) -> Result<(), ClientError> {
+        self.run(move |client| client.remove_layer(idx)).await
+    }
+
+    pub async fn set_binding(&self, layer: usize, x: usize, y: usize, function: String) -> Result<(), ClientError> {
+        self.run(move |client| client.set_binding(layer, x, y, function)).await
+    }
+
+    pub async fn clear_binding(&self, layer: usize, x: usize, y: usize) -> Result<(), ClientError> {
+        self.run(move |client| client.clear_binding(layer, x, y)).await
+    }
+
+    pub async fn addresses(&self) -> Result<String, ClientError> {
+        self.run(move |client| client.addresses()).await
+    }
+
+    pub async fn add_address(&self, address: String) -> Result<(), ClientError> {
+        self.run(move |client| client.add_address(address)).await
+    }
+
+    pub async fn remove_address(&self, x: usize, y: usize) -> Result<(), ClientError> {
+        self.run(move |client| client.remove_address(x, y)).await
+    }
+
+    pub async fn switch_layer(&self, idx: usize) -> Result<(), ClientError> {
+        self.run(move |client| client.switch_layer(idx)).await
+    }
+
+    pub async fn down_layer(&self) -> Result<(), ClientError> {
+        self.run(move |client| client.down_layer()).await
+    }
+
+    pub async fn up_layer(&self) -> Result<(), ClientError> {
+        self.run(move |client| client.up_layer()).await
+    }
+
+    pub async fn save_layer(&self) -> Result<(), ClientError> {
+        self.run(move |client| client.save_layer()).await
+    }
+
+    pub async fn variables(&self) -> Result<Vec<String>, ClientError> {
+        self.run(move |client| client.variables()).await
+    }
+
+    pub async fn get_variable(&self, name: String) -> Result<String, ClientError> {
+        self.run(move |client| client.get_variable(name)).await
+    }
+
+    pub async fn set_variable(&self, name: String, data: String) -> Result<(), ClientError> {
+        self.run(move |client| client.set_variable(name, data)).await
+    }
+
+    pub async fn save_variables(&self) -> Result<(), ClientError> {
+        self.run(move |client| client.save_variables()).await
+    }
+}
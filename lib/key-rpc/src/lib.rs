@@ -1,38 +1,78 @@
 #![doc = include_str!("../README.md")]
 
-use std::{io::{Write, Read}, fmt::{Debug, Display}};
+use std::{io::{self, Write, Read}, fmt::{Debug, Display}, time::Duration};
 
 use nanomsg::{Socket, Endpoint, Protocol};
 use serde::{Serialize, Deserialize};
 
+mod async_client;
+pub use async_client::AsyncClient;
+
+/// Protocol version, sent with every request, servers refuse requests from clients with a different version
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Send and receive timeout used by Client::new
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+
 #[derive(Debug)]
 pub enum ClientError {
+    Timeout,
     Return(String),
+    Server(RpcError),
     Serde(serde_json::Error),
     IO(std::io::Error),
     NNG(nanomsg::Error),
+    Task(String),
+}
+
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => ClientError::Timeout,
+            _ => ClientError::IO(e),
+        }
+    }
 }
 
 impl Display for ClientError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            ClientError::Timeout => f.write_str("Timed out waiting for the server"),
             ClientError::Return(ret) => f.write_fmt(format_args!("Unexpected return, {}", ret)),
+            ClientError::Server(e) => f.write_fmt(format_args!("Server error, {}", e)),
             ClientError::Serde(e) => f.write_fmt(format_args!("Unable to serialize/deserialize, {}", e)),
             ClientError::IO(e) => f.write_fmt(format_args!("IO error, {}", e)),
             ClientError::NNG(e) => f.write_fmt(format_args!("Nanomsg error, {}", e)),
+            ClientError::Task(e) => f.write_fmt(format_args!("Task error, {}", e)),
         }
     }
 }
 
 
+#[derive(Debug, Serialize, Deserialize)]
+/// Request sent to the server, a command and the protocol version of the client that sent it
+pub struct Request {
+    pub version: u32,
+    pub command: Command,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Command {
+    Version,
     Layer,
     GetLayer(usize),
     LayerIdx,
     NumLayers,
     AddLayer(String),
+    InsertLayer(usize, String),
+    SetLayer(usize, String),
+    MoveLayer(usize, usize),
     RemoveLayer(usize),
+    SetBinding(usize, usize, usize, String),
+    ClearBinding(usize, usize, usize),
+    Addresses,
+    AddAddress(String),
+    RemoveAddress(usize, usize),
     SwitchLayer(usize),
     UpLayer,
     DownLayer,
@@ -43,9 +83,101 @@ pub enum Command {
     SaveVariables,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// Reason a command failed
+pub enum ErrorCode {
+    /// Command could not be parsed
+    InvalidCommand,
+    /// Client and server protocol versions differ
+    VersionMismatch,
+    /// Command argument could not be parsed
+    InvalidArgument,
+    /// Layer, binding, address or variable does not exist
+    NotFound,
+    /// Change would leave the layout invalid
+    InvalidLayout,
+    /// Unable to read or write a config file
+    IO,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Error returned by the server
+pub struct RpcError {
+    pub code: ErrorCode,
+    pub msg: String,
+}
+
+impl RpcError {
+    pub fn new(code: ErrorCode, msg: impl Display) -> RpcError {
+        RpcError { code, msg: msg.to_string() }
+    }
+}
+
+impl Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("{:?}, {}", self.code, self.msg))
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
-pub struct FallibleRet {
-    pub ret: Option<String>
+/// Server response to a command
+pub enum Response {
+    Ok,
+    Version(u32),
+    String(String),
+    Usize(usize),
+    Strings(Vec<String>),
+    Error(RpcError),
+}
+
+impl From<RpcError> for Response {
+    fn from(e: RpcError) -> Self {
+        Response::Error(e)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Server event, published to subscribers
+pub enum Event {
+    /// Highest active layer changed
+    Layer(usize),
+    /// Variable updated, name and value
+    Variable(String, String),
+    /// HID switched to a new mode
+    HidMode(String),
+    /// Key at a grid coordinate pressed or released
+    Key {
+        x: usize,
+        y: usize,
+        pressed: bool,
+    },
+    /// Module returned an error
+    ModuleError(String),
+    /// Scan rate achieved over the last metrics period and the configured scan rate, in scans per second
+    ScanRate {
+        achieved: f64,
+        target: u32,
+    },
+}
+
+/// Event subscriber, iterates over server events as they are published
+pub struct Subscriber {
+    socket: Socket,
+    _endpoint: Endpoint,
+    buffer: String,
+}
+
+impl Iterator for Subscriber {
+    type Item = Result<Event, ClientError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.buffer.clear();
+        if let Err(e) = self.socket.read_to_string(&mut self.buffer) {
+            return Some(Err(e.into()));
+        }
+
+        Some(serde_json::from_str(&self.buffer).map_err(|e| ClientError::Serde(e)))
+    }
 }
 
 
@@ -56,80 +188,128 @@ pub struct Client {
 
 impl Client {
     pub fn new(socket_str: &str) -> Result<Client, ClientError>{
+        Client::with_timeout(socket_str, DEFAULT_TIMEOUT)
+    }
+
+    /// New, with a send and receive timeout
+    pub fn with_timeout(socket_str: &str, timeout: Duration) -> Result<Client, ClientError>{
         let mut socket = Socket::new(Protocol::Req).map_err(|e| ClientError::NNG(e))?;
         let _endpoint = socket.connect(socket_str).map_err(|e| ClientError::NNG(e))?;
 
-        socket.set_receive_timeout(100).map_err(|e| ClientError::NNG(e))?;
-        socket.set_send_timeout(100).map_err(|e| ClientError::NNG(e))?;
+        let timeout = timeout.as_millis() as isize;
+        socket.set_receive_timeout(timeout).map_err(|e| ClientError::NNG(e))?;
+        socket.set_send_timeout(timeout).map_err(|e| ClientError::NNG(e))?;
 
         Ok(Client { socket, _endpoint })
     }
 
-    fn call_infallible(&mut self, command: Command) -> Result<String, ClientError> {
-        let data = serde_json::to_string(&command).map_err(|e| ClientError::Serde(e))?;
-        self.socket.write_all(&data.as_bytes()).map_err(|e| ClientError::IO(e))?;
-
-        let mut buffer = String::new();
-        self.socket.read_to_string(&mut buffer).map_err(|e| ClientError::IO(e))?;
+    /// Subscribe to the events published by a server
+    pub fn subscribe(socket_str: &str) -> Result<Subscriber, ClientError> {
+        let mut socket = Socket::new(Protocol::Sub).map_err(|e| ClientError::NNG(e))?;
+        socket.subscribe(&[]).map_err(|e| ClientError::NNG(e))?;
+        let _endpoint = socket.connect(socket_str).map_err(|e| ClientError::NNG(e))?;
 
-        Ok(buffer)
+        Ok(Subscriber { socket, _endpoint, buffer: String::new() })
     }
 
-    fn call_no_ret(&mut self, command: Command) -> Result<(), ClientError> {
-        let data = serde_json::to_string(&command).map_err(|e| ClientError::Serde(e))?;
-        self.socket.write_all(&data.as_bytes()).map_err(|e| ClientError::IO(e))?;
+    fn call(&mut self, command: Command) -> Result<Response, ClientError> {
+        let data = serde_json::to_string(&Request { version: PROTOCOL_VERSION, command }).map_err(|e| ClientError::Serde(e))?;
+        self.socket.write_all(&data.as_bytes())?;
 
         let mut buffer = String::new();
-        self.socket.read_to_string(&mut buffer).map_err(|e| ClientError::IO(e))?;
+        self.socket.read_to_string(&mut buffer)?;
 
-        if buffer == "true" {
-            Ok(())
-        } else {
-            Err(ClientError::Return(buffer))
+        match serde_json::from_str(&buffer).map_err(|e| ClientError::Serde(e))? {
+            Response::Error(e) => Err(ClientError::Server(e)),
+            response => Ok(response),
         }
     }
 
-    fn call_fallible(&mut self, command: Command) -> Result<String, ClientError> {
-        let data = serde_json::to_string(&command).map_err(|e| ClientError::Serde(e))?;
-        self.socket.write_all(&data.as_bytes()).map_err(|e| ClientError::IO(e))?;
+    fn call_no_ret(&mut self, command: Command) -> Result<(), ClientError> {
+        match self.call(command)? {
+            Response::Ok => Ok(()),
+            response => Err(ClientError::Return(format!("{:?}", response))),
+        }
+    }
 
-        let mut buffer = String::new();
-        self.socket.read_to_string(&mut buffer).map_err(|e| ClientError::IO(e))?;
+    fn call_string(&mut self, command: Command) -> Result<String, ClientError> {
+        match self.call(command)? {
+            Response::String(ret) => Ok(ret),
+            response => Err(ClientError::Return(format!("{:?}", response))),
+        }
+    }
 
-        let ret: Option<FallibleRet> = serde_json::from_str(&buffer).ok();
+    fn call_usize(&mut self, command: Command) -> Result<usize, ClientError> {
+        match self.call(command)? {
+            Response::Usize(ret) => Ok(ret),
+            response => Err(ClientError::Return(format!("{:?}", response))),
+        }
+    }
 
-        if let Some(ret) = ret {
-            ret.ret.ok_or(ClientError::Return(buffer))
-        } else {
-            Err(ClientError::Return(buffer))
+    /// Protocol version of the server
+    pub fn version(&mut self) -> Result<u32, ClientError> {
+        match self.call(Command::Version)? {
+            Response::Version(version) => Ok(version),
+            response => Err(ClientError::Return(format!("{:?}", response))),
         }
     }
 
     pub fn layer(&mut self) -> Result<String, ClientError>{
-        self.call_infallible(Command::Layer)
+        self.call_string(Command::Layer)
     }
 
     pub fn get_layer(&mut self, index: usize) -> Result<String, ClientError> {
-        self.call_fallible(Command::GetLayer(index))
+        self.call_string(Command::GetLayer(index))
     }
 
-    pub fn layer_idx(&mut self) -> Result<String, ClientError>{
-        self.call_infallible(Command::LayerIdx)
+    pub fn layer_idx(&mut self) -> Result<usize, ClientError>{
+        self.call_usize(Command::LayerIdx)
     }
 
     pub fn num_layers(&mut self) -> Result<usize, ClientError>{
-        self.call_infallible(Command::NumLayers)
-            .and_then(|ret| usize::from_str_radix(&ret, 10).map_err(|e| ClientError::Return(e.to_string())))
+        self.call_usize(Command::NumLayers)
     }
 
     pub fn add_layer(&mut self, layer: String) -> Result<(), ClientError>{
         self.call_no_ret(Command::AddLayer(layer))
     }
 
+    pub fn insert_layer(&mut self, idx: usize, layer: String) -> Result<(), ClientError>{
+        self.call_no_ret(Command::InsertLayer(idx, layer))
+    }
+
+    pub fn set_layer(&mut self, idx: usize, layer: String) -> Result<(), ClientError>{
+        self.call_no_ret(Command::SetLayer(idx, layer))
+    }
+
+    pub fn move_layer(&mut self, from: usize, to: usize) -> Result<(), ClientError>{
+        self.call_no_ret(Command::MoveLayer(from, to))
+    }
+
     pub fn remove_layer(&mut self, idx: usize) -> Result<(), ClientError>{
         self.call_no_ret(Command::RemoveLayer(idx))
     }
 
+    pub fn set_binding(&mut self, layer: usize, x: usize, y: usize, function: String) -> Result<(), ClientError>{
+        self.call_no_ret(Command::SetBinding(layer, x, y, function))
+    }
+
+    pub fn clear_binding(&mut self, layer: usize, x: usize, y: usize) -> Result<(), ClientError>{
+        self.call_no_ret(Command::ClearBinding(layer, x, y))
+    }
+
+    pub fn addresses(&mut self) -> Result<String, ClientError>{
+        self.call_string(Command::Addresses)
+    }
+
+    pub fn add_address(&mut self, address: String) -> Result<(), ClientError>{
+        self.call_no_ret(Command::AddAddress(address))
+    }
+
+    pub fn remove_address(&mut self, x: usize, y: usize) -> Result<(), ClientError>{
+        self.call_no_ret(Command::RemoveAddress(x, y))
+    }
+
     pub fn switch_layer(&mut self, idx: usize) -> Result<(), ClientError>{
         self.call_no_ret(Command::SwitchLayer(idx))
     }
@@ -147,11 +327,14 @@ impl Client {
     }
 
     pub fn variables(&mut self) -> Result<Vec<String>, ClientError> {
-        self.call_infallible(Command::Variables).and_then(|str| serde_json::from_str(&str).map_err(|e| ClientError::Serde(e)))
+        match self.call(Command::Variables)? {
+            Response::Strings(ret) => Ok(ret),
+            response => Err(ClientError::Return(format!("{:?}", response))),
+        }
     }
 
     pub fn get_variable(&mut self, name: String) -> Result<String, ClientError> {
-        self.call_fallible(Command::GetVariable(name))
+        self.call_string(Command::GetVariable(name))
     }
 
     pub fn set_variable(&mut self, name: String, data: String) -> Result<(), ClientError> {
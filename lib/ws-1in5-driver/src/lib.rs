@@ -70,11 +70,26 @@ fn parse_index(chr: char) -> Option<usize> {
     }
 }
 
+/// Key-server client, kept between calls rather than connecting for each one
+static CLIENT: std::sync::Mutex<Option<Client>> = std::sync::Mutex::new(None);
+
+/// Call the key-server, reusing the last client. The client is dropped when a call fails for any reason
+/// other than a server error, so the next call reconnects
 fn rpc<F, U>(f: F) -> Result<U, ClientError>
 where
-    F: FnOnce(Client) -> Result<U, ClientError>,
+    F: FnOnce(&mut Client) -> Result<U, ClientError>,
 {
-    Client::new("ipc:///lmk/ksf.ipc").and_then(f)
+    let mut cached = CLIENT.lock().unwrap_or_else(|e| e.into_inner());
+    let client = match cached.as_mut() {
+        Some(client) => client,
+        None => cached.insert(Client::new("ipc:///lmk/ksf.ipc")?),
+    };
+
+    let res = f(client);
+    if matches!(res, Err(ref e) if !matches!(e, ClientError::Server(_))) {
+        *cached = None;
+    }
+    res
 }
 
 fn get_key(coord: (usize, usize), layer: String) -> Result<String, String> {
@@ -697,7 +712,7 @@ impl State for HomeState {
                 SpecialKey::ReturnEnter => if self.focus_x {
                     self.focus_x = false
                 } else {
-                    self.key = rpc(|mut client| client.get_layer(self.layer))
+                    self.key = rpc(|client| client.get_layer(self.layer))
                         .map_err(|e| e.to_string())
                         .and_then(|layer| get_key(self.coord, layer))
                         .or_log("RPC Failed");
@@ -791,7 +806,7 @@ impl State for VariablesState {
         _rt: &Handle,
     ) {
         self.selected = None;
-        self.variables = rpc(|mut client| client.variables()).or_log("RPC failed").unwrap_or_else(|| vec![]);
+        self.variables = rpc(|client| client.variables()).or_log("RPC failed").unwrap_or_else(|| vec![]);
         self.page = 0;
     }
 
@@ -919,7 +934,7 @@ impl State for VariablesState {
                     return;
                 };
 
-                let Some(variable) = rpc(|mut client| client.get_variable(variable_name.to_string())).or_log("RPC failed") else {
+                let Some(variable) = rpc(|client| client.get_variable(variable_name.to_string())).or_log("RPC failed") else {
                     return;
                 };
 
@@ -957,7 +972,7 @@ impl State for VariablesState {
                 }
                 SpecialKey::ReturnEnter => {
                     if let Some((name, variable)) = self.selected.take() {
-                        rpc(|mut client| client.set_variable(name, variable)).or_log("RPC failed");
+                        rpc(|client| client.set_variable(name, variable)).or_log("RPC failed");
                     }
 
                     self.draw(rt)
@@ -998,7 +1013,7 @@ impl State for VariablesState {
                     self.page += 1;
                     self.draw(rt)
                 } else if special == SpecialKey::ReturnEnter {
-                    rpc(|mut client| client.save_variables()).or_log("RPC failed");
+                    rpc(|client| client.save_variables()).or_log("RPC failed");
                 }
             }
         }